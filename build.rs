@@ -2,14 +2,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     const MEDIA_PROTOS: &[&str] =
         &["service-apis/proto/sited_io/media/v1/media_subscription.proto"];
 
-    const INCLUDES: &[&str] = &["service-apis/proto"];
+    // Messages published by this service.
+    const STRIPE_WEBHOOKS_PROTOS: &[&str] = &[
+        "proto/sited_io/stripe_webhooks/v1/access.proto",
+        "proto/sited_io/stripe_webhooks/v1/customer.proto",
+        "proto/sited_io/stripe_webhooks/v1/invoice.proto",
+        "proto/sited_io/stripe_webhooks/v1/payment_method.proto",
+        "proto/sited_io/stripe_webhooks/v1/subscription.proto",
+        "proto/sited_io/stripe_webhooks/v1/subscription_schedule.proto",
+    ];
+
+    const INCLUDES: &[&str] = &["service-apis/proto", "proto"];
 
     tonic_build::configure()
         .out_dir("src/api")
         .protoc_arg("--experimental_allow_proto3_optional")
         .build_server(false)
         .build_client(false)
-        .compile(MEDIA_PROTOS, INCLUDES)?;
+        .compile(&[MEDIA_PROTOS, STRIPE_WEBHOOKS_PROTOS].concat(), INCLUDES)?;
 
     Ok(())
 }
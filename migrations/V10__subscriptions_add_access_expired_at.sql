@@ -0,0 +1,4 @@
+ALTER TABLE
+  subscriptions
+ADD
+  COLUMN access_expired_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,13 @@
+CREATE TABLE grace_periods (
+  shop_id UUID NOT NULL,
+  offer_id UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000',
+  grace_period_seconds INT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  PRIMARY KEY (shop_id, offer_id)
+);
+
+ALTER TABLE
+  subscriptions
+ADD
+  COLUMN access_until TIMESTAMP WITH TIME ZONE;
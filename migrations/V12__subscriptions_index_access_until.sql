@@ -0,0 +1 @@
+CREATE INDEX ON subscriptions (access_until);
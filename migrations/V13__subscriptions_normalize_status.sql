@@ -0,0 +1,21 @@
+UPDATE
+  subscriptions
+SET
+  subscription_status = lower(trim(subscription_status))
+WHERE
+  subscription_status IS NOT NULL;
+UPDATE
+  subscriptions
+SET
+  subscription_status = NULL
+WHERE
+  subscription_status NOT IN (
+    'incomplete',
+    'incomplete_expired',
+    'trialing',
+    'active',
+    'past_due',
+    'canceled',
+    'unpaid',
+    'paused'
+  );
@@ -0,0 +1,15 @@
+ALTER TABLE
+  subscriptions
+ADD
+  CONSTRAINT check_subscription_status CHECK (
+    subscription_status IN (
+      'incomplete',
+      'incomplete_expired',
+      'trialing',
+      'active',
+      'past_due',
+      'canceled',
+      'unpaid',
+      'paused'
+    )
+  );
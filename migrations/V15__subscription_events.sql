@@ -0,0 +1,18 @@
+CREATE TABLE subscription_events (
+  subscription_event_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  subscription_id UUID NOT NULL REFERENCES subscriptions (subscription_id) ON DELETE CASCADE,
+  stripe_event_id VARCHAR,
+  old_status VARCHAR,
+  new_status VARCHAR,
+  old_current_period_start TIMESTAMP WITH TIME ZONE,
+  new_current_period_start TIMESTAMP WITH TIME ZONE,
+  old_current_period_end TIMESTAMP WITH TIME ZONE,
+  new_current_period_end TIMESTAMP WITH TIME ZONE,
+  old_cancel_at TIMESTAMP WITH TIME ZONE,
+  new_cancel_at TIMESTAMP WITH TIME ZONE,
+  old_canceled_at TIMESTAMP WITH TIME ZONE,
+  new_canceled_at TIMESTAMP WITH TIME ZONE,
+  event_timestamp INT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  INDEX (subscription_id, created_at)
+)
@@ -0,0 +1,18 @@
+CREATE TABLE customers (
+  customer_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_customer_id VARCHAR NOT NULL UNIQUE,
+  buyer_user_id VARCHAR,
+  email VARCHAR,
+  name VARCHAR,
+  phone VARCHAR,
+  address_line1 VARCHAR,
+  address_line2 VARCHAR,
+  address_postal_code VARCHAR,
+  address_city VARCHAR,
+  address_state VARCHAR,
+  address_country VARCHAR,
+  event_timestamp INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  INDEX (buyer_user_id)
+)
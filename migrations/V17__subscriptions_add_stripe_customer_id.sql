@@ -0,0 +1,5 @@
+ALTER TABLE
+  subscriptions
+ADD
+  COLUMN stripe_customer_id VARCHAR;
+CREATE INDEX ON subscriptions (stripe_customer_id);
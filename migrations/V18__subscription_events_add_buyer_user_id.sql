@@ -0,0 +1,6 @@
+ALTER TABLE
+  subscription_events
+ADD
+  COLUMN old_buyer_user_id VARCHAR,
+ADD
+  COLUMN new_buyer_user_id VARCHAR;
@@ -0,0 +1,50 @@
+CREATE TABLE coupons (
+  coupon_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_coupon_id VARCHAR NOT NULL UNIQUE,
+  name VARCHAR,
+  percent_off FLOAT,
+  amount_off BIGINT,
+  currency VARCHAR,
+  duration VARCHAR,
+  duration_in_months BIGINT,
+  valid BOOL,
+  deleted BOOL NOT NULL DEFAULT false,
+  event_timestamp INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW()
+);
+
+CREATE TABLE promotion_codes (
+  promotion_code_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_promotion_code_id VARCHAR NOT NULL UNIQUE,
+  code VARCHAR NOT NULL,
+  stripe_coupon_id VARCHAR NOT NULL,
+  active BOOL NOT NULL,
+  event_timestamp INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW()
+);
+
+CREATE TABLE discounts (
+  discount_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_discount_id VARCHAR NOT NULL UNIQUE,
+  stripe_coupon_id VARCHAR NOT NULL,
+  stripe_promotion_code_id VARCHAR,
+  stripe_subscription_id VARCHAR,
+  stripe_checkout_session_id VARCHAR,
+  start_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  end_at TIMESTAMP WITH TIME ZONE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  INDEX (stripe_subscription_id),
+  INDEX (stripe_promotion_code_id)
+);
+
+CREATE TABLE discount_amounts (
+  stripe_discount_id VARCHAR NOT NULL,
+  stripe_object_id VARCHAR NOT NULL,
+  amount BIGINT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  PRIMARY KEY (stripe_discount_id, stripe_object_id)
+);
@@ -0,0 +1,8 @@
+ALTER TABLE
+  invoices
+ADD
+  COLUMN invoiced_at TIMESTAMP WITH TIME ZONE,
+ADD
+  COLUMN customer_country VARCHAR,
+ADD
+  COLUMN customer_tax_exempt VARCHAR;
@@ -0,0 +1,26 @@
+CREATE TABLE invoice_tax_amounts (
+  stripe_invoice_id VARCHAR NOT NULL,
+  stripe_tax_rate_id VARCHAR NOT NULL,
+  amount BIGINT NOT NULL,
+  taxable_amount BIGINT,
+  inclusive BOOL NOT NULL,
+  taxability_reason VARCHAR,
+  display_name VARCHAR,
+  percentage FLOAT,
+  country VARCHAR,
+  state VARCHAR,
+  jurisdiction VARCHAR,
+  tax_type VARCHAR,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  PRIMARY KEY (stripe_invoice_id, stripe_tax_rate_id)
+);
+
+CREATE TABLE invoice_tax_ids (
+  stripe_invoice_id VARCHAR NOT NULL,
+  tax_id_type VARCHAR NOT NULL,
+  value VARCHAR NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  PRIMARY KEY (stripe_invoice_id, tax_id_type, value)
+);
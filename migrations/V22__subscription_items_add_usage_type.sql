@@ -0,0 +1,4 @@
+ALTER TABLE
+  subscription_items
+ADD
+  COLUMN usage_type VARCHAR;
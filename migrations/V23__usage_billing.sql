@@ -0,0 +1,45 @@
+CREATE TABLE invoice_lines (
+  stripe_invoice_id VARCHAR NOT NULL,
+  stripe_invoice_line_id VARCHAR NOT NULL,
+  stripe_subscription_id VARCHAR,
+  stripe_subscription_item_id VARCHAR,
+  price_id VARCHAR,
+  usage_type VARCHAR,
+  quantity BIGINT,
+  amount BIGINT NOT NULL,
+  currency VARCHAR NOT NULL,
+  proration BOOL NOT NULL,
+  period_start TIMESTAMP WITH TIME ZONE,
+  period_end TIMESTAMP WITH TIME ZONE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  PRIMARY KEY (stripe_invoice_id, stripe_invoice_line_id),
+  INDEX (stripe_subscription_id)
+);
+
+CREATE TABLE usage_records (
+  stripe_usage_record_summary_id VARCHAR PRIMARY KEY,
+  stripe_subscription_item_id VARCHAR NOT NULL,
+  stripe_invoice_id VARCHAR,
+  total_usage BIGINT NOT NULL,
+  period_start TIMESTAMP WITH TIME ZONE,
+  period_end TIMESTAMP WITH TIME ZONE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  INDEX (stripe_subscription_item_id)
+);
+
+CREATE TABLE upcoming_invoices (
+  stripe_subscription_id VARCHAR PRIMARY KEY,
+  amount_due BIGINT,
+  subtotal BIGINT,
+  tax BIGINT,
+  total BIGINT,
+  currency VARCHAR,
+  period_start TIMESTAMP WITH TIME ZONE,
+  period_end TIMESTAMP WITH TIME ZONE,
+  next_payment_attempt TIMESTAMP WITH TIME ZONE,
+  event_timestamp INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW()
+);
@@ -0,0 +1,21 @@
+CREATE TABLE plan_changes (
+  plan_change_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_event_id VARCHAR NOT NULL,
+  stripe_subscription_id VARCHAR NOT NULL,
+  stripe_subscription_item_id VARCHAR NOT NULL,
+  old_price_id VARCHAR,
+  new_price_id VARCHAR,
+  old_quantity BIGINT,
+  new_quantity BIGINT,
+  old_unit_amount BIGINT,
+  new_unit_amount BIGINT,
+  currency VARCHAR,
+  proration_amount BIGINT,
+  stripe_invoice_id VARCHAR,
+  changed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  UNIQUE (stripe_event_id, stripe_subscription_item_id),
+  INDEX (stripe_subscription_id),
+  INDEX (stripe_subscription_item_id)
+);
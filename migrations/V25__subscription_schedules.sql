@@ -0,0 +1,38 @@
+CREATE TABLE subscription_schedules (
+  subscription_schedule_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_subscription_schedule_id VARCHAR NOT NULL UNIQUE,
+  stripe_subscription_id VARCHAR,
+  schedule_status VARCHAR NOT NULL,
+  end_behavior VARCHAR NOT NULL,
+  current_phase_start TIMESTAMP WITH TIME ZONE,
+  current_phase_end TIMESTAMP WITH TIME ZONE,
+  canceled_at TIMESTAMP WITH TIME ZONE,
+  completed_at TIMESTAMP WITH TIME ZONE,
+  released_at TIMESTAMP WITH TIME ZONE,
+  event_timestamp INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  INDEX (stripe_subscription_id)
+);
+
+CREATE TABLE subscription_schedule_phases (
+  stripe_subscription_schedule_id VARCHAR NOT NULL,
+  phase_index INT NOT NULL,
+  start_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  end_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  currency VARCHAR NOT NULL,
+  trial_end TIMESTAMP WITH TIME ZONE,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  PRIMARY KEY (stripe_subscription_schedule_id, phase_index)
+);
+
+CREATE TABLE subscription_schedule_phase_items (
+  stripe_subscription_schedule_id VARCHAR NOT NULL,
+  phase_index INT NOT NULL,
+  price_id VARCHAR NOT NULL,
+  quantity BIGINT,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  PRIMARY KEY (stripe_subscription_schedule_id, phase_index, price_id)
+);
@@ -0,0 +1,6 @@
+ALTER TABLE
+  subscriptions
+ADD
+  COLUMN pause_behavior VARCHAR,
+ADD
+  COLUMN pause_resumes_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,25 @@
+CREATE TABLE pending_updates (
+  pending_update_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_subscription_id VARCHAR NOT NULL,
+  expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  billing_cycle_anchor TIMESTAMP WITH TIME ZONE,
+  trial_end TIMESTAMP WITH TIME ZONE,
+  trial_from_plan BOOL,
+  update_status VARCHAR NOT NULL DEFAULT 'pending',
+  resolved_at TIMESTAMP WITH TIME ZONE,
+  resolving_stripe_event_id VARCHAR,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  UNIQUE (stripe_subscription_id, expires_at)
+);
+
+CREATE TABLE pending_update_items (
+  pending_update_id UUID NOT NULL,
+  stripe_subscription_item_id VARCHAR NOT NULL,
+  price_id VARCHAR,
+  quantity BIGINT,
+  unit_amount BIGINT,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  PRIMARY KEY (pending_update_id, stripe_subscription_item_id)
+);
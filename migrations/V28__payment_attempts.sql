@@ -0,0 +1,23 @@
+CREATE TABLE payment_attempts (
+  payment_attempt_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_event_id VARCHAR NOT NULL UNIQUE,
+  event_type VARCHAR NOT NULL,
+  stripe_intent_id VARCHAR NOT NULL,
+  intent_type VARCHAR NOT NULL,
+  intent_status VARCHAR NOT NULL,
+  stripe_invoice_id VARCHAR,
+  stripe_customer_id VARCHAR,
+  stripe_payment_method_id VARCHAR,
+  amount BIGINT,
+  currency VARCHAR,
+  next_action_type VARCHAR,
+  error_type VARCHAR,
+  error_code VARCHAR,
+  decline_code VARCHAR,
+  error_message VARCHAR,
+  event_timestamp INT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  INDEX (stripe_intent_id),
+  INDEX (stripe_invoice_id),
+  INDEX (stripe_customer_id)
+);
@@ -0,0 +1,22 @@
+CREATE TABLE payment_methods (
+  payment_method_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_payment_method_id VARCHAR NOT NULL UNIQUE,
+  stripe_customer_id VARCHAR,
+  payment_method_type VARCHAR NOT NULL,
+  card_brand VARCHAR,
+  card_last4 VARCHAR,
+  card_exp_month INT,
+  card_exp_year INT,
+  expires_at TIMESTAMP WITH TIME ZONE,
+  detached_at TIMESTAMP WITH TIME ZONE,
+  expiry_warned_at TIMESTAMP WITH TIME ZONE,
+  event_timestamp INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  INDEX (stripe_customer_id)
+);
+
+ALTER TABLE
+  customers
+ADD
+  COLUMN default_payment_method_id VARCHAR;
@@ -0,0 +1,4 @@
+ALTER TABLE
+  subscriptions
+ADD
+  COLUMN buyer_event_timestamp INT NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+ALTER TABLE
+  customers
+ADD
+  COLUMN pseudonymized_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,4 @@
+ALTER TABLE
+  pending_updates
+ADD
+  COLUMN event_timestamp INT NOT NULL DEFAULT 0;
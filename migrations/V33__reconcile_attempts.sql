@@ -0,0 +1,7 @@
+CREATE TABLE reconcile_attempts (
+  stripe_subscription_id VARCHAR PRIMARY KEY,
+  attempts INT NOT NULL,
+  last_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW()
+);
@@ -0,0 +1,12 @@
+CREATE TABLE subscription_items (
+  subscription_item_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  subscription_id UUID NOT NULL REFERENCES subscriptions (subscription_id) ON DELETE CASCADE,
+  stripe_subscription_item_id VARCHAR NOT NULL UNIQUE,
+  price_id VARCHAR NOT NULL,
+  product_id VARCHAR,
+  quantity BIGINT,
+  unit_amount BIGINT,
+  currency VARCHAR,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW()
+)
@@ -0,0 +1,18 @@
+CREATE TABLE invoices (
+  invoice_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  stripe_invoice_id VARCHAR NOT NULL UNIQUE,
+  stripe_subscription_id VARCHAR,
+  amount_due INT,
+  amount_paid INT,
+  currency VARCHAR,
+  tax INT,
+  hosted_invoice_url VARCHAR,
+  invoice_pdf_url VARCHAR,
+  invoice_status VARCHAR,
+  period_start TIMESTAMP WITH TIME ZONE,
+  period_end TIMESTAMP WITH TIME ZONE,
+  event_timestamp INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() ON UPDATE NOW(),
+  INDEX (stripe_subscription_id)
+)
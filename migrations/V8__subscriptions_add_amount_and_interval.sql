@@ -0,0 +1,10 @@
+ALTER TABLE
+  subscriptions
+ADD
+  COLUMN amount INT,
+ADD
+  COLUMN currency VARCHAR,
+ADD
+  COLUMN recurring_interval VARCHAR,
+ADD
+  COLUMN recurring_interval_count INT;
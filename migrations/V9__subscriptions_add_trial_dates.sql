@@ -0,0 +1,6 @@
+ALTER TABLE
+  subscriptions
+ADD
+  COLUMN trial_start TIMESTAMP WITH TIME ZONE,
+ADD
+  COLUMN trial_end TIMESTAMP WITH TIME ZONE;
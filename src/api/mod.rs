@@ -10,4 +10,10 @@ pub mod sited_io {
             include!("sited_io.media.v1.rs");
         }
     }
+
+    pub mod stripe_webhooks {
+        pub mod v1 {
+            include!("sited_io.stripe_webhooks.v1.rs");
+        }
+    }
 }
@@ -25,6 +25,43 @@ pub struct MediaSubscriptionResponse {
     pub canceled_at: ::core::option::Option<u64>,
     #[prost(uint64, optional, tag = "13")]
     pub cancel_at: ::core::option::Option<u64>,
+    #[prost(message, repeated, tag = "14")]
+    pub items: ::prost::alloc::vec::Vec<MediaSubscriptionItem>,
+    #[prost(int64, optional, tag = "15")]
+    pub amount: ::core::option::Option<i64>,
+    #[prost(string, optional, tag = "16")]
+    pub currency: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "17")]
+    pub interval: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "18")]
+    pub interval_count: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "19")]
+    pub trial_start: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "20")]
+    pub trial_end: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "21")]
+    pub access_until: ::core::option::Option<u64>,
+    #[prost(enumeration = "SubscriptionStatus", tag = "22")]
+    pub status: i32,
+    #[prost(string, optional, tag = "23")]
+    pub pause_behavior: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "24")]
+    pub pause_resumes_at: ::core::option::Option<u64>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MediaSubscriptionItem {
+    #[prost(string, tag = "1")]
+    pub price_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "2")]
+    pub product_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "3")]
+    pub quantity: ::core::option::Option<u64>,
+    #[prost(int64, optional, tag = "4")]
+    pub unit_amount: ::core::option::Option<i64>,
+    #[prost(string, optional, tag = "5")]
+    pub currency: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "6")]
+    pub usage_type: ::core::option::Option<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PutMediaSubscriptionRequest {
@@ -97,3 +134,52 @@ pub struct ResumeMediaSubscriptionRequest {
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct ResumeMediaSubscriptionResponse {}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum SubscriptionStatus {
+    Unspecified = 0,
+    Incomplete = 1,
+    IncompleteExpired = 2,
+    Trialing = 3,
+    Active = 4,
+    PastDue = 5,
+    Canceled = 6,
+    Unpaid = 7,
+    Paused = 8,
+}
+impl SubscriptionStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::Unspecified => "SUBSCRIPTION_STATUS_UNSPECIFIED",
+            SubscriptionStatus::Incomplete => "SUBSCRIPTION_STATUS_INCOMPLETE",
+            SubscriptionStatus::IncompleteExpired => {
+                "SUBSCRIPTION_STATUS_INCOMPLETE_EXPIRED"
+            }
+            SubscriptionStatus::Trialing => "SUBSCRIPTION_STATUS_TRIALING",
+            SubscriptionStatus::Active => "SUBSCRIPTION_STATUS_ACTIVE",
+            SubscriptionStatus::PastDue => "SUBSCRIPTION_STATUS_PAST_DUE",
+            SubscriptionStatus::Canceled => "SUBSCRIPTION_STATUS_CANCELED",
+            SubscriptionStatus::Unpaid => "SUBSCRIPTION_STATUS_UNPAID",
+            SubscriptionStatus::Paused => "SUBSCRIPTION_STATUS_PAUSED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SUBSCRIPTION_STATUS_UNSPECIFIED" => Some(Self::Unspecified),
+            "SUBSCRIPTION_STATUS_INCOMPLETE" => Some(Self::Incomplete),
+            "SUBSCRIPTION_STATUS_INCOMPLETE_EXPIRED" => Some(Self::IncompleteExpired),
+            "SUBSCRIPTION_STATUS_TRIALING" => Some(Self::Trialing),
+            "SUBSCRIPTION_STATUS_ACTIVE" => Some(Self::Active),
+            "SUBSCRIPTION_STATUS_PAST_DUE" => Some(Self::PastDue),
+            "SUBSCRIPTION_STATUS_CANCELED" => Some(Self::Canceled),
+            "SUBSCRIPTION_STATUS_UNPAID" => Some(Self::Unpaid),
+            "SUBSCRIPTION_STATUS_PAUSED" => Some(Self::Paused),
+            _ => None,
+        }
+    }
+}
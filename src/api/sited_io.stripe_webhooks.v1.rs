@@ -0,0 +1,253 @@
+// This file is @generated by prost-build.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AccessExpiredResponse {
+    #[prost(string, tag = "1")]
+    pub media_subscription_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub stripe_subscription_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub buyer_user_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "4")]
+    pub shop_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "5")]
+    pub offer_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, tag = "6")]
+    pub expired_at: u64,
+    #[prost(enumeration = "AccessExpiredReason", tag = "7")]
+    pub reason: i32,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum AccessExpiredReason {
+    Unspecified = 0,
+    PaymentLapsed = 1,
+    Canceled = 2,
+}
+impl AccessExpiredReason {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            AccessExpiredReason::Unspecified => "ACCESS_EXPIRED_REASON_UNSPECIFIED",
+            AccessExpiredReason::PaymentLapsed => "ACCESS_EXPIRED_REASON_PAYMENT_LAPSED",
+            AccessExpiredReason::Canceled => "ACCESS_EXPIRED_REASON_CANCELED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ACCESS_EXPIRED_REASON_UNSPECIFIED" => Some(Self::Unspecified),
+            "ACCESS_EXPIRED_REASON_PAYMENT_LAPSED" => Some(Self::PaymentLapsed),
+            "ACCESS_EXPIRED_REASON_CANCELED" => Some(Self::Canceled),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CustomerAddressResponse {
+    #[prost(string, optional, tag = "1")]
+    pub line1: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "2")]
+    pub line2: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "3")]
+    pub postal_code: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "4")]
+    pub city: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "5")]
+    pub state: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "6")]
+    pub country: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CustomerResponse {
+    #[prost(string, tag = "1")]
+    pub customer_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub stripe_customer_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "3")]
+    pub buyer_user_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "4")]
+    pub email: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "5")]
+    pub name: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "6")]
+    pub phone: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(message, optional, tag = "7")]
+    pub address: ::core::option::Option<CustomerAddressResponse>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InvoiceResponse {
+    #[prost(string, tag = "1")]
+    pub invoice_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub stripe_invoice_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "3")]
+    pub stripe_subscription_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(int64, optional, tag = "4")]
+    pub amount_due: ::core::option::Option<i64>,
+    #[prost(int64, optional, tag = "5")]
+    pub amount_paid: ::core::option::Option<i64>,
+    #[prost(string, optional, tag = "6")]
+    pub currency: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(int64, optional, tag = "7")]
+    pub tax: ::core::option::Option<i64>,
+    #[prost(string, optional, tag = "8")]
+    pub hosted_invoice_url: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "9")]
+    pub invoice_pdf_url: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "10")]
+    pub invoice_status: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "11")]
+    pub period_start: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "12")]
+    pub period_end: ::core::option::Option<u64>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PaymentMethodExpiringResponse {
+    #[prost(string, tag = "1")]
+    pub media_subscription_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub stripe_subscription_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub buyer_user_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "4")]
+    pub shop_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "5")]
+    pub offer_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, tag = "6")]
+    pub stripe_payment_method_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "7")]
+    pub card_brand: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "8")]
+    pub card_last4: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint32, tag = "9")]
+    pub exp_month: u32,
+    #[prost(uint32, tag = "10")]
+    pub exp_year: u32,
+    #[prost(uint64, tag = "11")]
+    pub expires_at: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubscriptionUpdate {
+    #[prost(uint32, tag = "1")]
+    pub version: u32,
+    #[prost(bool, tag = "2")]
+    pub pending: bool,
+    #[prost(string, repeated, tag = "3")]
+    pub missing_fields: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(message, optional, tag = "4")]
+    pub subscription: ::core::option::Option<PartialSubscriptionResponse>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PartialSubscriptionResponse {
+    #[prost(string, tag = "1")]
+    pub media_subscription_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "2")]
+    pub buyer_user_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "4")]
+    pub shop_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "5")]
+    pub offer_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "6")]
+    pub current_period_start: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "7")]
+    pub current_period_end: ::core::option::Option<u64>,
+    #[prost(string, optional, tag = "8")]
+    pub subscription_status: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "9")]
+    pub payed_at: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "10")]
+    pub payed_until: ::core::option::Option<u64>,
+    #[prost(string, tag = "11")]
+    pub stripe_subscription_id: ::prost::alloc::string::String,
+    #[prost(uint64, optional, tag = "12")]
+    pub canceled_at: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "13")]
+    pub cancel_at: ::core::option::Option<u64>,
+    #[prost(message, repeated, tag = "14")]
+    pub items: ::prost::alloc::vec::Vec<super::super::media::v1::MediaSubscriptionItem>,
+    #[prost(int64, optional, tag = "15")]
+    pub amount: ::core::option::Option<i64>,
+    #[prost(string, optional, tag = "16")]
+    pub currency: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "17")]
+    pub interval: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "18")]
+    pub interval_count: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "19")]
+    pub trial_start: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "20")]
+    pub trial_end: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "21")]
+    pub access_until: ::core::option::Option<u64>,
+    #[prost(enumeration = "super::super::media::v1::SubscriptionStatus", tag = "22")]
+    pub status: i32,
+    #[prost(string, optional, tag = "23")]
+    pub pause_behavior: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "24")]
+    pub pause_resumes_at: ::core::option::Option<u64>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PlanChangedResponse {
+    #[prost(string, tag = "1")]
+    pub plan_change_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub stripe_subscription_item_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "3")]
+    pub old_price_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "4")]
+    pub new_price_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "5")]
+    pub old_quantity: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "6")]
+    pub new_quantity: ::core::option::Option<u64>,
+    #[prost(int64, optional, tag = "7")]
+    pub old_unit_amount: ::core::option::Option<i64>,
+    #[prost(int64, optional, tag = "8")]
+    pub new_unit_amount: ::core::option::Option<i64>,
+    #[prost(string, optional, tag = "9")]
+    pub currency: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, tag = "10")]
+    pub changed_at: u64,
+    #[prost(message, optional, tag = "11")]
+    pub subscription: ::core::option::Option<
+        super::super::media::v1::MediaSubscriptionResponse,
+    >,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubscriptionSchedulePhaseItemResponse {
+    #[prost(string, tag = "1")]
+    pub price_id: ::prost::alloc::string::String,
+    #[prost(uint64, optional, tag = "2")]
+    pub quantity: ::core::option::Option<u64>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubscriptionSchedulePhaseResponse {
+    #[prost(uint64, tag = "1")]
+    pub start_at: u64,
+    #[prost(uint64, tag = "2")]
+    pub end_at: u64,
+    #[prost(string, tag = "3")]
+    pub currency: ::prost::alloc::string::String,
+    #[prost(uint64, optional, tag = "4")]
+    pub trial_end: ::core::option::Option<u64>,
+    #[prost(message, repeated, tag = "5")]
+    pub items: ::prost::alloc::vec::Vec<SubscriptionSchedulePhaseItemResponse>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SchedulePhaseChangedResponse {
+    #[prost(string, tag = "1")]
+    pub stripe_subscription_schedule_id: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "2")]
+    pub stripe_subscription_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(message, optional, tag = "3")]
+    pub current_phase: ::core::option::Option<SubscriptionSchedulePhaseResponse>,
+    #[prost(message, optional, tag = "4")]
+    pub next_phase: ::core::option::Option<SubscriptionSchedulePhaseResponse>,
+    #[prost(message, optional, tag = "5")]
+    pub subscription: ::core::option::Option<
+        super::super::media::v1::MediaSubscriptionResponse,
+    >,
+}
@@ -1,12 +1,14 @@
 #[derive(Debug, Clone)]
 pub struct AppSettings {
     pub stripe_endpoint_secret: String,
+    pub admin_token: String,
 }
 
 impl AppSettings {
-    pub fn new(stripe_endpoint_secret: String) -> Self {
+    pub fn new(stripe_endpoint_secret: String, admin_token: String) -> Self {
         Self {
             stripe_endpoint_secret,
+            admin_token,
         }
     }
 }
@@ -28,6 +28,10 @@ impl HttpError {
         Self::from_message(StatusCode::BAD_REQUEST, message)
     }
 
+    pub fn unauthorized() -> Self {
+        Self::from_message(StatusCode::UNAUTHORIZED, "unauthorized")
+    }
+
     pub fn internal() -> Self {
         Self::from_message(
             StatusCode::INTERNAL_SERVER_ERROR,
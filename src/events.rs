@@ -1,28 +1,137 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
 use chrono::{DateTime, Utc};
-use deadpool_postgres::Pool;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use stripe::{
-    CheckoutSession, Event, EventObject, Invoice,
-    Subscription as StripeSubscription,
+    Card as StripeCard, CheckoutSession, CheckoutSessionId, Client,
+    Coupon as StripeCoupon, Customer as StripeCustomer,
+    Discount as StripeDiscount, Event, EventObject, EventType, Expandable,
+    Invoice as StripeInvoice, InvoiceStatus, List, ListCheckoutSessions,
+    Metadata, PaymentIntent as StripePaymentIntent,
+    PaymentMethod as StripePaymentMethod, PromotionCode as StripePromotionCode,
+    RecurringUsageType, SetupIntent as StripeSetupIntent,
+    Subscription as StripeSubscription, SubscriptionId,
+    SubscriptionSchedule as StripeSubscriptionSchedule, UpdateSubscription,
+    UsageRecordSummary,
+};
+use uuid::Uuid;
+
+use crate::api::sited_io::media::v1::{
+    MediaSubscriptionItem, MediaSubscriptionResponse,
+    SubscriptionStatus as SubscriptionStatusResponse,
+};
+use crate::api::sited_io::stripe_webhooks::v1::{
+    CustomerAddressResponse, CustomerResponse, InvoiceResponse,
+    PartialSubscriptionResponse, PlanChangedResponse,
+    SchedulePhaseChangedResponse, SubscriptionSchedulePhaseItemResponse,
+    SubscriptionSchedulePhaseResponse, SubscriptionUpdate,
 };
+use crate::gdpr;
+use crate::metadata::{Attribution, MetadataMapping, MetadataSource};
+use crate::model::{
+    Coupon, Customer, CustomerAddress, Discount, DiscountAmount, GracePeriod,
+    Invoice, InvoiceLine, InvoiceTaxAmount, InvoiceTaxId, PaymentAttempt,
+    PaymentMethod, PendingUpdate, PendingUpdateItem, PlanChange, PlanItem,
+    PromotionCode, Subscription, SubscriptionEvent, SubscriptionItem,
+    SubscriptionSchedule, SubscriptionSchedulePhase,
+    SubscriptionSchedulePhaseItem, SubscriptionStatus, UpcomingInvoice,
+    UsageRecord,
+};
+use crate::{DbError, HttpError, Metrics, Publisher};
+
+/// Query parameters of the usage record summaries of a subscription item,
+/// for which async-stripe has no request type.
+#[derive(Serialize)]
+struct ListUsageRecordSummaries {
+    limit: u64,
+}
+
+/// Query parameters expanding the discounts of a subscription, which
+/// async-stripe only models through the single `discount`.
+#[derive(Serialize)]
+struct RetrieveSubscriptionDiscounts {
+    expand: &'static [&'static str],
+}
+
+#[derive(Deserialize)]
+struct SubscriptionDiscounts {
+    discounts: Vec<StripeDiscount>,
+}
+
+/// Periods covered by the lines of an invoice for one subscription.
+#[derive(Default)]
+struct InvoicePeriods {
+    regular: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    proration: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl InvoicePeriods {
+    fn add(
+        &mut self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        proration: bool,
+    ) {
+        let period = if proration {
+            &mut self.proration
+        } else {
+            &mut self.regular
+        };
+
+        *period = Some(match *period {
+            Some((s, e)) => (s.min(start), e.max(end)),
+            None => (start, end),
+        });
+    }
 
-use crate::api::sited_io::media::v1::MediaSubscriptionResponse;
-use crate::model::Subscription;
-use crate::{DbError, HttpError, Publisher};
+    fn paid(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        self.regular.or(self.proration)
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct EventService {
     pool: Pool,
     publisher: Publisher,
+    stripe_client: Option<Client>,
+    metadata_mapping: MetadataMapping,
+    metrics: Metrics,
+    default_grace_period: Duration,
 }
 
 impl EventService {
-    const METADATA_KEY_USER_ID: &'static str = "user_id";
-    const METADATA_KEY_OFFER_ID: &'static str = "offer_id";
-    const METADATA_KEY_SHOP_ID: &'static str = "shop_id";
+    pub fn new(
+        pool: Pool,
+        publisher: Publisher,
+        stripe_client: Option<Client>,
+        metadata_mapping: MetadataMapping,
+        metrics: Metrics,
+        default_grace_period: Duration,
+    ) -> Self {
+        Self {
+            pool,
+            publisher,
+            stripe_client,
+            metadata_mapping,
+            metrics,
+            default_grace_period,
+        }
+    }
 
-    pub fn new(pool: Pool, publisher: Publisher) -> Self {
-        Self { pool, publisher }
+    /// Returns the Stripe client, which is only configured if
+    /// `STRIPE_SECRET_KEY` is set.
+    fn stripe_client(&self) -> Result<&Client, HttpError> {
+        self.stripe_client.as_ref().ok_or_else(|| {
+            HttpError::from_message(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Stripe API is not configured",
+            )
+        })
     }
 
     fn unexpected_object(event: &Event) -> HttpError {
@@ -33,10 +142,61 @@ impl EventService {
         ))
     }
 
-    async fn send_updated_subscription(
+    fn subscription_status_response(
+        status: SubscriptionStatus,
+    ) -> SubscriptionStatusResponse {
+        match status {
+            SubscriptionStatus::Incomplete => {
+                SubscriptionStatusResponse::Incomplete
+            }
+            SubscriptionStatus::IncompleteExpired => {
+                SubscriptionStatusResponse::IncompleteExpired
+            }
+            SubscriptionStatus::Trialing => {
+                SubscriptionStatusResponse::Trialing
+            }
+            SubscriptionStatus::Active => SubscriptionStatusResponse::Active,
+            SubscriptionStatus::PastDue => SubscriptionStatusResponse::PastDue,
+            SubscriptionStatus::Canceled => {
+                SubscriptionStatusResponse::Canceled
+            }
+            SubscriptionStatus::Unpaid => SubscriptionStatusResponse::Unpaid,
+            SubscriptionStatus::Paused => SubscriptionStatusResponse::Paused,
+        }
+    }
+
+    /// Computes `access_until` with the grace period configured for the offer
+    /// or shop of the subscription, falling back to the default one.
+    async fn access_until(
         &self,
+        conn: &impl GenericClient,
+        subscription: &Subscription,
+    ) -> Result<Option<DateTime<Utc>>, DbError> {
+        let grace_period = match &subscription.shop_id {
+            Some(shop_id) => GracePeriod::get_effective(
+                conn,
+                shop_id,
+                subscription.offer_id.as_ref(),
+            )
+            .await?
+            .map(|g| chrono::Duration::seconds(g.grace_period_seconds)),
+            None => None,
+        };
+
+        let grace_period = grace_period.unwrap_or_else(|| {
+            chrono::Duration::from_std(self.default_grace_period).unwrap()
+        });
+
+        Ok(subscription.compute_access_until(grace_period))
+    }
+
+    /// Returns the subscription with all fields that are known so far.
+    async fn partial_subscription(
+        conn: &impl GenericClient,
         subscription: Subscription,
-    ) -> Result<(), HttpError> {
+    ) -> Result<PartialSubscriptionResponse, HttpError> {
+        let (payed_at, payed_until) = subscription.access_period();
+
         let Subscription {
             subscription_id,
             stripe_subscription_id,
@@ -46,263 +206,2692 @@ impl EventService {
             current_period_start,
             current_period_end,
             subscription_status,
-            payed_at,
-            payed_until,
+            payed_at: _,
+            payed_until: _,
             created_at,
             updated_at,
             canceled_at,
             cancel_at,
             event_timestamp,
+            amount,
+            currency,
+            recurring_interval,
+            recurring_interval_count,
+            trial_start,
+            trial_end,
+            access_expired_at,
+            access_until,
+            stripe_customer_id,
+            pause_behavior,
+            pause_resumes_at,
+            buyer_event_timestamp,
         } = subscription;
 
         // These fields are destructured here, in order to get an compiler error,
         // when we add new fields to the Subscription struct and do not handle them.
         #[allow(unused_variables, clippy::no_effect)]
-        (created_at, updated_at, event_timestamp);
-
-        if let (
-            Some(buyer_user_id),
-            Some(offer_id),
-            Some(shop_id),
-            Some(current_period_start),
-            Some(current_period_end),
-            Some(subscription_status),
-            Some(payed_at),
-            Some(payed_until),
-        ) = (
+        (
+            created_at,
+            updated_at,
+            event_timestamp,
+            access_expired_at,
+            stripe_customer_id,
+            buyer_event_timestamp,
+        );
+
+        let items = SubscriptionItem::list(conn, &subscription_id)
+            .await?
+            .into_iter()
+            .map(|item| MediaSubscriptionItem {
+                price_id: item.price_id,
+                product_id: item.product_id,
+                quantity: item.quantity.map(|q| q.try_into().unwrap()),
+                unit_amount: item.unit_amount,
+                currency: item.currency,
+                usage_type: item.usage_type,
+            })
+            .collect();
+
+        let partial_subscription = PartialSubscriptionResponse {
+            media_subscription_id: subscription_id.to_string(),
+            buyer_user_id,
+            shop_id: shop_id.map(|id| id.to_string()),
+            offer_id: offer_id.map(|id| id.to_string()),
+            current_period_start: current_period_start
+                .map(|t| t.timestamp().try_into().unwrap()),
+            current_period_end: current_period_end
+                .map(|t| t.timestamp().try_into().unwrap()),
+            subscription_status: subscription_status.map(|s| s.to_string()),
+            payed_at: payed_at.map(|t| t.timestamp().try_into().unwrap()),
+            payed_until: payed_until.map(|t| t.timestamp().try_into().unwrap()),
+            stripe_subscription_id,
+            canceled_at: canceled_at.map(|t| t.timestamp().try_into().unwrap()),
+            cancel_at: cancel_at.map(|t| t.timestamp().try_into().unwrap()),
+            items,
+            amount,
+            currency,
+            interval: recurring_interval,
+            interval_count: recurring_interval_count
+                .map(|c| c.try_into().unwrap()),
+            trial_start: trial_start.map(|t| t.timestamp().try_into().unwrap()),
+            trial_end: trial_end.map(|t| t.timestamp().try_into().unwrap()),
+            access_until: access_until
+                .map(|t| t.timestamp().try_into().unwrap()),
+            status: subscription_status
+                .map(Self::subscription_status_response)
+                .unwrap_or(SubscriptionStatusResponse::Unspecified)
+                .into(),
+            pause_behavior,
+            pause_resumes_at: pause_resumes_at
+                .map(|t| t.timestamp().try_into().unwrap()),
+        };
+
+        Ok(partial_subscription)
+    }
+
+    /// Returns the subscription as published to the media api, or `None` if
+    /// fields required for it are still missing.
+    fn media_subscription_response(
+        subscription: PartialSubscriptionResponse,
+    ) -> Option<MediaSubscriptionResponse> {
+        let PartialSubscriptionResponse {
+            media_subscription_id,
             buyer_user_id,
-            offer_id,
             shop_id,
+            offer_id,
             current_period_start,
             current_period_end,
             subscription_status,
             payed_at,
             payed_until,
-        ) {
-            self.publisher
-                .publish_subscription_upsert(&MediaSubscriptionResponse {
-                    media_subscription_id: subscription_id.to_string(),
-                    buyer_user_id: buyer_user_id.clone(),
-                    shop_id: shop_id.to_string(),
-                    offer_id: offer_id.to_string(),
-                    current_period_start: current_period_start
-                        .timestamp()
-                        .try_into()
-                        .unwrap(),
-                    current_period_end: current_period_end
-                        .timestamp()
-                        .try_into()
-                        .unwrap(),
-                    subscription_status: subscription_status.clone(),
-                    payed_at: payed_at.timestamp().try_into().unwrap(),
-                    payed_until: payed_until.timestamp().try_into().unwrap(),
-                    stripe_subscription_id: Some(
-                        stripe_subscription_id.clone(),
-                    ),
-                    canceled_at: canceled_at
-                        .map(|t| t.timestamp().try_into().unwrap()),
-                    cancel_at: cancel_at
-                        .map(|t| t.timestamp().try_into().unwrap()),
-                })
-                .await;
+            stripe_subscription_id,
+            canceled_at,
+            cancel_at,
+            items,
+            amount,
+            currency,
+            interval,
+            interval_count,
+            trial_start,
+            trial_end,
+            access_until,
+            status,
+            pause_behavior,
+            pause_resumes_at,
+        } = subscription;
 
-            tracing::info!("[EventService.send_updated_subscription] Sucessfully sent subscription to media api");
-        }
+        Some(MediaSubscriptionResponse {
+            media_subscription_id,
+            buyer_user_id: buyer_user_id?,
+            shop_id: shop_id?,
+            offer_id: offer_id?,
+            current_period_start: current_period_start?,
+            current_period_end: current_period_end?,
+            subscription_status: subscription_status?,
+            payed_at: payed_at?,
+            payed_until: payed_until?,
+            stripe_subscription_id: Some(stripe_subscription_id),
+            canceled_at,
+            cancel_at,
+            items,
+            amount,
+            currency,
+            interval,
+            interval_count,
+            trial_start,
+            trial_end,
+            access_until,
+            status,
+            pause_behavior,
+            pause_resumes_at,
+        })
+    }
 
-        Ok(())
+    async fn media_subscription(
+        conn: &impl GenericClient,
+        subscription: Subscription,
+    ) -> Result<Option<MediaSubscriptionResponse>, HttpError> {
+        let partial_subscription =
+            Self::partial_subscription(conn, subscription).await?;
+
+        Ok(Self::media_subscription_response(partial_subscription))
     }
 
-    async fn handle_checkout_session(
+    /// Publishes the subscription and returns the message published to the
+    /// media api, if it is complete.
+    async fn send_updated_subscription(
         &self,
-        checkout_session: CheckoutSession,
-    ) -> Result<HttpResponse, HttpError> {
-        if let (Some(stripe_subscription), Some(metadata)) =
-            (checkout_session.subscription, checkout_session.metadata)
-        {
-            if let (Some(buyer_user_id), Some(offer_id), Some(shop_id)) = (
-                metadata.get(Self::METADATA_KEY_USER_ID),
-                metadata
-                    .get(Self::METADATA_KEY_OFFER_ID)
-                    .and_then(|id| id.parse().ok()),
-                metadata
-                    .get(Self::METADATA_KEY_SHOP_ID)
-                    .and_then(|id| id.parse().ok()),
-            ) {
-                let stripe_subscription_id =
-                    stripe_subscription.id().to_string();
+        conn: &impl GenericClient,
+        subscription: Subscription,
+    ) -> Result<Option<MediaSubscriptionResponse>, HttpError> {
+        self.send_subscription(conn, subscription, false).await
+    }
 
-                let updated_subscription = Subscription::put_checkout_session(
-                    &self.pool,
-                    &stripe_subscription_id,
-                    buyer_user_id,
-                    &offer_id,
-                    &shop_id,
-                    checkout_session.created,
-                )
-                .await?;
+    /// Publishes the subscription like `send_updated_subscription`, but as
+    /// delete to the media api.
+    async fn send_deleted_subscription(
+        &self,
+        conn: &impl GenericClient,
+        subscription: Subscription,
+    ) -> Result<Option<MediaSubscriptionResponse>, HttpError> {
+        self.send_subscription(conn, subscription, true).await
+    }
+
+    async fn send_subscription(
+        &self,
+        conn: &impl GenericClient,
+        subscription: Subscription,
+        deleted: bool,
+    ) -> Result<Option<MediaSubscriptionResponse>, HttpError> {
+        let access_until = self.access_until(conn, &subscription).await?;
+
+        let subscription = if access_until != subscription.access_until {
+            Subscription::update_access_until(
+                conn,
+                &subscription.subscription_id,
+                access_until,
+            )
+            .await?
+        } else {
+            subscription
+        };
+
+        let missing_fields = subscription.missing_fields();
+        let stripe_subscription_id =
+            subscription.stripe_subscription_id.clone();
+
+        let partial_subscription =
+            Self::partial_subscription(conn, subscription).await?;
+        let media_subscription =
+            Self::media_subscription_response(partial_subscription.clone());
+
+        let pending = media_subscription.is_none();
 
-                self.send_updated_subscription(updated_subscription).await?;
+        if let Some(media_subscription) = &media_subscription {
+            if deleted {
+                self.publisher
+                    .publish_subscription_delete(media_subscription)
+                    .await;
+            } else {
+                self.publisher
+                    .publish_subscription_upsert(media_subscription)
+                    .await;
+            }
+
+            tracing::info!("[EventService.send_subscription] Sucessfully sent subscription to media api");
+        } else {
+            tracing::warn!(
+                "[EventService.send_subscription] Holding back subscription {stripe_subscription_id}, missing fields: {}",
+                missing_fields.join(", ")
+            );
+
+            for field in missing_fields.iter() {
+                self.metrics.increment(
+                    "stripe_webhooks_subscriptions_pending_total",
+                    &[("missing_field", *field)],
+                );
             }
         }
 
-        Ok(HttpResponse::Ok().finish())
+        self.publisher
+            .publish_subscription_update(&SubscriptionUpdate {
+                version: Publisher::SUBSCRIPTION_UPDATE_VERSION,
+                pending,
+                missing_fields: missing_fields
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                subscription: Some(partial_subscription),
+            })
+            .await;
+
+        Ok(media_subscription)
     }
 
-    async fn handle_subscription(
+    async fn retrieve_subscription(
         &self,
-        subscription: StripeSubscription,
-        event_timestamp: i64,
-    ) -> Result<HttpResponse, HttpError> {
-        let stripe_subscription_id = subscription.id.to_string();
+        stripe_subscription_id: &SubscriptionId,
+    ) -> Option<StripeSubscription> {
+        StripeSubscription::retrieve(
+            self.stripe_client().ok()?,
+            stripe_subscription_id,
+            &["items.data.price.product"],
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "[EventService.retrieve_subscription] {stripe_subscription_id}: {err}"
+            );
+        })
+        .ok()
+    }
 
-        let mut conn = self.pool.get().await.map_err(DbError::from)?;
-        let transaction = conn.transaction().await.map_err(DbError::from)?;
+    async fn retrieve_checkout_session(
+        &self,
+        checkout_session_id: &CheckoutSessionId,
+    ) -> Option<CheckoutSession> {
+        CheckoutSession::retrieve(
+            self.stripe_client().ok()?,
+            checkout_session_id,
+            &["total_details.breakdown"],
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "[EventService.retrieve_checkout_session] {checkout_session_id}: {err}"
+            );
+        })
+        .ok()
+    }
 
-        let found_subscription =
-            Subscription::get(&transaction, &stripe_subscription_id).await?;
+    async fn retrieve_customer(
+        &self,
+        customer: &Expandable<StripeCustomer>,
+    ) -> Option<StripeCustomer> {
+        if let Some(customer) = customer.as_object() {
+            return Some(customer.clone());
+        }
 
-        let mut update = false;
+        StripeCustomer::retrieve(
+            self.stripe_client().ok()?,
+            &customer.id(),
+            &[],
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "[EventService.retrieve_customer] {}: {err}",
+                customer.id()
+            );
+        })
+        .ok()
+    }
 
-        match found_subscription {
-            Some(found_subscription) => {
-                if found_subscription.event_timestamp < event_timestamp {
-                    update = true;
+    /// Retrieves the invoice with the tax rates of its tax amounts expanded,
+    /// which events only reference by id.
+    async fn retrieve_invoice_tax_rates(
+        &self,
+        invoice: &StripeInvoice,
+    ) -> Result<StripeInvoice, HttpError> {
+        StripeInvoice::retrieve(
+            self.stripe_client()?,
+            &invoice.id,
+            &["total_tax_amounts.tax_rate"],
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "[EventService.retrieve_invoice_tax_rates] {}: {err}",
+                invoice.id
+            );
+            HttpError::internal()
+        })
+    }
+
+    async fn list_usage_record_summaries(
+        &self,
+        stripe_subscription_item_id: &String,
+    ) -> Option<List<UsageRecordSummary>> {
+        self.stripe_client()
+            .ok()?
+            .get_query(
+                &format!(
+                    "/subscription_items/{}/usage_record_summaries",
+                    stripe_subscription_item_id
+                ),
+                ListUsageRecordSummaries { limit: 100 },
+            )
+            .await
+            .map_err(|err| {
+                tracing::error!(
+                    "[EventService.list_usage_record_summaries] {stripe_subscription_item_id}: {err}"
+                );
+            })
+            .ok()
+    }
+
+    fn subscription_metadata(
+        subscription: &StripeSubscription,
+        source: MetadataSource,
+    ) -> Vec<&Metadata> {
+        let prices = subscription
+            .items
+            .data
+            .iter()
+            .filter_map(|item| item.price.as_ref());
+
+        match source {
+            MetadataSource::Subscription => vec![&subscription.metadata],
+            MetadataSource::Price => {
+                prices.filter_map(|price| price.metadata.as_ref()).collect()
+            }
+            MetadataSource::Product => prices
+                .filter_map(|price| price.product.as_ref())
+                .filter_map(|product| product.as_object())
+                .filter_map(|product| product.metadata.as_ref())
+                .collect(),
+            MetadataSource::Session | MetadataSource::Customer => Vec::new(),
+        }
+    }
+
+    /// Collects buyer, offer and shop from the metadata of the Stripe objects
+    /// configured in `MetadataMapping`, in the configured order. Objects that
+    /// are not part of the webhook payload are only fetched when needed.
+    async fn resolve_attribution(
+        &self,
+        checkout_session: &CheckoutSession,
+        stripe_subscription_id: &SubscriptionId,
+    ) -> Attribution {
+        let mut attribution = Attribution::default();
+        let mut subscription = None;
+
+        for source in self.metadata_mapping.sources() {
+            if attribution.is_complete() {
+                break;
+            }
+
+            match source {
+                MetadataSource::Session => {
+                    if let Some(metadata) = &checkout_session.metadata {
+                        attribution.fill(metadata, &self.metadata_mapping);
+                    }
                 }
+                MetadataSource::Customer => {
+                    let customer = match &checkout_session.customer {
+                        Some(customer) => {
+                            self.retrieve_customer(customer).await
+                        }
+                        None => None,
+                    };
 
-                if let Some(metadata_user_id) =
-                    subscription.metadata.get(Self::METADATA_KEY_USER_ID)
-                {
-                    if found_subscription
-                        .buyer_user_id
-                        .is_some_and(|user_id| user_id != *metadata_user_id)
-                    {
-                        Subscription::update_buyer_user_id(
-                            &transaction,
-                            &stripe_subscription_id,
-                            metadata_user_id,
-                        )
-                        .await?;
+                    if let Some(metadata) = customer.and_then(|c| c.metadata) {
+                        attribution.fill(&metadata, &self.metadata_mapping);
+                    }
+                }
+                MetadataSource::Subscription
+                | MetadataSource::Price
+                | MetadataSource::Product => {
+                    if subscription.is_none() {
+                        subscription = self
+                            .retrieve_subscription(stripe_subscription_id)
+                            .await;
+                    }
+
+                    if let Some(subscription) = &subscription {
+                        for metadata in
+                            Self::subscription_metadata(subscription, *source)
+                        {
+                            attribution.fill(metadata, &self.metadata_mapping);
+                        }
                     }
                 }
-            }
-            None => {
-                update = true;
             }
         }
 
-        if update {
-            let current_period_start = DateTime::<Utc>::from_timestamp(
-                subscription.current_period_start,
-                0,
-            )
-            .unwrap();
+        attribution
+    }
 
-            let current_period_end = DateTime::<Utc>::from_timestamp(
-                subscription.current_period_end,
-                0,
-            )
-            .unwrap();
+    async fn put_coupon(
+        conn: &impl GenericClient,
+        coupon: &StripeCoupon,
+        deleted: bool,
+        event_timestamp: i64,
+    ) -> Result<(), HttpError> {
+        Coupon::put(
+            conn,
+            &coupon.id.to_string(),
+            coupon.name.clone(),
+            coupon.percent_off,
+            coupon.amount_off,
+            coupon.currency.map(|c| c.to_string()),
+            coupon.duration.map(|d| d.to_string()),
+            coupon.duration_in_months,
+            coupon.valid,
+            deleted || coupon.deleted,
+            event_timestamp,
+        )
+        .await?;
 
-            let canceled_at = subscription
-                .canceled_at
-                .and_then(|c| DateTime::<Utc>::from_timestamp(c, 0));
+        Ok(())
+    }
 
-            let cancel_at = subscription
-                .cancel_at
-                .and_then(|c| DateTime::<Utc>::from_timestamp(c, 0));
+    async fn put_promotion_code(
+        conn: &impl GenericClient,
+        promotion_code: &StripePromotionCode,
+        event_timestamp: i64,
+    ) -> Result<(), HttpError> {
+        Self::put_coupon(conn, &promotion_code.coupon, false, event_timestamp)
+            .await?;
 
-            let updated_subscription = Subscription::put_subscription(
-                &transaction,
-                &stripe_subscription_id,
-                &current_period_start,
-                &current_period_end,
-                &subscription.status.to_string(),
-                canceled_at,
-                cancel_at,
-                subscription.created,
-            )
+        PromotionCode::put(
+            conn,
+            &promotion_code.id.to_string(),
+            &promotion_code.code,
+            &promotion_code.coupon.id.to_string(),
+            promotion_code.active,
+            event_timestamp,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores a discount together with its coupon and, if expanded, its
+    /// promotion code.
+    async fn put_discount(
+        conn: &impl GenericClient,
+        discount: &StripeDiscount,
+        stripe_subscription_id: Option<&String>,
+        stripe_checkout_session_id: Option<&String>,
+        event_timestamp: i64,
+    ) -> Result<(), HttpError> {
+        Self::put_coupon(conn, &discount.coupon, false, event_timestamp)
             .await?;
 
-            self.send_updated_subscription(updated_subscription).await?;
+        if let Some(promotion_code) =
+            discount.promotion_code.as_ref().and_then(|p| p.as_object())
+        {
+            Self::put_promotion_code(conn, promotion_code, event_timestamp)
+                .await?;
         }
 
-        transaction.commit().await.map_err(DbError::from)?;
+        Discount::put(
+            conn,
+            &discount.id.to_string(),
+            &discount.coupon.id.to_string(),
+            discount
+                .promotion_code
+                .as_ref()
+                .map(|p| p.id().to_string())
+                .as_ref(),
+            discount.subscription.as_ref().or(stripe_subscription_id),
+            discount
+                .checkout_session
+                .as_ref()
+                .or(stripe_checkout_session_id),
+            DateTime::<Utc>::from_timestamp(discount.start, 0).unwrap(),
+            discount
+                .end
+                .and_then(|e| DateTime::<Utc>::from_timestamp(e, 0)),
+        )
+        .await?;
 
-        Ok(HttpResponse::Ok().finish())
+        Ok(())
     }
 
-    async fn handle_invoice(
+    /// Returns all discounts of a subscription. Events only carry their ids,
+    /// so they are retrieved expanded. Without a Stripe client only the
+    /// single `discount` of the event is known.
+    async fn subscription_discounts(
         &self,
-        invoice: Invoice,
-    ) -> Result<HttpResponse, HttpError> {
-        if let Some(lines) = invoice.lines {
-            for line in lines.data {
-                if let Some(stripe_subscription) = line.subscription {
-                    if let (Some(payed_at), Some(payed_until)) = (
-                        line.period.as_ref().and_then(|p| p.start).and_then(
-                            |p| DateTime::<Utc>::from_timestamp(p, 0),
-                        ),
-                        line.period.and_then(|p| p.end).and_then(|p| {
-                            DateTime::<Utc>::from_timestamp(p, 0)
-                        }),
-                    ) {
-                        let stripe_subscription_id =
-                            stripe_subscription.id().to_string();
-
-                        let updated_subscription = Subscription::put_invoice(
-                            &self.pool,
-                            &stripe_subscription_id,
-                            &payed_at,
-                            &payed_until,
-                            invoice.created.unwrap_or(0),
-                        )
-                        .await?;
+        subscription: &StripeSubscription,
+    ) -> Result<Vec<StripeDiscount>, HttpError> {
+        let Some(stripe_client) = &self.stripe_client else {
+            return Ok(subscription.discount.clone().into_iter().collect());
+        };
 
-                        self.send_updated_subscription(updated_subscription)
-                            .await?;
-                    }
-                }
+        let subscription_discounts: SubscriptionDiscounts = stripe_client
+            .get_query(
+                &format!("/subscriptions/{}", subscription.id),
+                RetrieveSubscriptionDiscounts {
+                    expand: &["discounts"],
+                },
+            )
+            .await
+            .map_err(|err| {
+                tracing::error!(
+                    "[EventService.subscription_discounts] {}: {err}",
+                    subscription.id
+                );
+                HttpError::internal()
+            })?;
+
+        Ok(subscription_discounts.discounts)
+    }
+
+    /// Stores the discounts applied in a checkout session. The breakdown of
+    /// `total_details` is not part of the event, so the session is retrieved
+    /// again if it was discounted at all.
+    async fn put_checkout_session_discounts(
+        &self,
+        checkout_session: &CheckoutSession,
+    ) -> Result<(), HttpError> {
+        let amount_discount = checkout_session
+            .total_details
+            .as_ref()
+            .map(|t| t.amount_discount)
+            .unwrap_or_default();
+
+        if amount_discount == 0 {
+            return Ok(());
+        }
+
+        let stripe_checkout_session_id = checkout_session.id.to_string();
+        let stripe_subscription_id = checkout_session
+            .subscription
+            .as_ref()
+            .map(|s| s.id().to_string());
+
+        let breakdown = self
+            .retrieve_checkout_session(&checkout_session.id)
+            .await
+            .and_then(|c| c.total_details)
+            .and_then(|t| t.breakdown);
+
+        if let Some(breakdown) = breakdown {
+            let conn = self.pool.get().await.map_err(DbError::from)?;
+
+            for discount_amount in breakdown.discounts {
+                Self::put_discount(
+                    &conn,
+                    &discount_amount.discount,
+                    stripe_subscription_id.as_ref(),
+                    Some(&stripe_checkout_session_id),
+                    checkout_session.created,
+                )
+                .await?;
+
+                DiscountAmount::put(
+                    &conn,
+                    &discount_amount.discount.id.to_string(),
+                    &stripe_checkout_session_id,
+                    discount_amount.amount,
+                )
+                .await?;
             }
         }
 
+        Ok(())
+    }
+
+    async fn handle_coupon(
+        &self,
+        coupon: StripeCoupon,
+        event_type: EventType,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let conn = self.pool.get().await.map_err(DbError::from)?;
+
+        Self::put_coupon(
+            &conn,
+            &coupon,
+            event_type == EventType::CouponDeleted,
+            event_timestamp,
+        )
+        .await?;
+
         Ok(HttpResponse::Ok().finish())
     }
 
-    pub async fn handle_event(
+    async fn handle_promotion_code(
         &self,
-        event: Event,
+        promotion_code: StripePromotionCode,
+        event_timestamp: i64,
     ) -> Result<HttpResponse, HttpError> {
-        use stripe::EventType::*;
+        let conn = self.pool.get().await.map_err(DbError::from)?;
 
-        match event.type_ {
-            CheckoutSessionCompleted => {
-                if let EventObject::CheckoutSession(checkout_session) =
-                    event.data.object
-                {
-                    self.handle_checkout_session(checkout_session).await
-                } else {
-                    Err(Self::unexpected_object(&event))
-                }
-            }
-            CustomerSubscriptionResumed
-            | CustomerSubscriptionPendingUpdateExpired
-            | CustomerSubscriptionPendingUpdateApplied
-            | CustomerSubscriptionPaused
-            | CustomerSubscriptionDeleted
-            | CustomerSubscriptionTrialWillEnd
-            | CustomerSubscriptionCreated
-            | CustomerSubscriptionUpdated => {
-                if let EventObject::Subscription(subscription) =
-                    event.data.object
-                {
-                    self.handle_subscription(subscription, event.created).await
-                } else {
-                    Err(Self::unexpected_object(&event))
+        Self::put_promotion_code(&conn, &promotion_code, event_timestamp)
+            .await?;
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    async fn handle_checkout_session(
+        &self,
+        checkout_session: CheckoutSession,
+    ) -> Result<HttpResponse, HttpError> {
+        self.put_checkout_session_discounts(&checkout_session)
+            .await?;
+
+        if let Some(stripe_subscription) = &checkout_session.subscription {
+            let attribution = self
+                .resolve_attribution(
+                    &checkout_session,
+                    &stripe_subscription.id(),
+                )
+                .await;
+
+            if let Some(customer) = &checkout_session.customer {
+                let details = checkout_session.customer_details.clone();
+
+                self.put_customer(
+                    &customer.id().to_string(),
+                    attribution.buyer_user_id.clone(),
+                    details.as_ref().and_then(|d| d.email.clone()),
+                    details.as_ref().and_then(|d| d.name.clone()),
+                    details.as_ref().and_then(|d| d.phone.clone()),
+                    details
+                        .and_then(|d| d.address)
+                        .map(CustomerAddress::from)
+                        .unwrap_or_default(),
+                    checkout_session.created,
+                )
+                .await?;
+            }
+
+            if let Attribution {
+                buyer_user_id: Some(buyer_user_id),
+                offer_id: Some(offer_id),
+                shop_id: Some(shop_id),
+            } = attribution
+            {
+                let stripe_subscription_id =
+                    stripe_subscription.id().to_string();
+
+                let mut conn = self.pool.get().await.map_err(DbError::from)?;
+                let transaction =
+                    conn.transaction().await.map_err(DbError::from)?;
+
+                let updated_subscription = Subscription::put_checkout_session(
+                    &transaction,
+                    &stripe_subscription_id,
+                    &buyer_user_id,
+                    &offer_id,
+                    &shop_id,
+                    checkout_session.created,
+                )
+                .await?;
+
+                // A different buyer is only taken over if the checkout is
+                // newer than the one the subscription was last given, so
+                // redelivered checkouts cannot undo a transfer. Erased
+                // buyers are never restored.
+                match &updated_subscription.buyer_user_id {
+                    Some(user_id)
+                        if *user_id != buyer_user_id
+                            && !gdpr::is_pseudonym(user_id)
+                            && updated_subscription.buyer_event_timestamp
+                                < checkout_session.created =>
+                    {
+                        self.transfer_buyer(
+                            &transaction,
+                            &updated_subscription,
+                            &buyer_user_id,
+                            None,
+                            checkout_session.created,
+                        )
+                        .await?;
+                    }
+                    _ => {
+                        self.send_updated_subscription(
+                            &transaction,
+                            updated_subscription,
+                        )
+                        .await?;
+                    }
+                }
+
+                transaction.commit().await.map_err(DbError::from)?;
+            }
+        }
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    fn customer_response(customer: Customer) -> CustomerResponse {
+        CustomerResponse {
+            customer_id: customer.customer_id.to_string(),
+            stripe_customer_id: customer.stripe_customer_id,
+            buyer_user_id: customer.buyer_user_id,
+            email: customer.email,
+            name: customer.name,
+            phone: customer.phone,
+            address: Some(CustomerAddressResponse {
+                line1: customer.address.line1,
+                line2: customer.address.line2,
+                postal_code: customer.address.postal_code,
+                city: customer.address.city,
+                state: customer.address.state,
+                country: customer.address.country,
+            }),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn put_customer(
+        &self,
+        stripe_customer_id: &String,
+        buyer_user_id: Option<String>,
+        email: Option<String>,
+        name: Option<String>,
+        phone: Option<String>,
+        address: CustomerAddress,
+        event_timestamp: i64,
+    ) -> Result<(), HttpError> {
+        let updated_customer = Customer::put(
+            &self.pool,
+            stripe_customer_id,
+            buyer_user_id,
+            email,
+            name,
+            phone,
+            address,
+            event_timestamp,
+        )
+        .await?;
+
+        if let Some(updated_customer) = updated_customer {
+            self.publisher
+                .publish_customer_upsert(&Self::customer_response(
+                    updated_customer,
+                ))
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_customer(
+        &self,
+        customer: StripeCustomer,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let buyer_user_id = customer
+            .metadata
+            .as_ref()
+            .and_then(|m| self.metadata_mapping.get_user_id(m));
+
+        let stripe_customer_id = customer.id.to_string();
+        let default_payment_method_id = customer
+            .invoice_settings
+            .as_ref()
+            .and_then(|s| s.default_payment_method.as_ref())
+            .map(|p| p.id().to_string())
+            .or_else(|| {
+                customer.default_source.as_ref().map(|s| s.id().to_string())
+            });
+
+        self.put_customer(
+            &stripe_customer_id,
+            buyer_user_id,
+            customer.email,
+            customer.name,
+            customer.phone,
+            customer
+                .address
+                .map(CustomerAddress::from)
+                .unwrap_or_default(),
+            event_timestamp,
+        )
+        .await?;
+
+        Customer::put_default_payment_method(
+            &self.pool,
+            &stripe_customer_id,
+            default_payment_method_id,
+            event_timestamp,
+        )
+        .await?;
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    /// Stores brand, last digits and expiry of a payment method, so buyers
+    /// can be warned before their default card expires.
+    async fn handle_payment_method(
+        &self,
+        payment_method: StripePaymentMethod,
+        event_type: EventType,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let card = payment_method.card.as_ref();
+        let detached_at = (event_type == EventType::PaymentMethodDetached)
+            .then(|| DateTime::<Utc>::from_timestamp(event_timestamp, 0))
+            .flatten();
+
+        PaymentMethod::put(
+            &self.pool,
+            &payment_method.id.to_string(),
+            payment_method.customer.as_ref().map(|c| c.id().to_string()),
+            &payment_method.type_.to_string(),
+            card.map(|c| c.brand.clone()),
+            card.map(|c| c.last4.clone()),
+            card.map(|c| c.exp_month),
+            card.map(|c| c.exp_year),
+            detached_at,
+            event_timestamp,
+        )
+        .await?;
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    /// Stores a card attached to a customer as a legacy source, so it can be
+    /// resolved as the customer's default source like a payment method.
+    async fn handle_card_source(
+        &self,
+        card: StripeCard,
+        event_type: EventType,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let detached_at = (event_type == EventType::CustomerSourceDeleted)
+            .then(|| DateTime::<Utc>::from_timestamp(event_timestamp, 0))
+            .flatten();
+
+        PaymentMethod::put(
+            &self.pool,
+            &card.id.to_string(),
+            card.customer.as_ref().map(|c| c.id().to_string()),
+            &String::from("card"),
+            card.brand,
+            card.last4,
+            card.exp_month,
+            card.exp_year,
+            detached_at,
+            event_timestamp,
+        )
+        .await?;
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    /// Ends all subscriptions of a deleted Stripe customer, publishes their
+    /// deletion and removes the personal data stored for the customer.
+    async fn handle_customer_deleted(
+        &self,
+        customer: StripeCustomer,
+        stripe_event_id: String,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let stripe_customer_id = customer.id.to_string();
+        let ended_at = DateTime::<Utc>::from_timestamp(event_timestamp, 0)
+            .unwrap_or_else(Utc::now);
+
+        let mut conn = self.pool.get().await.map_err(DbError::from)?;
+        let transaction = conn.transaction().await.map_err(DbError::from)?;
+
+        let stored_customer =
+            Customer::get(&transaction, &stripe_customer_id).await?;
+
+        let subscriptions = Subscription::list_by_stripe_customer_id(
+            &transaction,
+            &stripe_customer_id,
+        )
+        .await?;
+
+        for subscription in subscriptions {
+            let ended_subscription = Subscription::end(
+                &transaction,
+                &subscription.subscription_id,
+                ended_at,
+            )
+            .await?;
+
+            SubscriptionEvent::record(
+                &transaction,
+                Some(&stripe_event_id),
+                Some(&subscription),
+                &ended_subscription,
+                event_timestamp,
+            )
+            .await?;
+
+            self.send_deleted_subscription(&transaction, ended_subscription)
+                .await?;
+        }
+
+        PaymentMethod::delete_by_stripe_customer_id(
+            &transaction,
+            &stripe_customer_id,
+        )
+        .await?;
+
+        // Keeps the record, so the customer stays linked to its
+        // subscriptions, but drops the contact data and the buyer.
+        let erased_customer = Customer::erase_by_stripe_customer_id(
+            &transaction,
+            &stripe_customer_id,
+        )
+        .await?;
+
+        transaction.commit().await.map_err(DbError::from)?;
+
+        if let Some(mut erased_customer) = erased_customer {
+            erased_customer.buyer_user_id =
+                stored_customer.and_then(|customer| customer.buyer_user_id);
+            self.publisher
+                .publish_customer_delete(&Self::customer_response(
+                    erased_customer,
+                ))
+                .await;
+        }
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    /// Deletes all customer records of the buyer, e.g. on a GDPR deletion
+    /// request, and publishes their deletion.
+    pub async fn delete_customers(
+        &self,
+        buyer_user_id: &String,
+    ) -> Result<(), HttpError> {
+        let deleted_customers =
+            Customer::delete_by_buyer_user_id(&self.pool, buyer_user_id)
+                .await?;
+
+        for mut customer in deleted_customers {
+            // The erased customer no longer knows its buyer, but the delete
+            // is for the buyer.
+            customer.buyer_user_id = Some(buyer_user_id.clone());
+
+            self.publisher
+                .publish_customer_delete(&Self::customer_response(customer))
+                .await;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the subscription to another buyer. Access of the previous buyer
+    /// is revoked by publishing a delete of the subscription as it was, then
+    /// the subscription is published for the new buyer.
+    async fn transfer_buyer<'a>(
+        &self,
+        transaction: &Transaction<'a>,
+        subscription: &Subscription,
+        buyer_user_id: &String,
+        stripe_event_id: Option<&String>,
+        event_timestamp: i64,
+    ) -> Result<Subscription, HttpError> {
+        let previous_media_subscription =
+            Self::media_subscription(transaction, subscription.clone()).await?;
+
+        let transferred_subscription = Subscription::update_buyer_user_id(
+            transaction,
+            &subscription.stripe_subscription_id,
+            buyer_user_id,
+            event_timestamp,
+        )
+        .await?;
+
+        SubscriptionEvent::record(
+            transaction,
+            stripe_event_id,
+            Some(subscription),
+            &transferred_subscription,
+            event_timestamp,
+        )
+        .await?;
+
+        if let Some(previous_media_subscription) = previous_media_subscription {
+            self.publisher
+                .publish_subscription_delete(&previous_media_subscription)
+                .await;
+        }
+
+        self.send_updated_subscription(
+            transaction,
+            transferred_subscription.clone(),
+        )
+        .await?;
+
+        tracing::info!(
+            "[EventService.transfer_buyer] Transferred subscription {} from {:?} to {buyer_user_id}",
+            subscription.stripe_subscription_id,
+            subscription.buyer_user_id
+        );
+
+        Ok(transferred_subscription)
+    }
+
+    /// Pseudonymizes the buyer and republishes the subscriptions, so they are
+    /// deleted for the buyer and kept under the pseudonym.
+    pub async fn erase_buyer(
+        &self,
+        buyer_user_id: &String,
+    ) -> Result<gdpr::Pseudonymization, HttpError> {
+        let mut conn = self.pool.get().await.map_err(DbError::from)?;
+
+        let subscriptions =
+            Subscription::list_by_buyer_user_id(&self.pool, buyer_user_id)
+                .await?;
+
+        let mut previous_media_subscriptions = Vec::new();
+
+        for subscription in subscriptions.iter() {
+            previous_media_subscriptions.extend(
+                Self::media_subscription(&conn, subscription.clone()).await?,
+            );
+        }
+
+        let pseudonymization =
+            gdpr::BuyerData::pseudonymize(&self.pool, buyer_user_id).await?;
+
+        for previous_media_subscription in previous_media_subscriptions {
+            self.publisher
+                .publish_subscription_delete(&previous_media_subscription)
+                .await;
+        }
+
+        let transaction = conn.transaction().await.map_err(DbError::from)?;
+
+        for subscription in subscriptions {
+            let pseudonymized_subscription = Subscription::get(
+                &transaction,
+                &subscription.stripe_subscription_id,
+            )
+            .await?;
+
+            if let Some(pseudonymized_subscription) = pseudonymized_subscription
+            {
+                self.send_updated_subscription(
+                    &transaction,
+                    pseudonymized_subscription,
+                )
+                .await?;
+            }
+        }
+
+        transaction.commit().await.map_err(DbError::from)?;
+
+        Ok(pseudonymization)
+    }
+
+    /// Writes the buyer to the metadata of the Stripe subscription, so later
+    /// events of the subscription carry the new buyer.
+    async fn update_subscription_user_id(
+        &self,
+        stripe_subscription_id: &String,
+        buyer_user_id: &str,
+    ) -> Result<(), HttpError> {
+        let Some(user_id_key) = self.metadata_mapping.user_id_key() else {
+            return Ok(());
+        };
+
+        let stripe_subscription_id: SubscriptionId =
+            stripe_subscription_id.parse().map_err(|_| {
+                HttpError::from_message(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "invalid subscription id '{stripe_subscription_id}'"
+                    ),
+                )
+            })?;
+
+        let mut params = UpdateSubscription::new();
+        params.metadata = Some(Metadata::from([(
+            user_id_key.clone(),
+            buyer_user_id.to_string(),
+        )]));
+
+        StripeSubscription::update(
+            self.stripe_client()?,
+            &stripe_subscription_id,
+            params,
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "[EventService.update_subscription_user_id] {stripe_subscription_id}: {err}"
+            );
+            HttpError::from_message(
+                StatusCode::BAD_GATEWAY,
+                format!(
+                    "failed to update subscription '{stripe_subscription_id}'"
+                ),
+            )
+        })?;
+
+        Ok(())
+    }
+
+    /// Transfers the subscription to `buyer_user_id`, e.g. on request of
+    /// support. The buyer is also written to the Stripe subscription, as the
+    /// next event of the subscription would transfer it back otherwise.
+    pub async fn transfer_subscription(
+        &self,
+        stripe_subscription_id: &String,
+        buyer_user_id: &String,
+    ) -> Result<(), HttpError> {
+        let mut conn = self.pool.get().await.map_err(DbError::from)?;
+        let transaction = conn.transaction().await.map_err(DbError::from)?;
+
+        let subscription =
+            Subscription::get(&transaction, stripe_subscription_id)
+                .await?
+                .ok_or_else(|| {
+                    HttpError::from_message(
+                        StatusCode::NOT_FOUND,
+                        format!(
+                            "subscription '{stripe_subscription_id}' not found"
+                        ),
+                    )
+                })?;
+
+        if subscription.buyer_user_id.as_ref() != Some(buyer_user_id) {
+            self.update_subscription_user_id(
+                stripe_subscription_id,
+                buyer_user_id,
+            )
+            .await?;
+
+            self.transfer_buyer(
+                &transaction,
+                &subscription,
+                buyer_user_id,
+                None,
+                Utc::now().timestamp(),
+            )
+            .await?;
+        }
+
+        transaction.commit().await.map_err(DbError::from)?;
+
+        Ok(())
+    }
+
+    /// Sums up the amounts of all priced items of the subscription, which is
+    /// what the buyer is charged per interval before discounts and taxes.
+    fn subscription_amount(subscription: &StripeSubscription) -> Option<i64> {
+        subscription
+            .items
+            .data
+            .iter()
+            .filter_map(|item| {
+                let unit_amount = item.price.as_ref()?.unit_amount?;
+                let quantity =
+                    i64::try_from(item.quantity.unwrap_or(1)).ok()?;
+                Some(unit_amount * quantity)
+            })
+            .reduce(|a, b| a + b)
+    }
+
+    async fn put_subscription_items<'a>(
+        transaction: &Transaction<'a>,
+        subscription_id: &Uuid,
+        subscription: &StripeSubscription,
+    ) -> Result<(), HttpError> {
+        let mut stripe_subscription_item_ids = Vec::new();
+
+        for item in subscription.items.data.iter() {
+            let Some(price) = &item.price else {
+                continue;
+            };
+
+            let stripe_subscription_item_id = item.id.to_string();
+
+            SubscriptionItem::put(
+                transaction,
+                subscription_id,
+                &stripe_subscription_item_id,
+                &price.id.to_string(),
+                price.product.as_ref().map(|p| p.id().to_string()),
+                item.quantity.map(|q| q.try_into().unwrap()),
+                price.unit_amount,
+                price.currency.map(|c| c.to_string()),
+                price.recurring.as_ref().map(|r| r.usage_type.to_string()),
+            )
+            .await?;
+
+            stripe_subscription_item_ids.push(stripe_subscription_item_id);
+        }
+
+        SubscriptionItem::delete_except(
+            transaction,
+            subscription_id,
+            &stripe_subscription_item_ids,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the pending update of the subscription, if any, i.e. changes
+    /// that wait for their invoice to be paid.
+    async fn put_pending_update<'a>(
+        transaction: &Transaction<'a>,
+        subscription: &StripeSubscription,
+        event_timestamp: i64,
+    ) -> Result<(), HttpError> {
+        let Some(pending_update) = &subscription.pending_update else {
+            return Ok(());
+        };
+
+        let items = pending_update
+            .subscription_items
+            .iter()
+            .flatten()
+            .map(|item| PendingUpdateItem {
+                stripe_subscription_item_id: item.id.to_string(),
+                price_id: item.price.as_ref().map(|p| p.id.to_string()),
+                quantity: item.quantity.map(|q| q.try_into().unwrap()),
+                unit_amount: item.price.as_ref().and_then(|p| p.unit_amount),
+            })
+            .collect();
+
+        PendingUpdate::put(
+            transaction,
+            &subscription.id.to_string(),
+            DateTime::<Utc>::from_timestamp(pending_update.expires_at, 0)
+                .unwrap(),
+            pending_update
+                .billing_cycle_anchor
+                .and_then(|b| DateTime::<Utc>::from_timestamp(b, 0)),
+            pending_update
+                .trial_end
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+            pending_update.trial_from_plan,
+            items,
+            event_timestamp,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records that the pending updates of the subscription were applied or
+    /// expired, so support can tell why a change did not take effect.
+    async fn resolve_pending_updates(
+        &self,
+        subscription: &StripeSubscription,
+        event_type: EventType,
+        stripe_event_id: &String,
+        event_timestamp: i64,
+    ) -> Result<(), HttpError> {
+        let update_status = if event_type
+            == EventType::CustomerSubscriptionPendingUpdateApplied
+        {
+            PendingUpdate::STATUS_APPLIED
+        } else {
+            PendingUpdate::STATUS_EXPIRED
+        };
+
+        let resolved = PendingUpdate::resolve(
+            &self.pool,
+            &subscription.id.to_string(),
+            update_status,
+            stripe_event_id,
+            event_timestamp,
+        )
+        .await?;
+
+        for pending_update in resolved {
+            tracing::info!(
+                "[EventService.resolve_pending_updates] {}: pending update {} {update_status}",
+                pending_update.stripe_subscription_id,
+                pending_update.pending_update_id,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns price and quantity of the items listed in the previous
+    /// attributes of a `customer.subscription.updated` event. These are only
+    /// present if the items changed.
+    fn previous_plan_items(
+        previous_attributes: &HashMap<String, Value>,
+    ) -> Option<BTreeMap<String, PlanItem>> {
+        let items =
+            previous_attributes.get("items")?.get("data")?.as_array()?;
+
+        Some(
+            items
+                .iter()
+                .filter_map(|item| {
+                    let price = item.get("price")?;
+
+                    Some((
+                        item.get("id")?.as_str()?.to_string(),
+                        PlanItem {
+                            price_id: price.get("id")?.as_str()?.to_string(),
+                            quantity: item
+                                .get("quantity")
+                                .and_then(Value::as_i64),
+                            unit_amount: price
+                                .get("unit_amount")
+                                .and_then(Value::as_i64),
+                        },
+                    ))
+                })
+                .collect(),
+        )
+    }
+
+    fn plan_items(
+        subscription: &StripeSubscription,
+    ) -> BTreeMap<String, PlanItem> {
+        subscription
+            .items
+            .data
+            .iter()
+            .filter_map(|item| {
+                let price = item.price.as_ref()?;
+
+                Some((
+                    item.id.to_string(),
+                    PlanItem {
+                        price_id: price.id.to_string(),
+                        quantity: item.quantity.map(|q| q.try_into().unwrap()),
+                        unit_amount: price.unit_amount,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Records a plan change for every item whose price or quantity differs
+    /// from the previous attributes of the event.
+    async fn record_plan_changes<'a>(
+        transaction: &Transaction<'a>,
+        stripe_event_id: &String,
+        subscription: &StripeSubscription,
+        previous_attributes: &HashMap<String, Value>,
+        event_timestamp: i64,
+    ) -> Result<Vec<PlanChange>, HttpError> {
+        let Some(old_items) = Self::previous_plan_items(previous_attributes)
+        else {
+            return Ok(Vec::new());
+        };
+
+        let new_items = Self::plan_items(subscription);
+        let stripe_subscription_item_ids: BTreeSet<&String> =
+            old_items.keys().chain(new_items.keys()).collect();
+
+        let mut plan_changes = Vec::new();
+
+        for stripe_subscription_item_id in stripe_subscription_item_ids {
+            let old = old_items.get(stripe_subscription_item_id);
+            let new = new_items.get(stripe_subscription_item_id);
+
+            if old == new {
+                continue;
+            }
+
+            let plan_change = PlanChange::record(
+                transaction,
+                stripe_event_id,
+                &subscription.id.to_string(),
+                stripe_subscription_item_id,
+                old,
+                new,
+                Some(subscription.currency.to_string()),
+                DateTime::<Utc>::from_timestamp(event_timestamp, 0).unwrap(),
+            )
+            .await?;
+
+            // The invoice with the proration lines of the change may have
+            // arrived before the event.
+            if plan_change.is_some() {
+                if let Some((stripe_invoice_id, proration_amount)) =
+                    InvoiceLine::get_unattributed_proration(
+                        transaction,
+                        stripe_subscription_item_id,
+                    )
+                    .await?
+                {
+                    PlanChange::put_proration(
+                        transaction,
+                        stripe_subscription_item_id,
+                        &stripe_invoice_id,
+                        proration_amount,
+                    )
+                    .await?;
+                }
+            }
+
+            plan_changes.extend(plan_change);
+        }
+
+        Ok(plan_changes)
+    }
+
+    fn plan_changed_response(
+        plan_change: PlanChange,
+        subscription: Option<MediaSubscriptionResponse>,
+    ) -> PlanChangedResponse {
+        PlanChangedResponse {
+            plan_change_id: plan_change.plan_change_id.to_string(),
+            stripe_subscription_item_id: plan_change
+                .stripe_subscription_item_id,
+            old_price_id: plan_change.old_price_id,
+            new_price_id: plan_change.new_price_id,
+            old_quantity: plan_change
+                .old_quantity
+                .map(|q| q.try_into().unwrap()),
+            new_quantity: plan_change
+                .new_quantity
+                .map(|q| q.try_into().unwrap()),
+            old_unit_amount: plan_change.old_unit_amount,
+            new_unit_amount: plan_change.new_unit_amount,
+            currency: plan_change.currency,
+            changed_at: plan_change.changed_at.timestamp().try_into().unwrap(),
+            subscription,
+        }
+    }
+
+    async fn handle_subscription(
+        &self,
+        subscription: StripeSubscription,
+        stripe_event_id: Option<String>,
+        previous_attributes: Option<&HashMap<String, Value>>,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let stripe_subscription_id = subscription.id.to_string();
+
+        let discounts = self.subscription_discounts(&subscription).await?;
+
+        let mut conn = self.pool.get().await.map_err(DbError::from)?;
+        let transaction = conn.transaction().await.map_err(DbError::from)?;
+
+        let mut found_subscription =
+            Subscription::get(&transaction, &stripe_subscription_id).await?;
+
+        let mut update = false;
+
+        match &mut found_subscription {
+            Some(found_subscription) => {
+                if found_subscription.event_timestamp < event_timestamp {
+                    update = true;
+                }
+
+                // The buyer is only taken from events newer than the one it
+                // was last set from, so replayed events cannot undo a
+                // transfer.
+                let metadata_user_id = self
+                    .metadata_mapping
+                    .get_user_id(&subscription.metadata)
+                    .filter(|_| {
+                        found_subscription.buyer_event_timestamp
+                            < event_timestamp
+                    });
+
+                if let Some(metadata_user_id) = metadata_user_id {
+                    match &found_subscription.buyer_user_id {
+                        Some(user_id)
+                            if *user_id == metadata_user_id
+                                || gdpr::is_pseudonym(user_id) => {}
+                        Some(_) => {
+                            *found_subscription = self
+                                .transfer_buyer(
+                                    &transaction,
+                                    found_subscription,
+                                    &metadata_user_id,
+                                    stripe_event_id.as_ref(),
+                                    event_timestamp,
+                                )
+                                .await?;
+                        }
+                        None => {
+                            let updated_subscription =
+                                Subscription::update_buyer_user_id(
+                                    &transaction,
+                                    &stripe_subscription_id,
+                                    &metadata_user_id,
+                                    event_timestamp,
+                                )
+                                .await?;
+
+                            SubscriptionEvent::record(
+                                &transaction,
+                                stripe_event_id.as_ref(),
+                                Some(&*found_subscription),
+                                &updated_subscription,
+                                event_timestamp,
+                            )
+                            .await?;
+
+                            *found_subscription = updated_subscription;
+                        }
+                    }
+                }
+            }
+            None => {
+                update = true;
+            }
+        }
+
+        if update {
+            let current_period_start = DateTime::<Utc>::from_timestamp(
+                subscription.current_period_start,
+                0,
+            )
+            .unwrap();
+
+            let current_period_end = DateTime::<Utc>::from_timestamp(
+                subscription.current_period_end,
+                0,
+            )
+            .unwrap();
+
+            let canceled_at = subscription
+                .canceled_at
+                .and_then(|c| DateTime::<Utc>::from_timestamp(c, 0));
+
+            let cancel_at = subscription
+                .cancel_at
+                .and_then(|c| DateTime::<Utc>::from_timestamp(c, 0));
+
+            let recurring = subscription
+                .items
+                .data
+                .iter()
+                .find_map(|item| item.price.as_ref()?.recurring.as_ref());
+
+            let updated_subscription = Subscription::put_subscription(
+                &transaction,
+                &stripe_subscription_id,
+                &current_period_start,
+                &current_period_end,
+                subscription.status.into(),
+                canceled_at,
+                cancel_at,
+                event_timestamp,
+                Self::subscription_amount(&subscription),
+                &subscription.currency.to_string(),
+                recurring.map(|r| r.interval.to_string()),
+                recurring.map(|r| r.interval_count.try_into().unwrap()),
+                subscription
+                    .trial_start
+                    .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+                subscription
+                    .trial_end
+                    .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+                &subscription.customer.id().to_string(),
+                subscription
+                    .pause_collection
+                    .as_ref()
+                    .map(|p| p.behavior.to_string()),
+                subscription
+                    .pause_collection
+                    .as_ref()
+                    .and_then(|p| p.resumes_at)
+                    .and_then(|r| DateTime::<Utc>::from_timestamp(r, 0)),
+            )
+            .await?;
+
+            Self::put_subscription_items(
+                &transaction,
+                &updated_subscription.subscription_id,
+                &subscription,
+            )
+            .await?;
+
+            Self::put_pending_update(
+                &transaction,
+                &subscription,
+                event_timestamp,
+            )
+            .await?;
+
+            SubscriptionEvent::record(
+                &transaction,
+                stripe_event_id.as_ref(),
+                found_subscription.as_ref(),
+                &updated_subscription,
+                event_timestamp,
+            )
+            .await?;
+
+            let plan_changes = match (&stripe_event_id, previous_attributes) {
+                (Some(stripe_event_id), Some(previous_attributes)) => {
+                    Self::record_plan_changes(
+                        &transaction,
+                        stripe_event_id,
+                        &subscription,
+                        previous_attributes,
+                        event_timestamp,
+                    )
+                    .await?
+                }
+                _ => Vec::new(),
+            };
+
+            let media_subscription = self
+                .send_updated_subscription(&transaction, updated_subscription)
+                .await?;
+
+            for plan_change in plan_changes {
+                self.publisher
+                    .publish_plan_changed(&Self::plan_changed_response(
+                        plan_change,
+                        media_subscription.clone(),
+                    ))
+                    .await;
+            }
+
+            for discount in &discounts {
+                Self::put_discount(
+                    &transaction,
+                    discount,
+                    Some(&stripe_subscription_id),
+                    None,
+                    event_timestamp,
+                )
+                .await?;
+            }
+        }
+
+        transaction.commit().await.map_err(DbError::from)?;
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    fn schedule_phase_response(
+        phase: SubscriptionSchedulePhase,
+    ) -> SubscriptionSchedulePhaseResponse {
+        SubscriptionSchedulePhaseResponse {
+            start_at: phase.start_at.timestamp().try_into().unwrap(),
+            end_at: phase.end_at.timestamp().try_into().unwrap(),
+            currency: phase.currency,
+            trial_end: phase
+                .trial_end
+                .map(|t| t.timestamp().try_into().unwrap()),
+            items: phase
+                .items
+                .into_iter()
+                .map(|item| SubscriptionSchedulePhaseItemResponse {
+                    price_id: item.price_id,
+                    quantity: item.quantity.map(|q| q.try_into().unwrap()),
+                })
+                .collect(),
+        }
+    }
+
+    fn schedule_phases(
+        schedule: &StripeSubscriptionSchedule,
+    ) -> Vec<SubscriptionSchedulePhase> {
+        let stripe_subscription_schedule_id = schedule.id.to_string();
+
+        schedule
+            .phases
+            .iter()
+            .enumerate()
+            .map(|(index, phase)| SubscriptionSchedulePhase {
+                stripe_subscription_schedule_id:
+                    stripe_subscription_schedule_id.clone(),
+                phase_index: index.try_into().unwrap(),
+                start_at: DateTime::<Utc>::from_timestamp(phase.start_date, 0)
+                    .unwrap(),
+                end_at: DateTime::<Utc>::from_timestamp(phase.end_date, 0)
+                    .unwrap(),
+                currency: phase.currency.to_string(),
+                trial_end: phase
+                    .trial_end
+                    .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+                items: phase
+                    .items
+                    .iter()
+                    .map(|item| SubscriptionSchedulePhaseItem {
+                        price_id: item.price.id().to_string(),
+                        quantity: item.quantity.map(|q| q.try_into().unwrap()),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Stores the schedule and its phases. When the schedule entered another
+    /// phase, the new and the following phase are published, so shops can
+    /// announce upcoming price changes.
+    async fn handle_subscription_schedule(
+        &self,
+        schedule: StripeSubscriptionSchedule,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let stripe_subscription_schedule_id = schedule.id.to_string();
+        // Released schedules no longer reference their subscription.
+        let stripe_subscription_id = schedule
+            .subscription
+            .as_ref()
+            .map(|s| s.id().to_string())
+            .or_else(|| schedule.released_subscription.clone());
+        let current_phase_start = schedule
+            .current_phase
+            .as_ref()
+            .and_then(|p| DateTime::<Utc>::from_timestamp(p.start_date, 0));
+
+        let mut conn = self.pool.get().await.map_err(DbError::from)?;
+        let transaction = conn.transaction().await.map_err(DbError::from)?;
+
+        let found_schedule = SubscriptionSchedule::get(
+            &transaction,
+            &stripe_subscription_schedule_id,
+        )
+        .await?;
+
+        let Some(updated_schedule) = SubscriptionSchedule::put(
+            &transaction,
+            &stripe_subscription_schedule_id,
+            stripe_subscription_id.clone(),
+            &schedule.status.to_string(),
+            &schedule.end_behavior.to_string(),
+            current_phase_start,
+            schedule
+                .current_phase
+                .as_ref()
+                .and_then(|p| DateTime::<Utc>::from_timestamp(p.end_date, 0)),
+            schedule
+                .canceled_at
+                .and_then(|c| DateTime::<Utc>::from_timestamp(c, 0)),
+            schedule
+                .completed_at
+                .and_then(|c| DateTime::<Utc>::from_timestamp(c, 0)),
+            schedule
+                .released_at
+                .and_then(|r| DateTime::<Utc>::from_timestamp(r, 0)),
+            event_timestamp,
+        )
+        .await?
+        else {
+            return Ok(HttpResponse::Ok().finish());
+        };
+
+        let phases = Self::schedule_phases(&schedule);
+
+        SubscriptionSchedulePhase::replace(
+            &transaction,
+            &stripe_subscription_schedule_id,
+            &phases,
+        )
+        .await?;
+
+        let phase_changed = current_phase_start.is_some()
+            && found_schedule.map(|s| s.current_phase_start)
+                != Some(current_phase_start);
+
+        if phase_changed {
+            let mut phases = phases
+                .into_iter()
+                .skip_while(|p| Some(p.start_at) != current_phase_start);
+
+            let subscription = match &updated_schedule.stripe_subscription_id {
+                Some(stripe_subscription_id) => {
+                    Subscription::get(&transaction, stripe_subscription_id)
+                        .await?
+                }
+                None => None,
+            };
+
+            let subscription = match subscription {
+                Some(subscription) => {
+                    Self::media_subscription(&transaction, subscription).await?
+                }
+                None => None,
+            };
+
+            self.publisher
+                .publish_schedule_phase_changed(&SchedulePhaseChangedResponse {
+                    stripe_subscription_schedule_id,
+                    stripe_subscription_id: updated_schedule
+                        .stripe_subscription_id,
+                    current_phase: phases
+                        .next()
+                        .map(Self::schedule_phase_response),
+                    next_phase: phases
+                        .next()
+                        .map(Self::schedule_phase_response),
+                    subscription,
+                })
+                .await;
+        }
+
+        transaction.commit().await.map_err(DbError::from)?;
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    fn invoice_response(invoice: Invoice) -> InvoiceResponse {
+        InvoiceResponse {
+            invoice_id: invoice.invoice_id.to_string(),
+            stripe_invoice_id: invoice.stripe_invoice_id,
+            stripe_subscription_id: invoice.stripe_subscription_id,
+            amount_due: invoice.amount_due,
+            amount_paid: invoice.amount_paid,
+            currency: invoice.currency,
+            tax: invoice.tax,
+            hosted_invoice_url: invoice.hosted_invoice_url,
+            invoice_pdf_url: invoice.invoice_pdf_url,
+            invoice_status: invoice.invoice_status,
+            period_start: invoice
+                .period_start
+                .map(|t| t.timestamp().try_into().unwrap()),
+            period_end: invoice
+                .period_end
+                .map(|t| t.timestamp().try_into().unwrap()),
+        }
+    }
+
+    async fn put_invoice(
+        &self,
+        invoice: &StripeInvoice,
+        event_type: EventType,
+        event_timestamp: i64,
+    ) -> Result<(), HttpError> {
+        let stripe_invoice_id = invoice.id.to_string();
+
+        if event_type == EventType::InvoiceDeleted {
+            Invoice::delete(&self.pool, &stripe_invoice_id).await?;
+            return Ok(());
+        }
+
+        let updated_invoice = Invoice::put(
+            &self.pool,
+            &stripe_invoice_id,
+            invoice.subscription.as_ref().map(|s| s.id().to_string()),
+            invoice.amount_due,
+            invoice.amount_paid,
+            invoice.currency.map(|c| c.to_string()),
+            invoice.tax,
+            invoice.hosted_invoice_url.clone(),
+            invoice.invoice_pdf.clone(),
+            invoice.status.map(|s| s.to_string()),
+            invoice
+                .period_start
+                .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+            invoice
+                .period_end
+                .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+            invoice
+                .status_transitions
+                .as_ref()
+                .and_then(|t| t.finalized_at)
+                .or(invoice.created)
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+            invoice
+                .customer_address
+                .as_ref()
+                .and_then(|a| a.country.clone()),
+            invoice.customer_tax_exempt.map(|t| t.to_string()),
+            event_timestamp,
+        )
+        .await?;
+
+        if updated_invoice.is_some() {
+            self.put_invoice_taxes(invoice, &stripe_invoice_id).await?;
+            self.put_invoice_lines(invoice, &stripe_invoice_id).await?;
+        }
+
+        let conn = self.pool.get().await.map_err(DbError::from)?;
+
+        for discount_amount in invoice.total_discount_amounts.iter().flatten() {
+            if let Some(discount) = discount_amount.discount.as_object() {
+                Self::put_discount(
+                    &conn,
+                    discount,
+                    invoice
+                        .subscription
+                        .as_ref()
+                        .map(|s| s.id().to_string())
+                        .as_ref(),
+                    None,
+                    event_timestamp,
+                )
+                .await?;
+            }
+
+            DiscountAmount::put(
+                &conn,
+                &discount_amount.discount.id().to_string(),
+                &stripe_invoice_id,
+                discount_amount.amount,
+            )
+            .await?;
+        }
+
+        if let Some(updated_invoice) = updated_invoice {
+            let invoice_response = Self::invoice_response(updated_invoice);
+
+            match event_type {
+                EventType::InvoiceFinalized => {
+                    self.publisher
+                        .publish_invoice_finalized(&invoice_response)
+                        .await
+                }
+                EventType::InvoicePaid => {
+                    self.publisher.publish_invoice_paid(&invoice_response).await
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stores tax amounts and customer tax ids of the invoice. Tax rates are
+    /// usually not expanded in events, so they are retrieved from Stripe.
+    async fn put_invoice_taxes(
+        &self,
+        invoice: &StripeInvoice,
+        stripe_invoice_id: &String,
+    ) -> Result<(), HttpError> {
+        let expanded_invoice;
+        let mut invoice = invoice;
+
+        if invoice
+            .total_tax_amounts
+            .iter()
+            .flatten()
+            .any(|t| t.tax_rate.as_object().is_none())
+        {
+            expanded_invoice = self.retrieve_invoice_tax_rates(invoice).await?;
+            invoice = &expanded_invoice;
+        }
+
+        let mut tax_amounts = Vec::new();
+
+        for tax_amount in invoice.total_tax_amounts.iter().flatten() {
+            let tax_rate = tax_amount.tax_rate.as_object().ok_or_else(|| {
+                tracing::error!(
+                    "[EventService.put_invoice_taxes] {}: tax rate {} not expanded",
+                    invoice.id,
+                    tax_amount.tax_rate.id()
+                );
+                HttpError::internal()
+            })?;
+
+            tax_amounts.push(InvoiceTaxAmount {
+                stripe_tax_rate_id: tax_rate.id.to_string(),
+                amount: tax_amount.amount,
+                taxable_amount: tax_amount.taxable_amount,
+                inclusive: tax_amount.inclusive,
+                taxability_reason: tax_amount
+                    .taxability_reason
+                    .map(|r| r.to_string()),
+                display_name: Some(tax_rate.display_name.clone()),
+                percentage: Some(tax_rate.percentage),
+                country: tax_rate.country.clone(),
+                state: tax_rate.state.clone(),
+                jurisdiction: tax_rate.jurisdiction.clone(),
+                tax_type: tax_rate.tax_type.map(|t| t.to_string()),
+            });
+        }
+
+        let tax_ids: Vec<InvoiceTaxId> = invoice
+            .customer_tax_ids
+            .iter()
+            .flatten()
+            .filter_map(|tax_id| {
+                Some(InvoiceTaxId {
+                    tax_id_type: tax_id.type_.to_string(),
+                    value: tax_id.value.clone()?,
+                })
+            })
+            .collect();
+
+        let mut conn = self.pool.get().await.map_err(DbError::from)?;
+        let transaction = conn.transaction().await.map_err(DbError::from)?;
+
+        InvoiceTaxAmount::replace(
+            &transaction,
+            stripe_invoice_id,
+            &tax_amounts,
+        )
+        .await?;
+        InvoiceTaxId::replace(&transaction, stripe_invoice_id, &tax_ids)
+            .await?;
+
+        transaction.commit().await.map_err(DbError::from)?;
+
+        Ok(())
+    }
+
+    fn invoice_lines(invoice: &StripeInvoice) -> Vec<InvoiceLine> {
+        let Some(lines) = &invoice.lines else {
+            return Vec::new();
+        };
+
+        if lines.has_more {
+            tracing::warn!(
+                "[EventService.invoice_lines] {}: only the first {} lines are stored",
+                invoice.id,
+                lines.data.len()
+            );
+        }
+
+        lines
+            .data
+            .iter()
+            .map(|line| InvoiceLine {
+                stripe_invoice_line_id: line.id.to_string(),
+                stripe_subscription_id: line
+                    .subscription
+                    .as_ref()
+                    .map(|s| s.id().to_string()),
+                stripe_subscription_item_id: line
+                    .subscription_item
+                    .as_ref()
+                    .map(|s| s.id().to_string()),
+                price_id: line.price.as_ref().map(|p| p.id.to_string()),
+                usage_type: line
+                    .price
+                    .as_ref()
+                    .and_then(|p| p.recurring.as_ref())
+                    .map(|r| r.usage_type.to_string()),
+                quantity: line.quantity.map(|q| q.try_into().unwrap()),
+                amount: line.amount,
+                currency: line.currency.to_string(),
+                proration: line.proration,
+                period_start: line
+                    .period
+                    .as_ref()
+                    .and_then(|p| p.start)
+                    .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+                period_end: line
+                    .period
+                    .as_ref()
+                    .and_then(|p| p.end)
+                    .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+            })
+            .collect()
+    }
+
+    /// Returns the subscription items of the invoice that are billed by
+    /// usage.
+    fn metered_subscription_item_ids(invoice: &StripeInvoice) -> Vec<String> {
+        let mut stripe_subscription_item_ids: Vec<String> = invoice
+            .lines
+            .iter()
+            .flat_map(|lines| lines.data.iter())
+            .filter(|line| {
+                line.price
+                    .as_ref()
+                    .and_then(|p| p.recurring.as_ref())
+                    .is_some_and(|r| {
+                        r.usage_type == RecurringUsageType::Metered
+                    })
+            })
+            .filter_map(|line| line.subscription_item.as_ref())
+            .map(|item| item.id().to_string())
+            .collect();
+
+        stripe_subscription_item_ids.sort();
+        stripe_subscription_item_ids.dedup();
+
+        stripe_subscription_item_ids
+    }
+
+    async fn put_invoice_lines(
+        &self,
+        invoice: &StripeInvoice,
+        stripe_invoice_id: &String,
+    ) -> Result<(), HttpError> {
+        let lines = Self::invoice_lines(invoice);
+
+        let mut conn = self.pool.get().await.map_err(DbError::from)?;
+        let transaction = conn.transaction().await.map_err(DbError::from)?;
+
+        InvoiceLine::replace(&transaction, stripe_invoice_id, &lines).await?;
+
+        let mut prorations = BTreeMap::<&String, i64>::new();
+
+        for line in lines.iter().filter(|line| line.proration) {
+            if let Some(stripe_subscription_item_id) =
+                &line.stripe_subscription_item_id
+            {
+                *prorations.entry(stripe_subscription_item_id).or_default() +=
+                    line.amount;
+            }
+        }
+
+        for (stripe_subscription_item_id, proration_amount) in prorations {
+            PlanChange::put_proration(
+                &transaction,
+                stripe_subscription_item_id,
+                stripe_invoice_id,
+                proration_amount,
+            )
+            .await?;
+        }
+
+        transaction.commit().await.map_err(DbError::from)?;
+
+        for stripe_subscription_item_id in
+            Self::metered_subscription_item_ids(invoice)
+        {
+            self.put_usage_records(&stripe_subscription_item_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores the usage of a metered subscription item per billing period,
+    /// including the running total of the current period.
+    async fn put_usage_records(
+        &self,
+        stripe_subscription_item_id: &String,
+    ) -> Result<(), HttpError> {
+        let Some(summaries) = self
+            .list_usage_record_summaries(stripe_subscription_item_id)
+            .await
+        else {
+            return Ok(());
+        };
+
+        for summary in summaries.data {
+            UsageRecord::put(
+                &self.pool,
+                &summary.id.to_string(),
+                stripe_subscription_item_id,
+                summary.invoice,
+                summary.total_usage,
+                summary
+                    .period
+                    .start
+                    .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+                summary
+                    .period
+                    .end
+                    .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores the projected charges of the next invoice of a subscription.
+    /// Upcoming invoices have no id, so they are kept per subscription.
+    async fn handle_upcoming_invoice(
+        &self,
+        invoice: StripeInvoice,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let Some(stripe_subscription) = &invoice.subscription else {
+            return Ok(HttpResponse::Ok().finish());
+        };
+
+        UpcomingInvoice::put(
+            &self.pool,
+            &stripe_subscription.id().to_string(),
+            invoice.amount_due,
+            invoice.subtotal,
+            invoice.tax,
+            invoice.total,
+            invoice.currency.map(|c| c.to_string()),
+            invoice
+                .period_start
+                .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+            invoice
+                .period_end
+                .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+            invoice
+                .next_payment_attempt
+                .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+            event_timestamp,
+        )
+        .await?;
+
+        for stripe_subscription_item_id in
+            Self::metered_subscription_item_ids(&invoice)
+        {
+            self.put_usage_records(&stripe_subscription_item_id).await?;
+        }
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    /// Records the state of a payment intent, e.g. to debug failed 3D Secure
+    /// authentications.
+    async fn handle_payment_intent(
+        &self,
+        payment_intent: StripePaymentIntent,
+        stripe_event_id: String,
+        event_type: EventType,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let error = payment_intent.last_payment_error.as_deref();
+
+        PaymentAttempt::record(
+            &self.pool,
+            &stripe_event_id,
+            &event_type.to_string(),
+            &payment_intent.id.to_string(),
+            PaymentAttempt::INTENT_TYPE_PAYMENT,
+            &payment_intent.status.to_string(),
+            payment_intent.invoice.as_ref().map(|i| i.id().to_string()),
+            payment_intent.customer.as_ref().map(|c| c.id().to_string()),
+            payment_intent
+                .payment_method
+                .as_ref()
+                .map(|p| p.id().to_string()),
+            Some(payment_intent.amount),
+            Some(payment_intent.currency.to_string()),
+            payment_intent.next_action.as_ref().map(|a| a.type_.clone()),
+            error.map(|e| e.type_.to_string()),
+            error.and_then(|e| e.code).map(|c| c.to_string()),
+            error.and_then(|e| e.decline_code.clone()),
+            error.and_then(|e| e.message.clone()),
+            event_timestamp,
+        )
+        .await?;
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    /// Records the state of a setup intent, i.e. the confirmation of a
+    /// payment method for later payments.
+    async fn handle_setup_intent(
+        &self,
+        setup_intent: StripeSetupIntent,
+        stripe_event_id: String,
+        event_type: EventType,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        let error = setup_intent.last_setup_error.as_deref();
+
+        PaymentAttempt::record(
+            &self.pool,
+            &stripe_event_id,
+            &event_type.to_string(),
+            &setup_intent.id.to_string(),
+            PaymentAttempt::INTENT_TYPE_SETUP,
+            &setup_intent.status.to_string(),
+            None,
+            setup_intent.customer.as_ref().map(|c| c.id().to_string()),
+            setup_intent
+                .payment_method
+                .as_ref()
+                .map(|p| p.id().to_string()),
+            None,
+            None,
+            setup_intent.next_action.as_ref().map(|a| a.type_.clone()),
+            error.map(|e| e.type_.to_string()),
+            error.and_then(|e| e.code).map(|c| c.to_string()),
+            error.and_then(|e| e.decline_code.clone()),
+            error.and_then(|e| e.message.clone()),
+            event_timestamp,
+        )
+        .await?;
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    async fn handle_invoice(
+        &self,
+        invoice: StripeInvoice,
+        event_type: EventType,
+        event_timestamp: i64,
+    ) -> Result<HttpResponse, HttpError> {
+        self.put_invoice(&invoice, event_type, event_timestamp)
+            .await?;
+
+        if event_type == EventType::InvoicePaid {
+            self.put_invoice_payment(invoice).await?;
+        }
+
+        Ok(HttpResponse::Ok().finish())
+    }
+
+    /// Groups the lines of an invoice by subscription and returns the period
+    /// paid for each of them. Proration lines only count for subscriptions
+    /// without a regular line, e.g. for an invoice created by an upgrade in
+    /// the middle of a period.
+    fn paid_periods(
+        invoice: &StripeInvoice,
+    ) -> BTreeMap<String, (DateTime<Utc>, DateTime<Utc>)> {
+        let mut periods = BTreeMap::<String, InvoicePeriods>::new();
+
+        for line in invoice.lines.iter().flat_map(|lines| lines.data.iter()) {
+            let (Some(stripe_subscription), Some(start), Some(end)) = (
+                &line.subscription,
+                line.period
+                    .as_ref()
+                    .and_then(|p| p.start)
+                    .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+                line.period
+                    .as_ref()
+                    .and_then(|p| p.end)
+                    .and_then(|p| DateTime::<Utc>::from_timestamp(p, 0)),
+            ) else {
+                continue;
+            };
+
+            periods
+                .entry(stripe_subscription.id().to_string())
+                .or_default()
+                .add(start, end, line.proration);
+        }
+
+        periods
+            .into_iter()
+            .filter_map(|(stripe_subscription_id, periods)| {
+                Some((stripe_subscription_id, periods.paid()?))
+            })
+            .collect()
+    }
+
+    /// Stores the periods covered by a paid invoice as paid periods of the
+    /// subscriptions it belongs to, publishing each subscription once.
+    async fn put_invoice_payment(
+        &self,
+        invoice: StripeInvoice,
+    ) -> Result<(), HttpError> {
+        for (stripe_subscription_id, (payed_at, payed_until)) in
+            Self::paid_periods(&invoice)
+        {
+            let updated_subscription = Subscription::put_invoice(
+                &self.pool,
+                &stripe_subscription_id,
+                &payed_at,
+                &payed_until,
+                invoice.created.unwrap_or(0),
+            )
+            .await?;
+
+            let conn = self.pool.get().await.map_err(DbError::from)?;
+            self.send_updated_subscription(&conn, updated_subscription)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the current state of a subscription, its checkout session and
+    /// its latest invoice from the Stripe API and processes them like the
+    /// corresponding webhook events. Used to complete subscriptions whose
+    /// events never arrived.
+    pub async fn reconcile_subscription(
+        &self,
+        stripe_subscription_id: &str,
+    ) -> Result<(), HttpError> {
+        let stripe_subscription_id: SubscriptionId =
+            stripe_subscription_id.parse().map_err(|_| {
+                HttpError::bad_request(format!(
+                    "invalid subscription id '{stripe_subscription_id}'"
+                ))
+            })?;
+
+        let stripe_client = self.stripe_client()?;
+
+        let subscription = self
+            .retrieve_subscription(&stripe_subscription_id)
+            .await
+            .ok_or_else(HttpError::internal)?;
+
+        let checkout_sessions = CheckoutSession::list(
+            stripe_client,
+            &ListCheckoutSessions {
+                subscription: Some(stripe_subscription_id.clone()),
+                ..ListCheckoutSessions::new()
+            },
+        )
+        .await
+        .map_err(|err| {
+            tracing::error!(
+                "[EventService.reconcile_subscription] {stripe_subscription_id}: {err}"
+            );
+            HttpError::internal()
+        })?;
+
+        for checkout_session in checkout_sessions.data {
+            self.handle_checkout_session(checkout_session).await?;
+        }
+
+        if let Some(latest_invoice) = &subscription.latest_invoice {
+            let invoice = StripeInvoice::retrieve(
+                stripe_client,
+                &latest_invoice.id(),
+                &[],
+            )
+            .await
+            .map_err(|err| {
+                tracing::error!(
+                    "[EventService.reconcile_subscription] {}: {err}",
+                    latest_invoice.id()
+                );
+                HttpError::internal()
+            })?;
+
+            if invoice.status == Some(InvoiceStatus::Paid) {
+                self.put_invoice_payment(invoice).await?;
+            }
+        }
+
+        // The fetched state is the most recent one, so older events that
+        // arrive afterwards must not overwrite it.
+        self.handle_subscription(
+            subscription,
+            None,
+            None,
+            Utc::now().timestamp(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Computes `access_until` of the subscriptions stored before it existed
+    /// and publishes them. They are ignored by the access expiry until then.
+    pub async fn backfill_access_until(&self) -> Result<(), HttpError> {
+        let subscriptions =
+            Subscription::list_without_access_until(&self.pool).await?;
+
+        let conn = self.pool.get().await.map_err(DbError::from)?;
+
+        for subscription in subscriptions {
+            let access_until = self.access_until(&conn, &subscription).await?;
+
+            if access_until.is_some() {
+                self.send_updated_subscription(&conn, subscription).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Recomputes `access_until` of all subscriptions of the shop after its
+    /// grace periods changed and publishes the ones that changed.
+    pub async fn refresh_access_until(
+        &self,
+        shop_id: &Uuid,
+    ) -> Result<(), HttpError> {
+        let subscriptions =
+            Subscription::list_by_shop_id(&self.pool, shop_id).await?;
+
+        let conn = self.pool.get().await.map_err(DbError::from)?;
+
+        for subscription in subscriptions {
+            let access_until = self.access_until(&conn, &subscription).await?;
+
+            if access_until != subscription.access_until {
+                self.send_updated_subscription(&conn, subscription).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn handle_event(
+        &self,
+        event: Event,
+    ) -> Result<HttpResponse, HttpError> {
+        use stripe::EventType::*;
+
+        match event.type_ {
+            CheckoutSessionCompleted => {
+                if let EventObject::CheckoutSession(checkout_session) =
+                    event.data.object
+                {
+                    self.handle_checkout_session(checkout_session).await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            CustomerCreated | CustomerUpdated => {
+                if let EventObject::Customer(customer) = event.data.object {
+                    self.handle_customer(customer, event.created).await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            CustomerDeleted => {
+                if let EventObject::Customer(customer) = event.data.object {
+                    self.handle_customer_deleted(
+                        customer,
+                        event.id.to_string(),
+                        event.created,
+                    )
+                    .await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            CouponCreated | CouponUpdated | CouponDeleted => {
+                if let EventObject::Coupon(coupon) = event.data.object {
+                    self.handle_coupon(coupon, event.type_, event.created).await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            PromotionCodeCreated | PromotionCodeUpdated => {
+                if let EventObject::PromotionCode(promotion_code) =
+                    event.data.object
+                {
+                    self.handle_promotion_code(promotion_code, event.created)
+                        .await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            CustomerSubscriptionPendingUpdateApplied
+            | CustomerSubscriptionPendingUpdateExpired => {
+                if let EventObject::Subscription(subscription) =
+                    event.data.object
+                {
+                    self.resolve_pending_updates(
+                        &subscription,
+                        event.type_,
+                        &event.id.to_string(),
+                        event.created,
+                    )
+                    .await?;
+
+                    self.handle_subscription(
+                        subscription,
+                        Some(event.id.to_string()),
+                        event.data.previous_attributes.as_ref(),
+                        event.created,
+                    )
+                    .await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            CustomerSubscriptionResumed
+            | CustomerSubscriptionPaused
+            | CustomerSubscriptionDeleted
+            | CustomerSubscriptionTrialWillEnd
+            | CustomerSubscriptionCreated
+            | CustomerSubscriptionUpdated => {
+                if let EventObject::Subscription(subscription) =
+                    event.data.object
+                {
+                    self.handle_subscription(
+                        subscription,
+                        Some(event.id.to_string()),
+                        event.data.previous_attributes.as_ref(),
+                        event.created,
+                    )
+                    .await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            SubscriptionScheduleAborted
+            | SubscriptionScheduleCanceled
+            | SubscriptionScheduleCompleted
+            | SubscriptionScheduleCreated
+            | SubscriptionScheduleExpiring
+            | SubscriptionScheduleReleased
+            | SubscriptionScheduleUpdated => {
+                if let EventObject::SubscriptionSchedule(schedule) =
+                    event.data.object
+                {
+                    self.handle_subscription_schedule(schedule, event.created)
+                        .await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            PaymentMethodAttached
+            | PaymentMethodAutomaticallyUpdated
+            | PaymentMethodDetached
+            | PaymentMethodUpdated => {
+                if let EventObject::PaymentMethod(payment_method) =
+                    event.data.object
+                {
+                    self.handle_payment_method(
+                        payment_method,
+                        event.type_,
+                        event.created,
+                    )
+                    .await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            CustomerSourceCreated
+            | CustomerSourceDeleted
+            | CustomerSourceExpiring
+            | CustomerSourceUpdated => match event.data.object {
+                EventObject::Card(card) => {
+                    self.handle_card_source(card, event.type_, event.created)
+                        .await
+                }
+                // Bank accounts and other sources cannot expire.
+                _ => Ok(HttpResponse::Ok().finish()),
+            },
+            PaymentIntentCanceled
+            | PaymentIntentPaymentFailed
+            | PaymentIntentProcessing
+            | PaymentIntentRequiresAction
+            | PaymentIntentSucceeded => {
+                if let EventObject::PaymentIntent(payment_intent) =
+                    event.data.object
+                {
+                    self.handle_payment_intent(
+                        payment_intent,
+                        event.id.to_string(),
+                        event.type_,
+                        event.created,
+                    )
+                    .await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            SetupIntentCanceled
+            | SetupIntentCreated
+            | SetupIntentRequiresAction
+            | SetupIntentSetupFailed
+            | SetupIntentSucceeded => {
+                if let EventObject::SetupIntent(setup_intent) =
+                    event.data.object
+                {
+                    self.handle_setup_intent(
+                        setup_intent,
+                        event.id.to_string(),
+                        event.type_,
+                        event.created,
+                    )
+                    .await
+                } else {
+                    Err(Self::unexpected_object(&event))
+                }
+            }
+            InvoiceUpcoming => {
+                if let EventObject::Invoice(invoice) = event.data.object {
+                    self.handle_upcoming_invoice(invoice, event.created).await
+                } else {
+                    Err(Self::unexpected_object(&event))
                 }
             }
-            InvoicePaid => {
+            InvoiceCreated
+            | InvoiceDeleted
+            | InvoiceFinalizationFailed
+            | InvoiceFinalized
+            | InvoiceMarkedUncollectible
+            | InvoicePaid
+            | InvoicePaymentActionRequired
+            | InvoicePaymentFailed
+            | InvoicePaymentSucceeded
+            | InvoiceSent
+            | InvoiceUpdated
+            | InvoiceVoided => {
                 if let EventObject::Invoice(invoice) = event.data.object {
-                    self.handle_invoice(invoice).await
+                    self.handle_invoice(invoice, event.type_, event.created)
+                        .await
                 } else {
                     Err(Self::unexpected_object(&event))
                 }
@@ -317,3 +2906,182 @@ impl EventService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use stripe::{InvoiceLineItem, Period};
+
+    use super::*;
+
+    const DAY: i64 = 86_400;
+
+    fn line(
+        stripe_subscription_id: &str,
+        start: i64,
+        end: i64,
+        proration: bool,
+    ) -> InvoiceLineItem {
+        InvoiceLineItem {
+            subscription: Some(Expandable::Id(
+                stripe_subscription_id.parse().unwrap(),
+            )),
+            period: Some(Period {
+                start: Some(start),
+                end: Some(end),
+            }),
+            proration,
+            ..Default::default()
+        }
+    }
+
+    fn invoice(lines: Vec<InvoiceLineItem>) -> StripeInvoice {
+        StripeInvoice {
+            lines: Some(List {
+                data: lines,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn period(start: i64, end: i64) -> (DateTime<Utc>, DateTime<Utc>) {
+        (
+            DateTime::<Utc>::from_timestamp(start, 0).unwrap(),
+            DateTime::<Utc>::from_timestamp(end, 0).unwrap(),
+        )
+    }
+
+    #[test]
+    fn groups_lines_by_subscription() {
+        let paid = EventService::paid_periods(&invoice(vec![
+            line("sub_a", 0, 30 * DAY, false),
+            line("sub_b", 10 * DAY, 40 * DAY, false),
+            line("sub_a", 0, 30 * DAY, false),
+        ]));
+
+        assert_eq!(
+            paid,
+            BTreeMap::from([
+                ("sub_a".to_string(), period(0, 30 * DAY)),
+                ("sub_b".to_string(), period(10 * DAY, 40 * DAY)),
+            ])
+        );
+    }
+
+    #[test]
+    fn spans_out_of_order_periods() {
+        let paid = EventService::paid_periods(&invoice(vec![
+            line("sub_a", 30 * DAY, 60 * DAY, false),
+            line("sub_a", 0, 30 * DAY, false),
+        ]));
+
+        assert_eq!(paid["sub_a"], period(0, 60 * DAY));
+    }
+
+    #[test]
+    fn ignores_proration_lines_next_to_regular_lines() {
+        let paid = EventService::paid_periods(&invoice(vec![
+            line("sub_a", 15 * DAY, 30 * DAY, true),
+            line("sub_a", 30 * DAY, 60 * DAY, false),
+            line("sub_a", 10 * DAY, 30 * DAY, true),
+        ]));
+
+        assert_eq!(paid["sub_a"], period(30 * DAY, 60 * DAY));
+    }
+
+    #[test]
+    fn pays_proration_only_invoices_for_their_period() {
+        let paid = EventService::paid_periods(&invoice(vec![
+            line("sub_a", 20 * DAY, 30 * DAY, true),
+            line("sub_a", 15 * DAY, 30 * DAY, true),
+        ]));
+
+        assert_eq!(paid["sub_a"], period(15 * DAY, 30 * DAY));
+    }
+
+    #[test]
+    fn skips_lines_without_subscription_or_period() {
+        let mut without_subscription = line("sub_a", 0, 30 * DAY, false);
+        without_subscription.subscription = None;
+        let mut without_period = line("sub_b", 0, 30 * DAY, false);
+        without_period.period = None;
+
+        let paid = EventService::paid_periods(&invoice(vec![
+            without_subscription,
+            without_period,
+        ]));
+
+        assert!(paid.is_empty());
+    }
+
+    #[test]
+    fn reads_previous_plan_items() {
+        let previous_attributes = HashMap::from([(
+            "items".to_string(),
+            serde_json::json!({
+                "data": [
+                    {
+                        "id": "si_a",
+                        "price": { "id": "price_old", "unit_amount": 1000 },
+                        "quantity": 2,
+                    },
+                    {
+                        "id": "si_b",
+                        "price": { "id": "price_metered" },
+                    },
+                    { "id": "si_c" },
+                ]
+            }),
+        )]);
+
+        assert_eq!(
+            EventService::previous_plan_items(&previous_attributes),
+            Some(BTreeMap::from([
+                (
+                    "si_a".to_string(),
+                    PlanItem {
+                        price_id: "price_old".to_string(),
+                        quantity: Some(2),
+                        unit_amount: Some(1000),
+                    },
+                ),
+                (
+                    "si_b".to_string(),
+                    PlanItem {
+                        price_id: "price_metered".to_string(),
+                        quantity: None,
+                        unit_amount: None,
+                    },
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn ignores_updates_without_previous_items() {
+        let previous_attributes =
+            HashMap::from([("cancel_at".to_string(), serde_json::json!(null))]);
+
+        assert_eq!(
+            EventService::previous_plan_items(&previous_attributes),
+            None
+        );
+    }
+
+    #[test]
+    fn merges_periods_per_kind() {
+        let mut periods = InvoicePeriods::default();
+        let (start, end) = period(10 * DAY, 20 * DAY);
+        periods.add(start, end, true);
+
+        assert_eq!(periods.paid(), Some((start, end)));
+
+        let (start, end) = period(20 * DAY, 50 * DAY);
+        periods.add(start, end, false);
+        let (earlier_start, earlier_end) = period(0, 30 * DAY);
+        periods.add(earlier_start, earlier_end, false);
+
+        assert_eq!(periods.paid(), Some(period(0, 50 * DAY)));
+        assert_eq!(periods.proration, Some(period(10 * DAY, 20 * DAY)));
+    }
+}
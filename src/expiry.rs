@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use deadpool_postgres::Pool;
+
+use crate::api::sited_io::stripe_webhooks::v1::{
+    AccessExpiredReason, AccessExpiredResponse,
+};
+use crate::model::Subscription;
+use crate::{DbError, Publisher};
+
+/// Periodically emits an access-expired message for subscriptions whose
+/// `access_until` has passed, so revocation of access does not depend on
+/// Stripe sending another event.
+#[derive(Debug, Clone)]
+pub struct AccessExpiryScheduler {
+    pool: Pool,
+    publisher: Publisher,
+    check_interval: Duration,
+}
+
+impl AccessExpiryScheduler {
+    pub fn new(
+        pool: Pool,
+        publisher: Publisher,
+        check_interval: Duration,
+    ) -> Self {
+        Self {
+            pool,
+            publisher,
+            check_interval,
+        }
+    }
+
+    /// Returns why access expired. `access_until` is capped at `cancel_at`,
+    /// so reaching it means the subscription was canceled.
+    fn reason(subscription: &Subscription) -> AccessExpiredReason {
+        if subscription.cancel_at.is_some()
+            && subscription.cancel_at == subscription.access_until
+        {
+            AccessExpiredReason::Canceled
+        } else {
+            AccessExpiredReason::PaymentLapsed
+        }
+    }
+
+    async fn check(&self) -> Result<(), DbError> {
+        let subscriptions =
+            Subscription::list_access_expired(&self.pool, Utc::now()).await?;
+
+        for subscription in subscriptions {
+            let Some(expired_at) = subscription.access_until else {
+                continue;
+            };
+            let reason = Self::reason(&subscription);
+
+            self.publisher
+                .publish_access_expired(&AccessExpiredResponse {
+                    media_subscription_id: subscription
+                        .subscription_id
+                        .to_string(),
+                    stripe_subscription_id: subscription
+                        .stripe_subscription_id
+                        .clone(),
+                    buyer_user_id: subscription
+                        .buyer_user_id
+                        .clone()
+                        .unwrap_or_default(),
+                    shop_id: subscription.shop_id.map(|id| id.to_string()),
+                    offer_id: subscription.offer_id.map(|id| id.to_string()),
+                    expired_at: expired_at.timestamp().try_into().unwrap(),
+                    reason: reason.into(),
+                })
+                .await;
+
+            Subscription::set_access_expired(
+                &self.pool,
+                &subscription.subscription_id,
+            )
+            .await?;
+
+            tracing::info!(
+                "[AccessExpiryScheduler.check] Access to subscription {} expired at {expired_at} ({})",
+                subscription.stripe_subscription_id,
+                reason.as_str_name()
+            );
+        }
+
+        Ok(())
+    }
+
+    pub async fn run(self) {
+        let mut interval = actix_web::rt::time::interval(self.check_interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.check().await {
+                tracing::error!("[AccessExpiryScheduler.run] {err:?}");
+            }
+        }
+    }
+}
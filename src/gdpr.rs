@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+
+use deadpool_postgres::Pool;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::model::{
+    Customer, Invoice, InvoiceTaxId, PaymentAttempt, PaymentMethod,
+    Subscription, SubscriptionEvent, SubscriptionItem,
+};
+use crate::DbError;
+
+/// Prefix of the ids that replace a `buyer_user_id` on erasure.
+const PSEUDONYM_PREFIX: &str = "erased-";
+
+pub fn is_pseudonym(buyer_user_id: &str) -> bool {
+    buyer_user_id.starts_with(PSEUDONYM_PREFIX)
+}
+
+/// Everything stored about a buyer, as answer to a data-subject access
+/// request.
+#[derive(Debug, Serialize)]
+pub struct BuyerData {
+    pub buyer_user_id: String,
+    pub customers: Vec<Customer>,
+    pub payment_methods: Vec<PaymentMethod>,
+    pub subscriptions: Vec<Subscription>,
+    pub subscription_items: Vec<SubscriptionItem>,
+    pub subscription_events: Vec<SubscriptionEvent>,
+    pub invoices: Vec<Invoice>,
+    /// Tax ids of the buyer per Stripe invoice id.
+    pub invoice_tax_ids: BTreeMap<String, Vec<InvoiceTaxId>>,
+    pub payment_attempts: Vec<PaymentAttempt>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Pseudonymization {
+    pub pseudonym: String,
+    pub subscriptions: u64,
+    pub customers: u64,
+    pub payment_methods: u64,
+    pub payment_attempts: u64,
+    pub invoice_tax_ids: u64,
+}
+
+impl BuyerData {
+    pub async fn export(
+        pool: &Pool,
+        buyer_user_id: &String,
+    ) -> Result<Self, DbError> {
+        let subscriptions =
+            Subscription::list_by_buyer_user_id(pool, buyer_user_id).await?;
+
+        let customers =
+            Customer::list_by_buyer_user_id(pool, buyer_user_id).await?;
+
+        let mut payment_methods = Vec::new();
+
+        for customer in customers.iter() {
+            payment_methods.extend(
+                PaymentMethod::list_by_stripe_customer_id(
+                    pool,
+                    &customer.stripe_customer_id,
+                )
+                .await?,
+            );
+        }
+
+        let conn = pool.get().await?;
+        let mut subscription_items = Vec::new();
+
+        for subscription in subscriptions.iter() {
+            subscription_items.extend(
+                SubscriptionItem::list(&conn, &subscription.subscription_id)
+                    .await?,
+            );
+        }
+
+        let invoices =
+            Invoice::list_by_buyer_user_id(pool, buyer_user_id).await?;
+        let mut invoice_tax_ids = BTreeMap::new();
+
+        for invoice in invoices.iter() {
+            let tax_ids =
+                InvoiceTaxId::list(&conn, &invoice.stripe_invoice_id).await?;
+
+            if !tax_ids.is_empty() {
+                invoice_tax_ids
+                    .insert(invoice.stripe_invoice_id.clone(), tax_ids);
+            }
+        }
+
+        Ok(Self {
+            buyer_user_id: buyer_user_id.clone(),
+            customers,
+            payment_methods,
+            subscriptions,
+            subscription_items,
+            subscription_events: SubscriptionEvent::list_by_buyer_user_id(
+                pool,
+                buyer_user_id,
+            )
+            .await?,
+            invoices,
+            invoice_tax_ids,
+            payment_attempts: PaymentAttempt::list_by_buyer_user_id(
+                pool,
+                buyer_user_id,
+            )
+            .await?,
+        })
+    }
+
+    /// Replaces `buyer_user_id` with a random pseudonym and drops the contact
+    /// data, payment methods, payment attempts and invoice tax ids of the
+    /// buyer. Subscriptions and invoices are kept, as they are needed for
+    /// accounting. Pseudonymized customers are no longer updated by events.
+    pub async fn pseudonymize(
+        pool: &Pool,
+        buyer_user_id: &String,
+    ) -> Result<Pseudonymization, DbError> {
+        let pseudonym = format!("{PSEUDONYM_PREFIX}{}", Uuid::new_v4());
+
+        let mut conn = pool.get().await?;
+        let transaction = conn.transaction().await?;
+
+        // These are found through the buyer, so they go before it is
+        // replaced.
+        let payment_methods =
+            PaymentMethod::delete_by_buyer_user_id(&transaction, buyer_user_id)
+                .await?;
+
+        let payment_attempts = PaymentAttempt::delete_by_buyer_user_id(
+            &transaction,
+            buyer_user_id,
+        )
+        .await?;
+
+        let invoice_tax_ids =
+            InvoiceTaxId::delete_by_buyer_user_id(&transaction, buyer_user_id)
+                .await?;
+
+        let subscriptions = Subscription::replace_buyer_user_id(
+            &transaction,
+            buyer_user_id,
+            &pseudonym,
+        )
+        .await?;
+
+        let customers =
+            Customer::pseudonymize(&transaction, buyer_user_id, &pseudonym)
+                .await?;
+
+        SubscriptionEvent::replace_buyer_user_id(
+            &transaction,
+            buyer_user_id,
+            &pseudonym,
+        )
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(Pseudonymization {
+            pseudonym,
+            subscriptions,
+            customers,
+            payment_methods,
+            payment_attempts,
+            invoice_tax_ids,
+        })
+    }
+}
@@ -4,9 +4,15 @@ mod app_settings;
 mod db;
 mod error;
 mod events;
+mod expiry;
+mod gdpr;
+mod metadata;
+mod metrics;
 mod model;
+mod payment_method_expiry;
 mod publisher;
 mod routes;
+mod stalled;
 
 pub mod api;
 
@@ -14,8 +20,14 @@ pub use app_settings::AppSettings;
 pub use db::{init_db_pool, migrate, DbError};
 pub use error::HttpError;
 pub use events::EventService;
+pub use expiry::AccessExpiryScheduler;
+pub use gdpr::{BuyerData, Pseudonymization};
+pub use metadata::{MetadataMapping, MetadataSource};
+pub use metrics::Metrics;
+pub use payment_method_expiry::PaymentMethodExpiryScheduler;
 pub use publisher::Publisher;
 pub use routes::init_routes;
+pub use stalled::StalledSubscriptionDetector;
 
 pub fn get_env_var(var: &str) -> String {
     std::env::var(var).unwrap_or_else(|_| {
@@ -1,9 +1,12 @@
+use std::time::Duration;
+
 use actix_web::middleware::Logger;
 use actix_web::{web, App, HttpServer};
 
 use stripe_webhooks::{
-    get_cors, get_env_var, init_db_pool, init_routes, migrate, AppSettings,
-    EventService, Publisher,
+    get_cors, get_env_var, init_db_pool, init_routes, migrate,
+    AccessExpiryScheduler, AppSettings, EventService, MetadataMapping, Metrics,
+    PaymentMethodExpiryScheduler, Publisher, StalledSubscriptionDetector,
 };
 
 #[actix_web::main]
@@ -27,7 +30,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     migrate(&db_pool).await?;
 
     // get AppSettings
-    let app_settings = AppSettings::new(get_env_var("STRIPE_ENDPOINT_SECRET"));
+    let app_settings = AppSettings::new(
+        get_env_var("STRIPE_ENDPOINT_SECRET"),
+        get_env_var("ADMIN_TOKEN"),
+    );
 
     // initialize NATS publisher
     let publisher = Publisher::new(
@@ -40,6 +46,124 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .await?,
     );
 
+    // get mapping of metadata keys used for attribution of checkouts
+    let metadata_mapping = MetadataMapping::new(
+        &std::env::var("METADATA_SOURCES")
+            .unwrap_or_else(|_| "session".to_string()),
+        &std::env::var("METADATA_KEYS_USER_ID")
+            .unwrap_or_else(|_| "user_id".to_string()),
+        &std::env::var("METADATA_KEYS_OFFER_ID")
+            .unwrap_or_else(|_| "offer_id".to_string()),
+        &std::env::var("METADATA_KEYS_SHOP_ID")
+            .unwrap_or_else(|_| "shop_id".to_string()),
+    )?;
+
+    // initialize Stripe client, which is only required if metadata has to be
+    // retrieved from the Stripe API
+    let stripe_client = if metadata_mapping.requires_stripe_api() {
+        Some(get_env_var("STRIPE_SECRET_KEY"))
+    } else {
+        std::env::var("STRIPE_SECRET_KEY").ok()
+    }
+    .map(stripe::Client::new);
+
+    if stripe_client.is_none() {
+        tracing::warn!(
+            "STRIPE_SECRET_KEY is not set, Stripe objects will not be retrieved"
+        );
+    }
+
+    let metrics = Metrics::new();
+
+    // get grace period for shops and offers without one of their own
+    let default_grace_period = Duration::from_secs(
+        std::env::var("ACCESS_GRACE_PERIOD_SECONDS")
+            .map(|s| s.parse().unwrap())
+            .unwrap_or(0),
+    );
+
+    // compute access_until of subscriptions stored before it existed
+    let access_until_backfill = EventService::new(
+        db_pool.clone(),
+        publisher.clone(),
+        stripe_client.clone(),
+        metadata_mapping.clone(),
+        metrics.clone(),
+        default_grace_period,
+    );
+
+    actix_web::rt::spawn(async move {
+        if let Err(err) = access_until_backfill.backfill_access_until().await {
+            tracing::error!("[EventService.backfill_access_until] {err}");
+        }
+    });
+
+    // start detector for subscriptions that never got complete
+    let stalled_subscription_detector = StalledSubscriptionDetector::new(
+        db_pool.clone(),
+        EventService::new(
+            db_pool.clone(),
+            publisher.clone(),
+            stripe_client.clone(),
+            metadata_mapping.clone(),
+            metrics.clone(),
+            default_grace_period,
+        ),
+        metrics.clone(),
+        Duration::from_secs(
+            std::env::var("STALLED_SUBSCRIPTION_MAX_AGE_SECONDS")
+                .map(|s| s.parse().unwrap())
+                .unwrap_or(3600),
+        ),
+        Duration::from_secs(
+            std::env::var("STALLED_SUBSCRIPTION_CHECK_INTERVAL_SECONDS")
+                .map(|s| s.parse().unwrap())
+                .unwrap_or(600),
+        ),
+        std::env::var("STALLED_SUBSCRIPTION_MAX_RECONCILE_ATTEMPTS")
+            .map(|s| s.parse().unwrap())
+            .unwrap_or(10),
+    );
+
+    // reconciliation retrieves the subscriptions from the Stripe API
+    if stripe_client.is_some() {
+        actix_web::rt::spawn(stalled_subscription_detector.clone().run());
+    }
+
+    // start scheduler for expiry of access
+    let access_expiry_scheduler = AccessExpiryScheduler::new(
+        db_pool.clone(),
+        publisher.clone(),
+        Duration::from_secs(
+            std::env::var("ACCESS_EXPIRY_CHECK_INTERVAL_SECONDS")
+                .map(|s| s.parse().unwrap())
+                .unwrap_or(60),
+        ),
+    );
+
+    actix_web::rt::spawn(access_expiry_scheduler.run());
+
+    // start scheduler for warnings about expiring payment methods
+    let payment_method_expiry_scheduler = PaymentMethodExpiryScheduler::new(
+        db_pool.clone(),
+        publisher.clone(),
+        Duration::from_secs(
+            std::env::var("PAYMENT_METHOD_EXPIRY_WARNING_DAYS")
+                .map(|s| s.parse::<u64>().unwrap())
+                .unwrap_or(30)
+                * 24
+                * 60
+                * 60,
+        ),
+        Duration::from_secs(
+            std::env::var("PAYMENT_METHOD_EXPIRY_CHECK_INTERVAL_SECONDS")
+                .map(|s| s.parse().unwrap())
+                .unwrap_or(3600),
+        ),
+    );
+
+    actix_web::rt::spawn(payment_method_expiry_scheduler.run());
+
     let cors_allowed_origins = get_env_var("CORS_ALLOWED_ORIGINS");
 
     tracing::info!("web server listening on {}", host);
@@ -48,8 +172,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let cors = get_cors(cors_allowed_origins.clone());
 
         // initialize event service
-        let event_service =
-            EventService::new(db_pool.clone(), publisher.clone());
+        let event_service = EventService::new(
+            db_pool.clone(),
+            publisher.clone(),
+            stripe_client.clone(),
+            metadata_mapping.clone(),
+            metrics.clone(),
+            default_grace_period,
+        );
 
         App::new()
             .wrap(cors)
@@ -58,6 +188,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ))
             .app_data(web::Data::new(app_settings.clone()))
             .app_data(web::Data::new(event_service))
+            .app_data(web::Data::new(db_pool.clone()))
+            .app_data(web::Data::new(metrics.clone()))
+            .app_data(web::Data::new(stalled_subscription_detector.clone()))
             .configure(init_routes)
     })
     .workers(2)
@@ -0,0 +1,268 @@
+use std::str::FromStr;
+
+use stripe::Metadata;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataSource {
+    Session,
+    Subscription,
+    Price,
+    Product,
+    Customer,
+}
+
+impl FromStr for MetadataSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "session" => Ok(Self::Session),
+            "subscription" => Ok(Self::Subscription),
+            "price" => Ok(Self::Price),
+            "product" => Ok(Self::Product),
+            "customer" => Ok(Self::Customer),
+            other => Err(format!("unknown metadata source '{other}'")),
+        }
+    }
+}
+
+/// Describes which metadata keys hold the attribution of a checkout and on
+/// which Stripe objects to look for them. Sources and keys are both tried in
+/// the given order, the first match wins.
+#[derive(Debug, Clone)]
+pub struct MetadataMapping {
+    sources: Vec<MetadataSource>,
+    user_id_keys: Vec<String>,
+    offer_id_keys: Vec<String>,
+    shop_id_keys: Vec<String>,
+}
+
+impl MetadataMapping {
+    pub fn new(
+        sources: &str,
+        user_id_keys: &str,
+        offer_id_keys: &str,
+        shop_id_keys: &str,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            sources: split_list(sources)
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?,
+            user_id_keys: split_list(user_id_keys),
+            offer_id_keys: split_list(offer_id_keys),
+            shop_id_keys: split_list(shop_id_keys),
+        })
+    }
+
+    pub fn sources(&self) -> &[MetadataSource] {
+        &self.sources
+    }
+
+    /// Whether any source has to be retrieved from the Stripe API, i.e. is
+    /// not part of the checkout session event itself.
+    pub fn requires_stripe_api(&self) -> bool {
+        self.sources
+            .iter()
+            .any(|source| *source != MetadataSource::Session)
+    }
+
+    /// Returns the key with the highest priority for the user id, which is
+    /// the one to write the user id to.
+    pub fn user_id_key(&self) -> Option<&String> {
+        self.user_id_keys.first()
+    }
+
+    pub fn get_user_id(&self, metadata: &Metadata) -> Option<String> {
+        Self::get_first(metadata, &self.user_id_keys).cloned()
+    }
+
+    pub fn get_offer_id(&self, metadata: &Metadata) -> Option<Uuid> {
+        Self::get_first(metadata, &self.offer_id_keys)
+            .and_then(|id| id.parse().ok())
+    }
+
+    pub fn get_shop_id(&self, metadata: &Metadata) -> Option<Uuid> {
+        Self::get_first(metadata, &self.shop_id_keys)
+            .and_then(|id| id.parse().ok())
+    }
+
+    fn get_first<'a>(
+        metadata: &'a Metadata,
+        keys: &[String],
+    ) -> Option<&'a String> {
+        keys.iter().find_map(|key| metadata.get(key))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Attribution {
+    pub buyer_user_id: Option<String>,
+    pub offer_id: Option<Uuid>,
+    pub shop_id: Option<Uuid>,
+}
+
+impl Attribution {
+    pub fn is_complete(&self) -> bool {
+        self.buyer_user_id.is_some()
+            && self.offer_id.is_some()
+            && self.shop_id.is_some()
+    }
+
+    /// Fills fields that are still missing from the given metadata. Fields
+    /// that were already found in a source with higher priority are kept.
+    pub fn fill(&mut self, metadata: &Metadata, mapping: &MetadataMapping) {
+        if self.buyer_user_id.is_none() {
+            self.buyer_user_id = mapping.get_user_id(metadata);
+        }
+        if self.offer_id.is_none() {
+            self.offer_id = mapping.get_offer_id(metadata);
+        }
+        if self.shop_id.is_none() {
+            self.shop_id = mapping.get_shop_id(metadata);
+        }
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sources_in_order() {
+        let mapping = MetadataMapping::new(
+            "customer, session,price",
+            "user_id",
+            "offer_id",
+            "shop_id",
+        )
+        .unwrap();
+
+        assert_eq!(
+            mapping.sources(),
+            [
+                MetadataSource::Customer,
+                MetadataSource::Session,
+                MetadataSource::Price
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_source() {
+        let err = MetadataMapping::new(
+            "session,invoice",
+            "user_id",
+            "offer_id",
+            "shop_id",
+        )
+        .unwrap_err();
+
+        assert_eq!(err, "unknown metadata source 'invoice'");
+    }
+
+    #[test]
+    fn parses_every_source() {
+        for (source, expected) in [
+            ("session", MetadataSource::Session),
+            ("subscription", MetadataSource::Subscription),
+            ("price", MetadataSource::Price),
+            ("product", MetadataSource::Product),
+            ("customer", MetadataSource::Customer),
+        ] {
+            assert_eq!(source.parse::<MetadataSource>(), Ok(expected));
+        }
+
+        assert!(" Session".parse::<MetadataSource>().is_err());
+    }
+
+    #[test]
+    fn splits_lists_and_skips_empty_entries() {
+        assert_eq!(split_list(" a, b ,,c,"), ["a", "b", "c"]);
+        assert!(split_list("").is_empty());
+        assert!(split_list(" , ").is_empty());
+    }
+
+    #[test]
+    fn requires_stripe_api_only_for_other_sources_than_session() {
+        let session = MetadataMapping::new("session", "", "", "").unwrap();
+        let price = MetadataMapping::new("session,price", "", "", "").unwrap();
+
+        assert!(!session.requires_stripe_api());
+        assert!(price.requires_stripe_api());
+    }
+
+    #[test]
+    fn gets_first_matching_key() {
+        let mapping = MetadataMapping::new(
+            "session",
+            "buyer_id,user_id",
+            "offer_id",
+            "shop_id",
+        )
+        .unwrap();
+
+        let metadata = Metadata::from([
+            ("user_id".to_string(), "user".to_string()),
+            ("buyer_id".to_string(), "buyer".to_string()),
+        ]);
+
+        assert_eq!(mapping.user_id_key().unwrap(), "buyer_id");
+        assert_eq!(mapping.get_user_id(&metadata).as_deref(), Some("buyer"));
+
+        let metadata =
+            Metadata::from([("user_id".to_string(), "user".to_string())]);
+
+        assert_eq!(mapping.get_user_id(&metadata).as_deref(), Some("user"));
+        assert_eq!(mapping.get_user_id(&Metadata::new()), None);
+    }
+
+    #[test]
+    fn ignores_ids_that_are_not_uuids() {
+        let mapping =
+            MetadataMapping::new("session", "user_id", "offer_id", "shop_id")
+                .unwrap();
+
+        let offer_id = Uuid::new_v4();
+        let metadata = Metadata::from([
+            ("offer_id".to_string(), offer_id.to_string()),
+            ("shop_id".to_string(), "not-a-uuid".to_string()),
+        ]);
+
+        assert_eq!(mapping.get_offer_id(&metadata), Some(offer_id));
+        assert_eq!(mapping.get_shop_id(&metadata), None);
+    }
+
+    #[test]
+    fn fills_only_missing_attribution() {
+        let mapping =
+            MetadataMapping::new("session", "user_id", "offer_id", "shop_id")
+                .unwrap();
+
+        let shop_id = Uuid::new_v4();
+        let mut attribution = Attribution {
+            buyer_user_id: Some("first".to_string()),
+            ..Default::default()
+        };
+
+        attribution.fill(
+            &Metadata::from([
+                ("user_id".to_string(), "second".to_string()),
+                ("shop_id".to_string(), shop_id.to_string()),
+            ]),
+            &mapping,
+        );
+
+        assert_eq!(attribution.buyer_user_id.as_deref(), Some("first"));
+        assert_eq!(attribution.shop_id, Some(shop_id));
+        assert!(!attribution.is_complete());
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Minimal registry of counters and gauges, rendered in the Prometheus text
+/// format on `/metrics`.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    values: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(name: &str, labels: &[(&str, &str)]) -> String {
+        if labels.is_empty() {
+            return name.to_string();
+        }
+
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{key}=\"{value}\""))
+            .collect();
+
+        format!("{name}{{{}}}", labels.join(","))
+    }
+
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    pub fn add(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let mut values = self.values.lock().unwrap();
+        *values.entry(Self::key(name, labels)).or_default() += value;
+    }
+
+    pub fn set(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let mut values = self.values.lock().unwrap();
+        values.insert(Self::key(name, labels), value);
+    }
+
+    pub fn render(&self) -> String {
+        self.values
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, value)| format!("{key} {value}\n"))
+            .collect()
+    }
+}
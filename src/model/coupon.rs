@@ -0,0 +1,88 @@
+use crate::DbError;
+use deadpool_postgres::GenericClient;
+use sea_query::{Expr, Iden, OnConflict, PostgresQueryBuilder, Query};
+use sea_query_postgres::PostgresBinder;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "coupons")]
+enum CouponIden {
+    Table,
+    StripeCouponId,
+    Name,
+    PercentOff,
+    AmountOff,
+    Currency,
+    Duration,
+    DurationInMonths,
+    Valid,
+    Deleted,
+    EventTimestamp,
+}
+
+/// Coupon a discount or promotion code is based on.
+pub struct Coupon;
+
+impl Coupon {
+    const PUT_COLUMNS: [CouponIden; 10] = [
+        CouponIden::StripeCouponId,
+        CouponIden::Name,
+        CouponIden::PercentOff,
+        CouponIden::AmountOff,
+        CouponIden::Currency,
+        CouponIden::Duration,
+        CouponIden::DurationInMonths,
+        CouponIden::Valid,
+        CouponIden::Deleted,
+        CouponIden::EventTimestamp,
+    ];
+
+    /// Inserts or updates the coupon unless the stored coupon was written by
+    /// an event newer than `event_timestamp`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put(
+        conn: &impl GenericClient,
+        stripe_coupon_id: &String,
+        name: Option<String>,
+        percent_off: Option<f64>,
+        amount_off: Option<i64>,
+        currency: Option<String>,
+        duration: Option<String>,
+        duration_in_months: Option<i64>,
+        valid: Option<bool>,
+        deleted: bool,
+        event_timestamp: i64,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(CouponIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_coupon_id.into(),
+                name.into(),
+                percent_off.into(),
+                amount_off.into(),
+                currency.into(),
+                duration.into(),
+                duration_in_months.into(),
+                valid.into(),
+                deleted.into(),
+                event_timestamp.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(CouponIden::StripeCouponId)
+                    .update_columns(Self::PUT_COLUMNS)
+                    .action_and_where(
+                        Expr::col((
+                            CouponIden::Table,
+                            CouponIden::EventTimestamp,
+                        ))
+                        .lte(event_timestamp),
+                    )
+                    .to_owned(),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,365 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+    SelectStatement, UpdateStatement,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "customers")]
+pub(super) enum CustomerIden {
+    Table,
+    CustomerId,
+    StripeCustomerId,
+    BuyerUserId,
+    Email,
+    Name,
+    Phone,
+    AddressLine1,
+    AddressLine2,
+    AddressPostalCode,
+    AddressCity,
+    AddressState,
+    AddressCountry,
+    EventTimestamp,
+    CreatedAt,
+    UpdatedAt,
+    DefaultPaymentMethodId,
+    PseudonymizedAt,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CustomerAddress {
+    pub line1: Option<String>,
+    pub line2: Option<String>,
+    pub postal_code: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub country: Option<String>,
+}
+
+impl From<stripe::Address> for CustomerAddress {
+    fn from(address: stripe::Address) -> Self {
+        Self {
+            line1: address.line1,
+            line2: address.line2,
+            postal_code: address.postal_code,
+            city: address.city,
+            state: address.state,
+            country: address.country,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Customer {
+    pub customer_id: Uuid,
+    pub stripe_customer_id: String,
+    pub buyer_user_id: Option<String>,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub phone: Option<String>,
+    pub address: CustomerAddress,
+    pub event_timestamp: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub default_payment_method_id: Option<String>,
+    pub pseudonymized_at: Option<DateTime<Utc>>,
+}
+
+impl Customer {
+    const PUT_COLUMNS: [CustomerIden; 11] = [
+        CustomerIden::StripeCustomerId,
+        CustomerIden::Email,
+        CustomerIden::Name,
+        CustomerIden::Phone,
+        CustomerIden::AddressLine1,
+        CustomerIden::AddressLine2,
+        CustomerIden::AddressPostalCode,
+        CustomerIden::AddressCity,
+        CustomerIden::AddressState,
+        CustomerIden::AddressCountry,
+        CustomerIden::EventTimestamp,
+    ];
+
+    /// Inserts or updates the customer. A known `buyer_user_id` is kept if
+    /// none is given. Returns `None` if the stored customer was written by an
+    /// event newer than `event_timestamp` or was pseudonymized, so erased data
+    /// is not restored by later events.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put(
+        pool: &Pool,
+        stripe_customer_id: &String,
+        buyer_user_id: Option<String>,
+        email: Option<String>,
+        name: Option<String>,
+        phone: Option<String>,
+        address: CustomerAddress,
+        event_timestamp: i64,
+    ) -> Result<Option<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let mut on_conflict =
+            OnConflict::column(CustomerIden::StripeCustomerId)
+                .update_columns(Self::PUT_COLUMNS)
+                .action_and_where(
+                    Expr::col((
+                        CustomerIden::Table,
+                        CustomerIden::EventTimestamp,
+                    ))
+                    .lte(event_timestamp)
+                    .and(
+                        Expr::col((
+                            CustomerIden::Table,
+                            CustomerIden::PseudonymizedAt,
+                        ))
+                        .is_null(),
+                    ),
+                )
+                .to_owned();
+
+        if buyer_user_id.is_some() {
+            on_conflict.update_column(CustomerIden::BuyerUserId);
+        }
+
+        let (sql, values) = Query::insert()
+            .into_table(CustomerIden::Table)
+            .columns(
+                Self::PUT_COLUMNS
+                    .into_iter()
+                    .chain([CustomerIden::BuyerUserId]),
+            )
+            .values([
+                stripe_customer_id.into(),
+                email.into(),
+                name.into(),
+                phone.into(),
+                address.line1.into(),
+                address.line2.into(),
+                address.postal_code.into(),
+                address.city.into(),
+                address.state.into(),
+                address.country.into(),
+                event_timestamp.into(),
+                buyer_user_id.into(),
+            ])?
+            .on_conflict(on_conflict)
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Sets the payment method invoices of the customer are charged with,
+    /// unless the customer was written by a newer event or was pseudonymized.
+    pub async fn put_default_payment_method(
+        pool: &Pool,
+        stripe_customer_id: &String,
+        default_payment_method_id: Option<String>,
+        event_timestamp: i64,
+    ) -> Result<(), DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::update()
+            .table(CustomerIden::Table)
+            .value(
+                CustomerIden::DefaultPaymentMethodId,
+                default_payment_method_id,
+            )
+            .and_where(
+                Expr::col(CustomerIden::StripeCustomerId)
+                    .eq(stripe_customer_id),
+            )
+            .and_where(
+                Expr::col(CustomerIden::EventTimestamp).lte(event_timestamp),
+            )
+            .and_where(Expr::col(CustomerIden::PseudonymizedAt).is_null())
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    /// Selects the Stripe ids of all customers of the buyer.
+    pub(super) fn select_stripe_customer_ids(
+        buyer_user_id: &String,
+    ) -> SelectStatement {
+        Query::select()
+            .column(CustomerIden::StripeCustomerId)
+            .from(CustomerIden::Table)
+            .and_where(Expr::col(CustomerIden::BuyerUserId).eq(buyer_user_id))
+            .to_owned()
+    }
+
+    /// Selects the buyers of the customer, to find subscriptions stored
+    /// without their Stripe customer id.
+    pub(super) fn select_buyer_user_ids(
+        stripe_customer_id: &String,
+    ) -> SelectStatement {
+        Query::select()
+            .column(CustomerIden::BuyerUserId)
+            .from(CustomerIden::Table)
+            .and_where(
+                Expr::col(CustomerIden::StripeCustomerId)
+                    .eq(stripe_customer_id),
+            )
+            .and_where(Expr::col(CustomerIden::BuyerUserId).is_not_null())
+            .to_owned()
+    }
+
+    pub async fn get(
+        conn: &impl GenericClient,
+        stripe_customer_id: &String,
+    ) -> Result<Option<Self>, DbError> {
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(CustomerIden::Table)
+            .and_where(
+                Expr::col(CustomerIden::StripeCustomerId)
+                    .eq(stripe_customer_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    pub async fn list_by_buyer_user_id(
+        pool: &Pool,
+        buyer_user_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(CustomerIden::Table)
+            .and_where(Expr::col(CustomerIden::BuyerUserId).eq(buyer_user_id))
+            .order_by(CustomerIden::CreatedAt, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Drops the contact data of all customers of the buyer, replaces the
+    /// buyer and marks the customers as pseudonymized. Returns the number of
+    /// updated customers.
+    pub async fn pseudonymize<'a>(
+        conn: &Transaction<'a>,
+        buyer_user_id: &String,
+        new_buyer_user_id: &String,
+    ) -> Result<u64, DbError> {
+        let (sql, values) = Self::erase(Some(new_buyer_user_id))
+            .and_where(Expr::col(CustomerIden::BuyerUserId).eq(buyer_user_id))
+            .build_postgres(PostgresQueryBuilder);
+
+        Ok(conn.execute(sql.as_str(), &values.as_params()).await?)
+    }
+
+    /// Drops the contact data and the buyer of all customers of the buyer
+    /// and marks them as pseudonymized, so later events do not recreate
+    /// them. Returns the erased customers.
+    pub async fn delete_by_buyer_user_id(
+        pool: &Pool,
+        buyer_user_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Self::erase(None)
+            .and_where(Expr::col(CustomerIden::BuyerUserId).eq(buyer_user_id))
+            .and_where(Expr::col(CustomerIden::PseudonymizedAt).is_null())
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Drops the contact data and the buyer of a customer deleted at Stripe
+    /// and marks it as pseudonymized. Returns the erased customer, or `None`
+    /// if it is unknown or was erased before.
+    pub async fn erase_by_stripe_customer_id<'a>(
+        conn: &Transaction<'a>,
+        stripe_customer_id: &String,
+    ) -> Result<Option<Self>, DbError> {
+        let (sql, values) = Self::erase(None)
+            .and_where(
+                Expr::col(CustomerIden::StripeCustomerId)
+                    .eq(stripe_customer_id),
+            )
+            .and_where(Expr::col(CustomerIden::PseudonymizedAt).is_null())
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    fn erase(new_buyer_user_id: Option<&String>) -> UpdateStatement {
+        Query::update()
+            .table(CustomerIden::Table)
+            .value(CustomerIden::BuyerUserId, new_buyer_user_id.cloned())
+            .value(CustomerIden::PseudonymizedAt, Expr::current_timestamp())
+            .values(
+                [
+                    CustomerIden::Email,
+                    CustomerIden::Name,
+                    CustomerIden::Phone,
+                    CustomerIden::AddressLine1,
+                    CustomerIden::AddressLine2,
+                    CustomerIden::AddressPostalCode,
+                    CustomerIden::AddressCity,
+                    CustomerIden::AddressState,
+                    CustomerIden::AddressCountry,
+                ]
+                .map(|column| (column, Option::<String>::None.into())),
+            )
+            .to_owned()
+    }
+}
+
+impl From<Row> for Customer {
+    fn from(row: Row) -> Self {
+        Self {
+            customer_id: row.get(CustomerIden::CustomerId.to_string().as_str()),
+            stripe_customer_id: row
+                .get(CustomerIden::StripeCustomerId.to_string().as_str()),
+            buyer_user_id: row
+                .get(CustomerIden::BuyerUserId.to_string().as_str()),
+            email: row.get(CustomerIden::Email.to_string().as_str()),
+            name: row.get(CustomerIden::Name.to_string().as_str()),
+            phone: row.get(CustomerIden::Phone.to_string().as_str()),
+            address: CustomerAddress {
+                line1: row.get(CustomerIden::AddressLine1.to_string().as_str()),
+                line2: row.get(CustomerIden::AddressLine2.to_string().as_str()),
+                postal_code: row
+                    .get(CustomerIden::AddressPostalCode.to_string().as_str()),
+                city: row.get(CustomerIden::AddressCity.to_string().as_str()),
+                state: row.get(CustomerIden::AddressState.to_string().as_str()),
+                country: row
+                    .get(CustomerIden::AddressCountry.to_string().as_str()),
+            },
+            event_timestamp: row
+                .get(CustomerIden::EventTimestamp.to_string().as_str()),
+            created_at: row.get(CustomerIden::CreatedAt.to_string().as_str()),
+            updated_at: row.get(CustomerIden::UpdatedAt.to_string().as_str()),
+            default_payment_method_id: row
+                .get(CustomerIden::DefaultPaymentMethodId.to_string().as_str()),
+            pseudonymized_at: row
+                .get(CustomerIden::PseudonymizedAt.to_string().as_str()),
+        }
+    }
+}
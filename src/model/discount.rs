@@ -0,0 +1,235 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{GenericClient, Pool};
+use sea_query::{
+    Alias, Expr, Func, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+    SimpleExpr,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+use super::discount_amount::DiscountAmountIden;
+use super::promotion_code::PromotionCodeIden;
+use super::subscription::SubscriptionIden;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "discounts")]
+enum DiscountIden {
+    Table,
+    StripeDiscountId,
+    StripeCouponId,
+    StripePromotionCodeId,
+    StripeSubscriptionId,
+    StripeCheckoutSessionId,
+    StartAt,
+    EndAt,
+}
+
+/// Coupon applied to a subscription or a checkout session.
+pub struct Discount;
+
+/// Redemptions of a promotion code within the subscriptions of a shop.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromotionCodeUsage {
+    pub stripe_promotion_code_id: String,
+    pub code: Option<String>,
+    pub stripe_coupon_id: String,
+    pub subscription_count: i64,
+    pub amount_discounted: i64,
+}
+
+impl Discount {
+    const PUT_COLUMNS: [DiscountIden; 7] = [
+        DiscountIden::StripeDiscountId,
+        DiscountIden::StripeCouponId,
+        DiscountIden::StripePromotionCodeId,
+        DiscountIden::StripeSubscriptionId,
+        DiscountIden::StripeCheckoutSessionId,
+        DiscountIden::StartAt,
+        DiscountIden::EndAt,
+    ];
+
+    /// Inserts or updates the discount. Subscription and checkout session are
+    /// only overwritten if given, as not every object referencing a discount
+    /// knows both.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put(
+        conn: &impl GenericClient,
+        stripe_discount_id: &String,
+        stripe_coupon_id: &String,
+        stripe_promotion_code_id: Option<&String>,
+        stripe_subscription_id: Option<&String>,
+        stripe_checkout_session_id: Option<&String>,
+        start_at: DateTime<Utc>,
+        end_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(DiscountIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_discount_id.into(),
+                stripe_coupon_id.into(),
+                stripe_promotion_code_id.map(|s| s.as_str()).into(),
+                stripe_subscription_id.map(|s| s.as_str()).into(),
+                stripe_checkout_session_id.map(|s| s.as_str()).into(),
+                start_at.into(),
+                end_at.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(DiscountIden::StripeDiscountId)
+                    .update_columns([
+                        DiscountIden::StripeCouponId,
+                        DiscountIden::StartAt,
+                        DiscountIden::EndAt,
+                    ])
+                    .values([
+                        (
+                            DiscountIden::StripePromotionCodeId,
+                            Self::excluded_or_existing(
+                                DiscountIden::StripePromotionCodeId,
+                            ),
+                        ),
+                        (
+                            DiscountIden::StripeSubscriptionId,
+                            Self::excluded_or_existing(
+                                DiscountIden::StripeSubscriptionId,
+                            ),
+                        ),
+                        (
+                            DiscountIden::StripeCheckoutSessionId,
+                            Self::excluded_or_existing(
+                                DiscountIden::StripeCheckoutSessionId,
+                            ),
+                        ),
+                    ])
+                    .to_owned(),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    fn excluded_or_existing(column: DiscountIden) -> SimpleExpr {
+        Func::coalesce([
+            Expr::col((Alias::new("excluded"), column.clone())).into(),
+            Expr::col((DiscountIden::Table, column)).into(),
+        ])
+        .into()
+    }
+
+    pub async fn list_promotion_code_usage_by_shop_id(
+        pool: &Pool,
+        shop_id: &Uuid,
+    ) -> Result<Vec<PromotionCodeUsage>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column((DiscountIden::Table, DiscountIden::StripePromotionCodeId))
+            .column((PromotionCodeIden::Table, PromotionCodeIden::Code))
+            .column((DiscountIden::Table, DiscountIden::StripeCouponId))
+            .expr_as(
+                Func::count_distinct(Expr::col((
+                    DiscountIden::Table,
+                    DiscountIden::StripeSubscriptionId,
+                ))),
+                Alias::new("subscription_count"),
+            )
+            .expr_as(
+                Func::cast_as(
+                    Expr::expr(Func::sum(Expr::col((
+                        DiscountAmountIden::Table,
+                        DiscountAmountIden::Amount,
+                    ))))
+                    .if_null(0),
+                    Alias::new("INT8"),
+                ),
+                Alias::new("amount_discounted"),
+            )
+            .from(DiscountIden::Table)
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::StripeSubscriptionId,
+                ))
+                .equals((
+                    DiscountIden::Table,
+                    DiscountIden::StripeSubscriptionId,
+                )),
+            )
+            .left_join(
+                PromotionCodeIden::Table,
+                Expr::col((
+                    PromotionCodeIden::Table,
+                    PromotionCodeIden::StripePromotionCodeId,
+                ))
+                .equals((
+                    DiscountIden::Table,
+                    DiscountIden::StripePromotionCodeId,
+                )),
+            )
+            // The amount of the checkout session is also on the first
+            // invoice, so only the invoices are summed up.
+            .left_join(
+                DiscountAmountIden::Table,
+                Expr::col((
+                    DiscountAmountIden::Table,
+                    DiscountAmountIden::StripeDiscountId,
+                ))
+                .equals((DiscountIden::Table, DiscountIden::StripeDiscountId))
+                .and(
+                    Expr::col((
+                        DiscountAmountIden::Table,
+                        DiscountAmountIden::StripeObjectId,
+                    ))
+                    .ne(Expr::col((
+                        DiscountIden::Table,
+                        DiscountIden::StripeCheckoutSessionId,
+                    )))
+                    .or(Expr::col((
+                        DiscountIden::Table,
+                        DiscountIden::StripeCheckoutSessionId,
+                    ))
+                    .is_null()),
+                ),
+            )
+            .and_where(
+                Expr::col((SubscriptionIden::Table, SubscriptionIden::ShopId))
+                    .eq(*shop_id),
+            )
+            .and_where(
+                Expr::col((
+                    DiscountIden::Table,
+                    DiscountIden::StripePromotionCodeId,
+                ))
+                .is_not_null(),
+            )
+            .group_by_columns([
+                (DiscountIden::Table, DiscountIden::StripePromotionCodeId),
+                (DiscountIden::Table, DiscountIden::StripeCouponId),
+            ])
+            .group_by_col((PromotionCodeIden::Table, PromotionCodeIden::Code))
+            .order_by(Alias::new("subscription_count"), Order::Desc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| PromotionCodeUsage {
+                stripe_promotion_code_id: row.get(
+                    DiscountIden::StripePromotionCodeId.to_string().as_str(),
+                ),
+                code: row.get(PromotionCodeIden::Code.to_string().as_str()),
+                stripe_coupon_id: row
+                    .get(DiscountIden::StripeCouponId.to_string().as_str()),
+                subscription_count: row.get("subscription_count"),
+                amount_discounted: row.get("amount_discounted"),
+            })
+            .collect())
+    }
+}
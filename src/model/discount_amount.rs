@@ -0,0 +1,52 @@
+use deadpool_postgres::GenericClient;
+use sea_query::{Iden, OnConflict, PostgresQueryBuilder, Query};
+use sea_query_postgres::PostgresBinder;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "discount_amounts")]
+pub(super) enum DiscountAmountIden {
+    Table,
+    StripeDiscountId,
+    StripeObjectId,
+    Amount,
+}
+
+/// Amount a discount took off a checkout session or an invoice.
+pub struct DiscountAmount;
+
+impl DiscountAmount {
+    pub async fn put(
+        conn: &impl GenericClient,
+        stripe_discount_id: &String,
+        stripe_object_id: &String,
+        amount: i64,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(DiscountAmountIden::Table)
+            .columns([
+                DiscountAmountIden::StripeDiscountId,
+                DiscountAmountIden::StripeObjectId,
+                DiscountAmountIden::Amount,
+            ])
+            .values([
+                stripe_discount_id.into(),
+                stripe_object_id.into(),
+                amount.into(),
+            ])?
+            .on_conflict(
+                OnConflict::columns([
+                    DiscountAmountIden::StripeDiscountId,
+                    DiscountAmountIden::StripeObjectId,
+                ])
+                .update_column(DiscountAmountIden::Amount)
+                .to_owned(),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Pool};
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "grace_periods")]
+enum GracePeriodIden {
+    Table,
+    ShopId,
+    OfferId,
+    GracePeriodSeconds,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Time a buyer keeps access after the paid period ended, e.g. while a
+/// renewal payment is still being processed. A grace period without
+/// `offer_id` applies to all offers of the shop that have none of their own.
+#[derive(Debug, Clone, Serialize)]
+pub struct GracePeriod {
+    pub shop_id: Uuid,
+    pub offer_id: Option<Uuid>,
+    pub grace_period_seconds: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl GracePeriod {
+    /// Returns the grace period of the offer, falling back to the one of the
+    /// shop.
+    pub async fn get_effective(
+        conn: &impl GenericClient,
+        shop_id: &Uuid,
+        offer_id: Option<&Uuid>,
+    ) -> Result<Option<Self>, DbError> {
+        let offer_ids = [offer_id.copied(), Some(Uuid::nil())];
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(GracePeriodIden::Table)
+            .and_where(Expr::col(GracePeriodIden::ShopId).eq(*shop_id))
+            .and_where(
+                Expr::col(GracePeriodIden::OfferId)
+                    .is_in(offer_ids.into_iter().flatten()),
+            )
+            .order_by(GracePeriodIden::OfferId, Order::Desc)
+            .limit(1)
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    pub async fn list(
+        pool: &Pool,
+        shop_id: &Uuid,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(GracePeriodIden::Table)
+            .and_where(Expr::col(GracePeriodIden::ShopId).eq(*shop_id))
+            .order_by(GracePeriodIden::OfferId, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    pub async fn put(
+        pool: &Pool,
+        shop_id: &Uuid,
+        offer_id: Option<&Uuid>,
+        grace_period_seconds: i64,
+    ) -> Result<Self, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::insert()
+            .into_table(GracePeriodIden::Table)
+            .columns([
+                GracePeriodIden::ShopId,
+                GracePeriodIden::OfferId,
+                GracePeriodIden::GracePeriodSeconds,
+            ])
+            .values([
+                (*shop_id).into(),
+                offer_id.copied().unwrap_or_else(Uuid::nil).into(),
+                grace_period_seconds.into(),
+            ])?
+            .on_conflict(
+                OnConflict::columns([
+                    GracePeriodIden::ShopId,
+                    GracePeriodIden::OfferId,
+                ])
+                .update_column(GracePeriodIden::GracePeriodSeconds)
+                .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Self::from(row))
+    }
+
+    pub async fn delete(
+        pool: &Pool,
+        shop_id: &Uuid,
+        offer_id: Option<&Uuid>,
+    ) -> Result<(), DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(GracePeriodIden::Table)
+            .and_where(Expr::col(GracePeriodIden::ShopId).eq(*shop_id))
+            .and_where(
+                Expr::col(GracePeriodIden::OfferId)
+                    .eq(offer_id.copied().unwrap_or_else(Uuid::nil)),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
+
+impl From<Row> for GracePeriod {
+    fn from(row: Row) -> Self {
+        let offer_id: Uuid =
+            row.get(GracePeriodIden::OfferId.to_string().as_str());
+
+        Self {
+            shop_id: row.get(GracePeriodIden::ShopId.to_string().as_str()),
+            offer_id: (!offer_id.is_nil()).then_some(offer_id),
+            grace_period_seconds: row
+                .get(GracePeriodIden::GracePeriodSeconds.to_string().as_str()),
+            created_at: row
+                .get(GracePeriodIden::CreatedAt.to_string().as_str()),
+            updated_at: row
+                .get(GracePeriodIden::UpdatedAt.to_string().as_str()),
+        }
+    }
+}
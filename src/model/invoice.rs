@@ -0,0 +1,320 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::Pool;
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+    SelectStatement,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+use super::subscription::SubscriptionIden;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "invoices")]
+pub(super) enum InvoiceIden {
+    Table,
+    InvoiceId,
+    StripeInvoiceId,
+    StripeSubscriptionId,
+    AmountDue,
+    AmountPaid,
+    Currency,
+    Tax,
+    HostedInvoiceUrl,
+    InvoicePdfUrl,
+    InvoiceStatus,
+    PeriodStart,
+    PeriodEnd,
+    InvoicedAt,
+    CustomerCountry,
+    CustomerTaxExempt,
+    EventTimestamp,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Invoice {
+    pub invoice_id: Uuid,
+    pub stripe_invoice_id: String,
+    pub stripe_subscription_id: Option<String>,
+    pub amount_due: Option<i64>,
+    pub amount_paid: Option<i64>,
+    pub currency: Option<String>,
+    pub tax: Option<i64>,
+    pub hosted_invoice_url: Option<String>,
+    pub invoice_pdf_url: Option<String>,
+    pub invoice_status: Option<String>,
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub invoiced_at: Option<DateTime<Utc>>,
+    pub customer_country: Option<String>,
+    pub customer_tax_exempt: Option<String>,
+    pub event_timestamp: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Invoice {
+    const PUT_COLUMNS: [InvoiceIden; 15] = [
+        InvoiceIden::StripeInvoiceId,
+        InvoiceIden::StripeSubscriptionId,
+        InvoiceIden::AmountDue,
+        InvoiceIden::AmountPaid,
+        InvoiceIden::Currency,
+        InvoiceIden::Tax,
+        InvoiceIden::HostedInvoiceUrl,
+        InvoiceIden::InvoicePdfUrl,
+        InvoiceIden::InvoiceStatus,
+        InvoiceIden::PeriodStart,
+        InvoiceIden::PeriodEnd,
+        InvoiceIden::InvoicedAt,
+        InvoiceIden::CustomerCountry,
+        InvoiceIden::CustomerTaxExempt,
+        InvoiceIden::EventTimestamp,
+    ];
+
+    /// Inserts or updates the invoice. Returns `None` if the stored invoice
+    /// was written by an event newer than `event_timestamp`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put(
+        pool: &Pool,
+        stripe_invoice_id: &String,
+        stripe_subscription_id: Option<String>,
+        amount_due: Option<i64>,
+        amount_paid: Option<i64>,
+        currency: Option<String>,
+        tax: Option<i64>,
+        hosted_invoice_url: Option<String>,
+        invoice_pdf_url: Option<String>,
+        invoice_status: Option<String>,
+        period_start: Option<DateTime<Utc>>,
+        period_end: Option<DateTime<Utc>>,
+        invoiced_at: Option<DateTime<Utc>>,
+        customer_country: Option<String>,
+        customer_tax_exempt: Option<String>,
+        event_timestamp: i64,
+    ) -> Result<Option<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::insert()
+            .into_table(InvoiceIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_invoice_id.into(),
+                stripe_subscription_id.into(),
+                amount_due.into(),
+                amount_paid.into(),
+                currency.into(),
+                tax.into(),
+                hosted_invoice_url.into(),
+                invoice_pdf_url.into(),
+                invoice_status.into(),
+                period_start.into(),
+                period_end.into(),
+                invoiced_at.into(),
+                customer_country.into(),
+                customer_tax_exempt.into(),
+                event_timestamp.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(InvoiceIden::StripeInvoiceId)
+                    .update_columns(Self::PUT_COLUMNS)
+                    .action_and_where(
+                        Expr::col((
+                            InvoiceIden::Table,
+                            InvoiceIden::EventTimestamp,
+                        ))
+                        .lte(event_timestamp),
+                    )
+                    .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    pub async fn delete(
+        pool: &Pool,
+        stripe_invoice_id: &String,
+    ) -> Result<(), DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(InvoiceIden::Table)
+            .and_where(
+                Expr::col(InvoiceIden::StripeInvoiceId).eq(stripe_invoice_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    pub async fn list_by_stripe_subscription_id(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(InvoiceIden::Table)
+            .and_where(
+                Expr::col(InvoiceIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .order_by(InvoiceIden::CreatedAt, Order::Desc)
+            .limit(limit)
+            .offset(offset)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    pub async fn list_by_shop_id(
+        pool: &Pool,
+        shop_id: &Uuid,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column((InvoiceIden::Table, Asterisk))
+            .from(InvoiceIden::Table)
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::StripeSubscriptionId,
+                ))
+                .equals((
+                    InvoiceIden::Table,
+                    InvoiceIden::StripeSubscriptionId,
+                )),
+            )
+            .and_where(
+                Expr::col((SubscriptionIden::Table, SubscriptionIden::ShopId))
+                    .eq(*shop_id),
+            )
+            .order_by((InvoiceIden::Table, InvoiceIden::CreatedAt), Order::Desc)
+            .limit(limit)
+            .offset(offset)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Selects the Stripe ids of the invoices of all subscriptions of the
+    /// buyer.
+    pub(super) fn select_stripe_invoice_ids(
+        buyer_user_id: &String,
+    ) -> SelectStatement {
+        Query::select()
+            .column((InvoiceIden::Table, InvoiceIden::StripeInvoiceId))
+            .from(InvoiceIden::Table)
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::StripeSubscriptionId,
+                ))
+                .equals((
+                    InvoiceIden::Table,
+                    InvoiceIden::StripeSubscriptionId,
+                )),
+            )
+            .and_where(
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::BuyerUserId,
+                ))
+                .eq(buyer_user_id),
+            )
+            .to_owned()
+    }
+
+    pub async fn list_by_buyer_user_id(
+        pool: &Pool,
+        buyer_user_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column((InvoiceIden::Table, Asterisk))
+            .from(InvoiceIden::Table)
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::StripeSubscriptionId,
+                ))
+                .equals((
+                    InvoiceIden::Table,
+                    InvoiceIden::StripeSubscriptionId,
+                )),
+            )
+            .and_where(
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::BuyerUserId,
+                ))
+                .eq(buyer_user_id),
+            )
+            .order_by((InvoiceIden::Table, InvoiceIden::CreatedAt), Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+}
+
+impl From<Row> for Invoice {
+    fn from(row: Row) -> Self {
+        Self {
+            invoice_id: row.get(InvoiceIden::InvoiceId.to_string().as_str()),
+            stripe_invoice_id: row
+                .get(InvoiceIden::StripeInvoiceId.to_string().as_str()),
+            stripe_subscription_id: row
+                .get(InvoiceIden::StripeSubscriptionId.to_string().as_str()),
+            amount_due: row.get(InvoiceIden::AmountDue.to_string().as_str()),
+            amount_paid: row.get(InvoiceIden::AmountPaid.to_string().as_str()),
+            currency: row.get(InvoiceIden::Currency.to_string().as_str()),
+            tax: row.get(InvoiceIden::Tax.to_string().as_str()),
+            hosted_invoice_url: row
+                .get(InvoiceIden::HostedInvoiceUrl.to_string().as_str()),
+            invoice_pdf_url: row
+                .get(InvoiceIden::InvoicePdfUrl.to_string().as_str()),
+            invoice_status: row
+                .get(InvoiceIden::InvoiceStatus.to_string().as_str()),
+            period_start: row
+                .get(InvoiceIden::PeriodStart.to_string().as_str()),
+            period_end: row.get(InvoiceIden::PeriodEnd.to_string().as_str()),
+            invoiced_at: row.get(InvoiceIden::InvoicedAt.to_string().as_str()),
+            customer_country: row
+                .get(InvoiceIden::CustomerCountry.to_string().as_str()),
+            customer_tax_exempt: row
+                .get(InvoiceIden::CustomerTaxExempt.to_string().as_str()),
+            event_timestamp: row
+                .get(InvoiceIden::EventTimestamp.to_string().as_str()),
+            created_at: row.get(InvoiceIden::CreatedAt.to_string().as_str()),
+            updated_at: row.get(InvoiceIden::UpdatedAt.to_string().as_str()),
+        }
+    }
+}
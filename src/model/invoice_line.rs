@@ -0,0 +1,211 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use sea_query::{
+    Alias, Asterisk, Expr, Func, Iden, OnConflict, Order, PostgresQueryBuilder,
+    Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+
+use crate::DbError;
+
+use super::PlanChange;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "invoice_lines")]
+enum InvoiceLineIden {
+    Table,
+    StripeInvoiceId,
+    StripeInvoiceLineId,
+    StripeSubscriptionId,
+    StripeSubscriptionItemId,
+    PriceId,
+    UsageType,
+    Quantity,
+    Amount,
+    Currency,
+    Proration,
+    PeriodStart,
+    PeriodEnd,
+}
+
+/// Line of an invoice, i.e. the quantity and amount charged for one price in
+/// one period. For metered prices, `quantity` is the usage of the period.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvoiceLine {
+    pub stripe_invoice_line_id: String,
+    pub stripe_subscription_id: Option<String>,
+    pub stripe_subscription_item_id: Option<String>,
+    pub price_id: Option<String>,
+    pub usage_type: Option<String>,
+    pub quantity: Option<i64>,
+    pub amount: i64,
+    pub currency: String,
+    pub proration: bool,
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+}
+
+impl InvoiceLine {
+    const PUT_COLUMNS: [InvoiceLineIden; 12] = [
+        InvoiceLineIden::StripeInvoiceId,
+        InvoiceLineIden::StripeInvoiceLineId,
+        InvoiceLineIden::StripeSubscriptionId,
+        InvoiceLineIden::StripeSubscriptionItemId,
+        InvoiceLineIden::PriceId,
+        InvoiceLineIden::UsageType,
+        InvoiceLineIden::Quantity,
+        InvoiceLineIden::Amount,
+        InvoiceLineIden::Currency,
+        InvoiceLineIden::Proration,
+        InvoiceLineIden::PeriodStart,
+        InvoiceLineIden::PeriodEnd,
+    ];
+
+    /// Replaces the lines stored for the invoice.
+    pub async fn replace<'a>(
+        conn: &Transaction<'a>,
+        stripe_invoice_id: &String,
+        lines: &[Self],
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(InvoiceLineIden::Table)
+            .and_where(
+                Expr::col(InvoiceLineIden::StripeInvoiceId)
+                    .eq(stripe_invoice_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        for line in lines {
+            let (sql, values) = Query::insert()
+                .into_table(InvoiceLineIden::Table)
+                .columns(Self::PUT_COLUMNS)
+                .values([
+                    stripe_invoice_id.into(),
+                    line.stripe_invoice_line_id.as_str().into(),
+                    line.stripe_subscription_id.clone().into(),
+                    line.stripe_subscription_item_id.clone().into(),
+                    line.price_id.clone().into(),
+                    line.usage_type.clone().into(),
+                    line.quantity.into(),
+                    line.amount.into(),
+                    line.currency.as_str().into(),
+                    line.proration.into(),
+                    line.period_start.into(),
+                    line.period_end.into(),
+                ])?
+                .on_conflict(
+                    OnConflict::columns([
+                        InvoiceLineIden::StripeInvoiceId,
+                        InvoiceLineIden::StripeInvoiceLineId,
+                    ])
+                    .update_columns(Self::PUT_COLUMNS)
+                    .to_owned(),
+                )
+                .build_postgres(PostgresQueryBuilder);
+
+            conn.execute(sql.as_str(), &values.as_params()).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_by_stripe_invoice_id(
+        pool: &Pool,
+        stripe_invoice_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(InvoiceLineIden::Table)
+            .and_where(
+                Expr::col(InvoiceLineIden::StripeInvoiceId)
+                    .eq(stripe_invoice_id),
+            )
+            .order_by(InvoiceLineIden::PeriodStart, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Returns the latest invoice with proration lines of the subscription
+    /// item that are not attributed to a plan change yet, e.g. because the
+    /// invoice arrived before the change, and the sum of these lines.
+    pub async fn get_unattributed_proration(
+        conn: &impl GenericClient,
+        stripe_subscription_item_id: &String,
+    ) -> Result<Option<(String, i64)>, DbError> {
+        let (sql, values) = Query::select()
+            .column(InvoiceLineIden::StripeInvoiceId)
+            .expr_as(
+                Func::cast_as(
+                    Func::sum(Expr::col(InvoiceLineIden::Amount)),
+                    Alias::new("INT8"),
+                ),
+                Alias::new("proration_amount"),
+            )
+            .from(InvoiceLineIden::Table)
+            .and_where(
+                Expr::col(InvoiceLineIden::StripeSubscriptionItemId)
+                    .eq(stripe_subscription_item_id),
+            )
+            .and_where(Expr::col(InvoiceLineIden::Proration).eq(true))
+            .and_where(
+                Expr::col(InvoiceLineIden::StripeInvoiceId).not_in_subquery(
+                    PlanChange::select_stripe_invoice_ids(
+                        stripe_subscription_item_id,
+                    ),
+                ),
+            )
+            .group_by_col(InvoiceLineIden::StripeInvoiceId)
+            .order_by_expr(
+                Func::max(Expr::col(InvoiceLineIden::PeriodStart)).into(),
+                Order::Desc,
+            )
+            .limit(1)
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(|row| {
+            (
+                row.get(InvoiceLineIden::StripeInvoiceId.to_string().as_str()),
+                row.get("proration_amount"),
+            )
+        }))
+    }
+}
+
+impl From<Row> for InvoiceLine {
+    fn from(row: Row) -> Self {
+        Self {
+            stripe_invoice_line_id: row
+                .get(InvoiceLineIden::StripeInvoiceLineId.to_string().as_str()),
+            stripe_subscription_id: row.get(
+                InvoiceLineIden::StripeSubscriptionId.to_string().as_str(),
+            ),
+            stripe_subscription_item_id: row.get(
+                InvoiceLineIden::StripeSubscriptionItemId
+                    .to_string()
+                    .as_str(),
+            ),
+            price_id: row.get(InvoiceLineIden::PriceId.to_string().as_str()),
+            usage_type: row
+                .get(InvoiceLineIden::UsageType.to_string().as_str()),
+            quantity: row.get(InvoiceLineIden::Quantity.to_string().as_str()),
+            amount: row.get(InvoiceLineIden::Amount.to_string().as_str()),
+            currency: row.get(InvoiceLineIden::Currency.to_string().as_str()),
+            proration: row.get(InvoiceLineIden::Proration.to_string().as_str()),
+            period_start: row
+                .get(InvoiceLineIden::PeriodStart.to_string().as_str()),
+            period_end: row
+                .get(InvoiceLineIden::PeriodEnd.to_string().as_str()),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{Pool, Transaction};
+use sea_query::{
+    Alias, Expr, Func, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+    SimpleExpr,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+use super::invoice::InvoiceIden;
+use super::subscription::SubscriptionIden;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "invoice_tax_amounts")]
+enum InvoiceTaxAmountIden {
+    Table,
+    StripeInvoiceId,
+    StripeTaxRateId,
+    Amount,
+    TaxableAmount,
+    Inclusive,
+    TaxabilityReason,
+    DisplayName,
+    Percentage,
+    Country,
+    State,
+    Jurisdiction,
+    TaxType,
+}
+
+/// Tax charged on an invoice for one tax rate. The details of the tax rate
+/// are copied, so that reports do not depend on the rate as it is today.
+#[derive(Debug, Clone)]
+pub struct InvoiceTaxAmount {
+    pub stripe_tax_rate_id: String,
+    pub amount: i64,
+    pub taxable_amount: Option<i64>,
+    pub inclusive: bool,
+    pub taxability_reason: Option<String>,
+    pub display_name: Option<String>,
+    pub percentage: Option<f64>,
+    pub country: Option<String>,
+    pub state: Option<String>,
+    pub jurisdiction: Option<String>,
+    pub tax_type: Option<String>,
+}
+
+/// Tax collected by a shop for one tax rate, as needed for VAT returns.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvoiceTaxSummary {
+    pub country: Option<String>,
+    pub state: Option<String>,
+    pub jurisdiction: Option<String>,
+    pub tax_type: Option<String>,
+    pub display_name: Option<String>,
+    pub percentage: Option<f64>,
+    pub inclusive: bool,
+    pub currency: Option<String>,
+    pub invoice_count: i64,
+    pub taxable_amount: i64,
+    pub amount: i64,
+}
+
+impl InvoiceTaxAmount {
+    const PUT_COLUMNS: [InvoiceTaxAmountIden; 12] = [
+        InvoiceTaxAmountIden::StripeInvoiceId,
+        InvoiceTaxAmountIden::StripeTaxRateId,
+        InvoiceTaxAmountIden::Amount,
+        InvoiceTaxAmountIden::TaxableAmount,
+        InvoiceTaxAmountIden::Inclusive,
+        InvoiceTaxAmountIden::TaxabilityReason,
+        InvoiceTaxAmountIden::DisplayName,
+        InvoiceTaxAmountIden::Percentage,
+        InvoiceTaxAmountIden::Country,
+        InvoiceTaxAmountIden::State,
+        InvoiceTaxAmountIden::Jurisdiction,
+        InvoiceTaxAmountIden::TaxType,
+    ];
+
+    /// Replaces the tax amounts stored for the invoice.
+    pub async fn replace<'a>(
+        conn: &Transaction<'a>,
+        stripe_invoice_id: &String,
+        tax_amounts: &[Self],
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(InvoiceTaxAmountIden::Table)
+            .and_where(
+                Expr::col(InvoiceTaxAmountIden::StripeInvoiceId)
+                    .eq(stripe_invoice_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        for tax_amount in tax_amounts {
+            let (sql, values) = Query::insert()
+                .into_table(InvoiceTaxAmountIden::Table)
+                .columns(Self::PUT_COLUMNS)
+                .values([
+                    stripe_invoice_id.into(),
+                    tax_amount.stripe_tax_rate_id.as_str().into(),
+                    tax_amount.amount.into(),
+                    tax_amount.taxable_amount.into(),
+                    tax_amount.inclusive.into(),
+                    tax_amount.taxability_reason.clone().into(),
+                    tax_amount.display_name.clone().into(),
+                    tax_amount.percentage.into(),
+                    tax_amount.country.clone().into(),
+                    tax_amount.state.clone().into(),
+                    tax_amount.jurisdiction.clone().into(),
+                    tax_amount.tax_type.clone().into(),
+                ])?
+                .on_conflict(
+                    OnConflict::columns([
+                        InvoiceTaxAmountIden::StripeInvoiceId,
+                        InvoiceTaxAmountIden::StripeTaxRateId,
+                    ])
+                    .update_columns(Self::PUT_COLUMNS)
+                    .to_owned(),
+                )
+                .build_postgres(PostgresQueryBuilder);
+
+            conn.execute(sql.as_str(), &values.as_params()).await?;
+        }
+
+        Ok(())
+    }
+
+    fn sum(column: InvoiceTaxAmountIden) -> SimpleExpr {
+        Func::cast_as(
+            Expr::expr(Func::sum(Expr::col((
+                InvoiceTaxAmountIden::Table,
+                column,
+            ))))
+            .if_null(0),
+            Alias::new("INT8"),
+        )
+        .into()
+    }
+
+    /// Sums up the tax of the paid invoices of a shop that were issued
+    /// between `from` (inclusive) and `to` (exclusive), per tax rate and
+    /// currency.
+    pub async fn summarize_by_shop_id(
+        pool: &Pool,
+        shop_id: &Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<InvoiceTaxSummary>, DbError> {
+        let conn = pool.get().await?;
+
+        let group_columns = [
+            InvoiceTaxAmountIden::Country,
+            InvoiceTaxAmountIden::State,
+            InvoiceTaxAmountIden::Jurisdiction,
+            InvoiceTaxAmountIden::TaxType,
+            InvoiceTaxAmountIden::DisplayName,
+            InvoiceTaxAmountIden::Percentage,
+            InvoiceTaxAmountIden::Inclusive,
+        ]
+        .map(|c| (InvoiceTaxAmountIden::Table, c));
+
+        let (sql, values) = Query::select()
+            .columns(group_columns.clone())
+            .column((InvoiceIden::Table, InvoiceIden::Currency))
+            .expr_as(
+                Func::count_distinct(Expr::col((
+                    InvoiceTaxAmountIden::Table,
+                    InvoiceTaxAmountIden::StripeInvoiceId,
+                ))),
+                Alias::new("invoice_count"),
+            )
+            .expr_as(
+                Self::sum(InvoiceTaxAmountIden::TaxableAmount),
+                InvoiceTaxAmountIden::TaxableAmount,
+            )
+            .expr_as(
+                Self::sum(InvoiceTaxAmountIden::Amount),
+                InvoiceTaxAmountIden::Amount,
+            )
+            .from(InvoiceTaxAmountIden::Table)
+            .inner_join(
+                InvoiceIden::Table,
+                Expr::col((InvoiceIden::Table, InvoiceIden::StripeInvoiceId))
+                    .equals((
+                        InvoiceTaxAmountIden::Table,
+                        InvoiceTaxAmountIden::StripeInvoiceId,
+                    )),
+            )
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::StripeSubscriptionId,
+                ))
+                .equals((
+                    InvoiceIden::Table,
+                    InvoiceIden::StripeSubscriptionId,
+                )),
+            )
+            .and_where(
+                Expr::col((SubscriptionIden::Table, SubscriptionIden::ShopId))
+                    .eq(*shop_id),
+            )
+            .and_where(
+                Expr::col((InvoiceIden::Table, InvoiceIden::InvoiceStatus))
+                    .eq("paid"),
+            )
+            .and_where(
+                Expr::col((InvoiceIden::Table, InvoiceIden::InvoicedAt))
+                    .gte(from),
+            )
+            .and_where(
+                Expr::col((InvoiceIden::Table, InvoiceIden::InvoicedAt)).lt(to),
+            )
+            .group_by_columns(group_columns)
+            .group_by_col((InvoiceIden::Table, InvoiceIden::Currency))
+            .order_by(
+                (InvoiceTaxAmountIden::Table, InvoiceTaxAmountIden::Country),
+                Order::Asc,
+            )
+            .order_by(
+                (
+                    InvoiceTaxAmountIden::Table,
+                    InvoiceTaxAmountIden::Percentage,
+                ),
+                Order::Desc,
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(InvoiceTaxSummary::from).collect())
+    }
+}
+
+impl From<Row> for InvoiceTaxSummary {
+    fn from(row: Row) -> Self {
+        Self {
+            country: row
+                .get(InvoiceTaxAmountIden::Country.to_string().as_str()),
+            state: row.get(InvoiceTaxAmountIden::State.to_string().as_str()),
+            jurisdiction: row
+                .get(InvoiceTaxAmountIden::Jurisdiction.to_string().as_str()),
+            tax_type: row
+                .get(InvoiceTaxAmountIden::TaxType.to_string().as_str()),
+            display_name: row
+                .get(InvoiceTaxAmountIden::DisplayName.to_string().as_str()),
+            percentage: row
+                .get(InvoiceTaxAmountIden::Percentage.to_string().as_str()),
+            inclusive: row
+                .get(InvoiceTaxAmountIden::Inclusive.to_string().as_str()),
+            currency: row.get(InvoiceIden::Currency.to_string().as_str()),
+            invoice_count: row.get("invoice_count"),
+            taxable_amount: row
+                .get(InvoiceTaxAmountIden::TaxableAmount.to_string().as_str()),
+            amount: row.get(InvoiceTaxAmountIden::Amount.to_string().as_str()),
+        }
+    }
+}
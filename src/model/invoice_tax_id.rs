@@ -0,0 +1,119 @@
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Transaction};
+use sea_query::{Expr, Iden, OnConflict, PostgresQueryBuilder, Query};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+
+use crate::DbError;
+
+use super::Invoice;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "invoice_tax_ids")]
+enum InvoiceTaxIdIden {
+    Table,
+    StripeInvoiceId,
+    TaxIdType,
+    Value,
+}
+
+/// Tax id of the customer as shown on an invoice, e.g. an EU VAT number.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvoiceTaxId {
+    pub tax_id_type: String,
+    pub value: String,
+}
+
+impl InvoiceTaxId {
+    /// Replaces the customer tax ids stored for the invoice.
+    pub async fn replace<'a>(
+        conn: &Transaction<'a>,
+        stripe_invoice_id: &String,
+        tax_ids: &[Self],
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(InvoiceTaxIdIden::Table)
+            .and_where(
+                Expr::col(InvoiceTaxIdIden::StripeInvoiceId)
+                    .eq(stripe_invoice_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        for tax_id in tax_ids {
+            let (sql, values) = Query::insert()
+                .into_table(InvoiceTaxIdIden::Table)
+                .columns([
+                    InvoiceTaxIdIden::StripeInvoiceId,
+                    InvoiceTaxIdIden::TaxIdType,
+                    InvoiceTaxIdIden::Value,
+                ])
+                .values([
+                    stripe_invoice_id.into(),
+                    tax_id.tax_id_type.as_str().into(),
+                    tax_id.value.as_str().into(),
+                ])?
+                .on_conflict(
+                    OnConflict::columns([
+                        InvoiceTaxIdIden::StripeInvoiceId,
+                        InvoiceTaxIdIden::TaxIdType,
+                        InvoiceTaxIdIden::Value,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .build_postgres(PostgresQueryBuilder);
+
+            conn.execute(sql.as_str(), &values.as_params()).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn list(
+        conn: &impl GenericClient,
+        stripe_invoice_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let (sql, values) = Query::select()
+            .columns([InvoiceTaxIdIden::TaxIdType, InvoiceTaxIdIden::Value])
+            .from(InvoiceTaxIdIden::Table)
+            .and_where(
+                Expr::col(InvoiceTaxIdIden::StripeInvoiceId)
+                    .eq(stripe_invoice_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Deletes the tax ids of all invoices of the buyer. Returns the number
+    /// of deleted tax ids.
+    pub async fn delete_by_buyer_user_id<'a>(
+        conn: &Transaction<'a>,
+        buyer_user_id: &String,
+    ) -> Result<u64, DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(InvoiceTaxIdIden::Table)
+            .and_where(
+                Expr::col(InvoiceTaxIdIden::StripeInvoiceId).in_subquery(
+                    Invoice::select_stripe_invoice_ids(buyer_user_id),
+                ),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        Ok(conn.execute(sql.as_str(), &values.as_params()).await?)
+    }
+}
+
+impl From<Row> for InvoiceTaxId {
+    fn from(row: Row) -> Self {
+        Self {
+            tax_id_type: row
+                .get(InvoiceTaxIdIden::TaxIdType.to_string().as_str()),
+            value: row.get(InvoiceTaxIdIden::Value.to_string().as_str()),
+        }
+    }
+}
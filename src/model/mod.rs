@@ -1,3 +1,49 @@
+mod coupon;
+mod customer;
+mod discount;
+mod discount_amount;
+mod grace_period;
+mod invoice;
+mod invoice_line;
+mod invoice_tax_amount;
+mod invoice_tax_id;
+mod payment_attempt;
+mod payment_method;
+mod pending_update;
+mod plan_change;
+mod promotion_code;
+mod reconcile_attempt;
 mod subscription;
+mod subscription_event;
+mod subscription_item;
+mod subscription_schedule;
+mod subscription_schedule_phase;
+mod subscription_status;
+mod upcoming_invoice;
+mod usage_record;
 
+pub use coupon::Coupon;
+pub use customer::{Customer, CustomerAddress};
+pub use discount::Discount;
+pub use discount_amount::DiscountAmount;
+pub use grace_period::GracePeriod;
+pub use invoice::Invoice;
+pub use invoice_line::InvoiceLine;
+pub use invoice_tax_amount::InvoiceTaxAmount;
+pub use invoice_tax_id::InvoiceTaxId;
+pub use payment_attempt::PaymentAttempt;
+pub use payment_method::PaymentMethod;
+pub use pending_update::{PendingUpdate, PendingUpdateItem};
+pub use plan_change::{PlanChange, PlanItem};
+pub use promotion_code::PromotionCode;
+pub use reconcile_attempt::ReconcileAttempt;
 pub use subscription::Subscription;
+pub use subscription_event::SubscriptionEvent;
+pub use subscription_item::SubscriptionItem;
+pub use subscription_schedule::SubscriptionSchedule;
+pub use subscription_schedule_phase::{
+    SubscriptionSchedulePhase, SubscriptionSchedulePhaseItem,
+};
+pub use subscription_status::SubscriptionStatus;
+pub use upcoming_invoice::UpcomingInvoice;
+pub use usage_record::UsageRecord;
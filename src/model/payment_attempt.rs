@@ -0,0 +1,294 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{Pool, Transaction};
+use sea_query::{
+    Asterisk, Cond, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+use super::invoice::InvoiceIden;
+use super::subscription::SubscriptionIden;
+use super::{Customer, Invoice};
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "payment_attempts")]
+enum PaymentAttemptIden {
+    Table,
+    PaymentAttemptId,
+    StripeEventId,
+    EventType,
+    StripeIntentId,
+    IntentType,
+    IntentStatus,
+    StripeInvoiceId,
+    StripeCustomerId,
+    StripePaymentMethodId,
+    Amount,
+    Currency,
+    NextActionType,
+    ErrorType,
+    ErrorCode,
+    DeclineCode,
+    ErrorMessage,
+    EventTimestamp,
+    CreatedAt,
+}
+
+/// State of a payment or setup intent as reported by one event, including the
+/// action the customer was asked for (e.g. 3D Secure) and the last error.
+#[derive(Debug, Clone, Serialize)]
+pub struct PaymentAttempt {
+    pub payment_attempt_id: Uuid,
+    pub stripe_event_id: String,
+    pub event_type: String,
+    pub stripe_intent_id: String,
+    pub intent_type: String,
+    pub intent_status: String,
+    pub stripe_invoice_id: Option<String>,
+    pub stripe_customer_id: Option<String>,
+    pub stripe_payment_method_id: Option<String>,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    pub next_action_type: Option<String>,
+    pub error_type: Option<String>,
+    pub error_code: Option<String>,
+    pub decline_code: Option<String>,
+    pub error_message: Option<String>,
+    pub event_timestamp: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PaymentAttempt {
+    pub const INTENT_TYPE_PAYMENT: &'static str = "payment_intent";
+    pub const INTENT_TYPE_SETUP: &'static str = "setup_intent";
+
+    const PUT_COLUMNS: [PaymentAttemptIden; 16] = [
+        PaymentAttemptIden::StripeEventId,
+        PaymentAttemptIden::EventType,
+        PaymentAttemptIden::StripeIntentId,
+        PaymentAttemptIden::IntentType,
+        PaymentAttemptIden::IntentStatus,
+        PaymentAttemptIden::StripeInvoiceId,
+        PaymentAttemptIden::StripeCustomerId,
+        PaymentAttemptIden::StripePaymentMethodId,
+        PaymentAttemptIden::Amount,
+        PaymentAttemptIden::Currency,
+        PaymentAttemptIden::NextActionType,
+        PaymentAttemptIden::ErrorType,
+        PaymentAttemptIden::ErrorCode,
+        PaymentAttemptIden::DeclineCode,
+        PaymentAttemptIden::ErrorMessage,
+        PaymentAttemptIden::EventTimestamp,
+    ];
+
+    /// Records the state of the intent reported by the event. Redelivered
+    /// events are ignored.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record(
+        pool: &Pool,
+        stripe_event_id: &String,
+        event_type: &String,
+        stripe_intent_id: &String,
+        intent_type: &str,
+        intent_status: &String,
+        stripe_invoice_id: Option<String>,
+        stripe_customer_id: Option<String>,
+        stripe_payment_method_id: Option<String>,
+        amount: Option<i64>,
+        currency: Option<String>,
+        next_action_type: Option<String>,
+        error_type: Option<String>,
+        error_code: Option<String>,
+        decline_code: Option<String>,
+        error_message: Option<String>,
+        event_timestamp: i64,
+    ) -> Result<(), DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::insert()
+            .into_table(PaymentAttemptIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_event_id.into(),
+                event_type.into(),
+                stripe_intent_id.into(),
+                intent_type.into(),
+                intent_status.into(),
+                stripe_invoice_id.into(),
+                stripe_customer_id.into(),
+                stripe_payment_method_id.into(),
+                amount.into(),
+                currency.into(),
+                next_action_type.into(),
+                error_type.into(),
+                error_code.into(),
+                decline_code.into(),
+                error_message.into(),
+                event_timestamp.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(PaymentAttemptIden::StripeEventId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    /// Lists the attempts to pay the invoices of the subscription, together
+    /// with the setup intents of its customer, newest first.
+    pub async fn list_by_stripe_subscription_id(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let invoices = Query::select()
+            .column(InvoiceIden::StripeInvoiceId)
+            .from(InvoiceIden::Table)
+            .and_where(
+                Expr::col(InvoiceIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .to_owned();
+
+        let customers = Query::select()
+            .column(SubscriptionIden::StripeCustomerId)
+            .from(SubscriptionIden::Table)
+            .and_where(
+                Expr::col(SubscriptionIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .to_owned();
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(PaymentAttemptIden::Table)
+            .cond_where(
+                Cond::any()
+                    .add(
+                        Expr::col(PaymentAttemptIden::StripeInvoiceId)
+                            .in_subquery(invoices),
+                    )
+                    .add(
+                        Cond::all()
+                            .add(
+                                Expr::col(PaymentAttemptIden::IntentType)
+                                    .eq(Self::INTENT_TYPE_SETUP),
+                            )
+                            .add(
+                                Expr::col(PaymentAttemptIden::StripeCustomerId)
+                                    .in_subquery(customers),
+                            ),
+                    ),
+            )
+            .order_by(PaymentAttemptIden::EventTimestamp, Order::Desc)
+            .limit(limit)
+            .offset(offset)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Matches the attempts to pay invoices of the buyer and all intents of
+    /// customers of the buyer.
+    fn of_buyer(buyer_user_id: &String) -> Cond {
+        Cond::any()
+            .add(
+                Expr::col(PaymentAttemptIden::StripeInvoiceId).in_subquery(
+                    Invoice::select_stripe_invoice_ids(buyer_user_id),
+                ),
+            )
+            .add(Expr::col(PaymentAttemptIden::StripeCustomerId).in_subquery(
+                Customer::select_stripe_customer_ids(buyer_user_id),
+            ))
+    }
+
+    pub async fn list_by_buyer_user_id(
+        pool: &Pool,
+        buyer_user_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(PaymentAttemptIden::Table)
+            .cond_where(Self::of_buyer(buyer_user_id))
+            .order_by(PaymentAttemptIden::EventTimestamp, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Deletes all attempts of the buyer. Returns the number of deleted
+    /// attempts.
+    pub async fn delete_by_buyer_user_id<'a>(
+        conn: &Transaction<'a>,
+        buyer_user_id: &String,
+    ) -> Result<u64, DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(PaymentAttemptIden::Table)
+            .cond_where(Self::of_buyer(buyer_user_id))
+            .build_postgres(PostgresQueryBuilder);
+
+        Ok(conn.execute(sql.as_str(), &values.as_params()).await?)
+    }
+}
+
+impl From<Row> for PaymentAttempt {
+    fn from(row: Row) -> Self {
+        Self {
+            payment_attempt_id: row
+                .get(PaymentAttemptIden::PaymentAttemptId.to_string().as_str()),
+            stripe_event_id: row
+                .get(PaymentAttemptIden::StripeEventId.to_string().as_str()),
+            event_type: row
+                .get(PaymentAttemptIden::EventType.to_string().as_str()),
+            stripe_intent_id: row
+                .get(PaymentAttemptIden::StripeIntentId.to_string().as_str()),
+            intent_type: row
+                .get(PaymentAttemptIden::IntentType.to_string().as_str()),
+            intent_status: row
+                .get(PaymentAttemptIden::IntentStatus.to_string().as_str()),
+            stripe_invoice_id: row
+                .get(PaymentAttemptIden::StripeInvoiceId.to_string().as_str()),
+            stripe_customer_id: row
+                .get(PaymentAttemptIden::StripeCustomerId.to_string().as_str()),
+            stripe_payment_method_id: row.get(
+                PaymentAttemptIden::StripePaymentMethodId
+                    .to_string()
+                    .as_str(),
+            ),
+            amount: row.get(PaymentAttemptIden::Amount.to_string().as_str()),
+            currency: row
+                .get(PaymentAttemptIden::Currency.to_string().as_str()),
+            next_action_type: row
+                .get(PaymentAttemptIden::NextActionType.to_string().as_str()),
+            error_type: row
+                .get(PaymentAttemptIden::ErrorType.to_string().as_str()),
+            error_code: row
+                .get(PaymentAttemptIden::ErrorCode.to_string().as_str()),
+            decline_code: row
+                .get(PaymentAttemptIden::DeclineCode.to_string().as_str()),
+            error_message: row
+                .get(PaymentAttemptIden::ErrorMessage.to_string().as_str()),
+            event_timestamp: row
+                .get(PaymentAttemptIden::EventTimestamp.to_string().as_str()),
+            created_at: row
+                .get(PaymentAttemptIden::CreatedAt.to_string().as_str()),
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use sea_query::{
+    Alias, Asterisk, Expr, Func, Iden, OnConflict, Order, PostgresQueryBuilder,
+    Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+use super::customer::CustomerIden;
+use super::Customer;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "payment_methods")]
+enum PaymentMethodIden {
+    Table,
+    PaymentMethodId,
+    StripePaymentMethodId,
+    StripeCustomerId,
+    PaymentMethodType,
+    CardBrand,
+    CardLast4,
+    CardExpMonth,
+    CardExpYear,
+    ExpiresAt,
+    DetachedAt,
+    ExpiryWarnedAt,
+    EventTimestamp,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PaymentMethod {
+    pub payment_method_id: Uuid,
+    pub stripe_payment_method_id: String,
+    pub stripe_customer_id: Option<String>,
+    pub payment_method_type: String,
+    pub card_brand: Option<String>,
+    pub card_last4: Option<String>,
+    pub card_exp_month: Option<i64>,
+    pub card_exp_year: Option<i64>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub detached_at: Option<DateTime<Utc>>,
+    pub expiry_warned_at: Option<DateTime<Utc>>,
+    pub event_timestamp: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PaymentMethod {
+    const PUT_COLUMNS: [PaymentMethodIden; 10] = [
+        PaymentMethodIden::StripePaymentMethodId,
+        PaymentMethodIden::StripeCustomerId,
+        PaymentMethodIden::PaymentMethodType,
+        PaymentMethodIden::CardBrand,
+        PaymentMethodIden::CardLast4,
+        PaymentMethodIden::CardExpMonth,
+        PaymentMethodIden::CardExpYear,
+        PaymentMethodIden::ExpiresAt,
+        PaymentMethodIden::DetachedAt,
+        PaymentMethodIden::EventTimestamp,
+    ];
+
+    /// Returns the time a card expires at, i.e. the start of the month after
+    /// its expiry month.
+    pub fn card_expires_at(
+        exp_month: i64,
+        exp_year: i64,
+    ) -> Option<DateTime<Utc>> {
+        let (year, month) = if exp_month >= 12 {
+            (exp_year + 1, 1)
+        } else {
+            (exp_year, exp_month + 1)
+        };
+
+        NaiveDate::from_ymd_opt(
+            year.try_into().ok()?,
+            month.try_into().ok()?,
+            1,
+        )?
+        .and_hms_opt(0, 0, 0)
+        .map(|t| t.and_utc())
+    }
+
+    /// Inserts or updates the payment method. A changed expiry, e.g. after
+    /// the card was renewed, allows another warning. The customer is kept if
+    /// not given, as detached payment methods no longer reference it. Returns
+    /// `None` if the stored payment method was written by an event newer than
+    /// `event_timestamp`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put(
+        pool: &Pool,
+        stripe_payment_method_id: &String,
+        stripe_customer_id: Option<String>,
+        payment_method_type: &String,
+        card_brand: Option<String>,
+        card_last4: Option<String>,
+        card_exp_month: Option<i64>,
+        card_exp_year: Option<i64>,
+        detached_at: Option<DateTime<Utc>>,
+        event_timestamp: i64,
+    ) -> Result<Option<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let expires_at = card_exp_month
+            .zip(card_exp_year)
+            .and_then(|(month, year)| Self::card_expires_at(month, year));
+
+        let (sql, values) = Query::insert()
+            .into_table(PaymentMethodIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_payment_method_id.into(),
+                stripe_customer_id.into(),
+                payment_method_type.into(),
+                card_brand.into(),
+                card_last4.into(),
+                card_exp_month.into(),
+                card_exp_year.into(),
+                expires_at.into(),
+                detached_at.into(),
+                event_timestamp.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(PaymentMethodIden::StripePaymentMethodId)
+                    .update_columns([
+                        PaymentMethodIden::PaymentMethodType,
+                        PaymentMethodIden::CardBrand,
+                        PaymentMethodIden::CardLast4,
+                        PaymentMethodIden::CardExpMonth,
+                        PaymentMethodIden::CardExpYear,
+                        PaymentMethodIden::ExpiresAt,
+                        PaymentMethodIden::DetachedAt,
+                        PaymentMethodIden::EventTimestamp,
+                    ])
+                    .value(
+                        PaymentMethodIden::StripeCustomerId,
+                        Func::coalesce([
+                            Expr::col((
+                                Alias::new("excluded"),
+                                PaymentMethodIden::StripeCustomerId,
+                            ))
+                            .into(),
+                            Expr::col((
+                                PaymentMethodIden::Table,
+                                PaymentMethodIden::StripeCustomerId,
+                            ))
+                            .into(),
+                        ]),
+                    )
+                    .value(
+                        PaymentMethodIden::ExpiryWarnedAt,
+                        Expr::case(
+                            Expr::col((
+                                Alias::new("excluded"),
+                                PaymentMethodIden::ExpiresAt,
+                            ))
+                            .ne(Expr::col((
+                                PaymentMethodIden::Table,
+                                PaymentMethodIden::ExpiresAt,
+                            ))),
+                            Option::<DateTime<Utc>>::None,
+                        )
+                        .finally(Expr::col((
+                            PaymentMethodIden::Table,
+                            PaymentMethodIden::ExpiryWarnedAt,
+                        ))),
+                    )
+                    .action_and_where(
+                        Expr::col((
+                            PaymentMethodIden::Table,
+                            PaymentMethodIden::EventTimestamp,
+                        ))
+                        .lte(event_timestamp),
+                    )
+                    .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Lists the attached default payment methods of customers that expire
+    /// before `expires_before` and were not warned about yet.
+    pub async fn list_expiring_defaults(
+        pool: &Pool,
+        expires_before: DateTime<Utc>,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column((PaymentMethodIden::Table, Asterisk))
+            .from(PaymentMethodIden::Table)
+            .inner_join(
+                CustomerIden::Table,
+                Expr::col((
+                    CustomerIden::Table,
+                    CustomerIden::StripeCustomerId,
+                ))
+                .equals((
+                    PaymentMethodIden::Table,
+                    PaymentMethodIden::StripeCustomerId,
+                ))
+                .and(
+                    Expr::col((
+                        CustomerIden::Table,
+                        CustomerIden::DefaultPaymentMethodId,
+                    ))
+                    .equals((
+                        PaymentMethodIden::Table,
+                        PaymentMethodIden::StripePaymentMethodId,
+                    )),
+                ),
+            )
+            .and_where(
+                Expr::col((
+                    PaymentMethodIden::Table,
+                    PaymentMethodIden::DetachedAt,
+                ))
+                .is_null(),
+            )
+            .and_where(
+                Expr::col((
+                    PaymentMethodIden::Table,
+                    PaymentMethodIden::ExpiryWarnedAt,
+                ))
+                .is_null(),
+            )
+            .and_where(
+                Expr::col((
+                    PaymentMethodIden::Table,
+                    PaymentMethodIden::ExpiresAt,
+                ))
+                .lte(expires_before),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    pub async fn list_by_stripe_customer_id(
+        pool: &Pool,
+        stripe_customer_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(PaymentMethodIden::Table)
+            .and_where(
+                Expr::col(PaymentMethodIden::StripeCustomerId)
+                    .eq(stripe_customer_id),
+            )
+            .order_by(PaymentMethodIden::CreatedAt, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    pub async fn set_expiry_warned(
+        pool: &Pool,
+        payment_method_id: &Uuid,
+    ) -> Result<(), DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::update()
+            .table(PaymentMethodIden::Table)
+            .value(PaymentMethodIden::ExpiryWarnedAt, Expr::current_timestamp())
+            .and_where(
+                Expr::col(PaymentMethodIden::PaymentMethodId)
+                    .eq(*payment_method_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    /// Deletes the payment methods of all customers of the buyer. Returns the
+    /// number of deleted payment methods.
+    pub async fn delete_by_buyer_user_id<'a>(
+        conn: &Transaction<'a>,
+        buyer_user_id: &String,
+    ) -> Result<u64, DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(PaymentMethodIden::Table)
+            .and_where(
+                Expr::col(PaymentMethodIden::StripeCustomerId).in_subquery(
+                    Customer::select_stripe_customer_ids(buyer_user_id),
+                ),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        Ok(conn.execute(sql.as_str(), &values.as_params()).await?)
+    }
+
+    pub async fn delete_by_stripe_customer_id(
+        conn: &impl GenericClient,
+        stripe_customer_id: &String,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(PaymentMethodIden::Table)
+            .and_where(
+                Expr::col(PaymentMethodIden::StripeCustomerId)
+                    .eq(stripe_customer_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
+
+impl From<Row> for PaymentMethod {
+    fn from(row: Row) -> Self {
+        Self {
+            payment_method_id: row
+                .get(PaymentMethodIden::PaymentMethodId.to_string().as_str()),
+            stripe_payment_method_id: row.get(
+                PaymentMethodIden::StripePaymentMethodId
+                    .to_string()
+                    .as_str(),
+            ),
+            stripe_customer_id: row
+                .get(PaymentMethodIden::StripeCustomerId.to_string().as_str()),
+            payment_method_type: row
+                .get(PaymentMethodIden::PaymentMethodType.to_string().as_str()),
+            card_brand: row
+                .get(PaymentMethodIden::CardBrand.to_string().as_str()),
+            card_last4: row
+                .get(PaymentMethodIden::CardLast4.to_string().as_str()),
+            card_exp_month: row
+                .get(PaymentMethodIden::CardExpMonth.to_string().as_str()),
+            card_exp_year: row
+                .get(PaymentMethodIden::CardExpYear.to_string().as_str()),
+            expires_at: row
+                .get(PaymentMethodIden::ExpiresAt.to_string().as_str()),
+            detached_at: row
+                .get(PaymentMethodIden::DetachedAt.to_string().as_str()),
+            expiry_warned_at: row
+                .get(PaymentMethodIden::ExpiryWarnedAt.to_string().as_str()),
+            event_timestamp: row
+                .get(PaymentMethodIden::EventTimestamp.to_string().as_str()),
+            created_at: row
+                .get(PaymentMethodIden::CreatedAt.to_string().as_str()),
+            updated_at: row
+                .get(PaymentMethodIden::UpdatedAt.to_string().as_str()),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "pending_updates")]
+enum PendingUpdateIden {
+    Table,
+    PendingUpdateId,
+    StripeSubscriptionId,
+    ExpiresAt,
+    BillingCycleAnchor,
+    TrialEnd,
+    TrialFromPlan,
+    UpdateStatus,
+    ResolvedAt,
+    ResolvingStripeEventId,
+    EventTimestamp,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "pending_update_items")]
+enum PendingUpdateItemIden {
+    Table,
+    PendingUpdateId,
+    StripeSubscriptionItemId,
+    PriceId,
+    Quantity,
+    UnitAmount,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingUpdateItem {
+    pub stripe_subscription_item_id: String,
+    pub price_id: Option<String>,
+    pub quantity: Option<i64>,
+    pub unit_amount: Option<i64>,
+}
+
+/// Update of a subscription that only takes effect once its invoice is paid,
+/// e.g. an upgrade. Stays `pending` until Stripe reports that it was applied
+/// or that it expired.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingUpdate {
+    pub pending_update_id: Uuid,
+    pub stripe_subscription_id: String,
+    pub expires_at: DateTime<Utc>,
+    pub billing_cycle_anchor: Option<DateTime<Utc>>,
+    pub trial_end: Option<DateTime<Utc>>,
+    pub trial_from_plan: Option<bool>,
+    pub update_status: String,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolving_stripe_event_id: Option<String>,
+    pub event_timestamp: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub items: Vec<PendingUpdateItem>,
+}
+
+impl PendingUpdate {
+    pub const STATUS_PENDING: &'static str = "pending";
+    pub const STATUS_APPLIED: &'static str = "applied";
+    pub const STATUS_EXPIRED: &'static str = "expired";
+
+    const PUT_COLUMNS: [PendingUpdateIden; 6] = [
+        PendingUpdateIden::StripeSubscriptionId,
+        PendingUpdateIden::ExpiresAt,
+        PendingUpdateIden::BillingCycleAnchor,
+        PendingUpdateIden::TrialEnd,
+        PendingUpdateIden::TrialFromPlan,
+        PendingUpdateIden::EventTimestamp,
+    ];
+
+    const PUT_ITEM_COLUMNS: [PendingUpdateItemIden; 5] = [
+        PendingUpdateItemIden::PendingUpdateId,
+        PendingUpdateItemIden::StripeSubscriptionItemId,
+        PendingUpdateItemIden::PriceId,
+        PendingUpdateItemIden::Quantity,
+        PendingUpdateItemIden::UnitAmount,
+    ];
+
+    /// Stores the pending update of the subscription together with the items
+    /// it would apply. Stripe does not assign ids to pending updates, so they
+    /// are identified by subscription and expiry. Returns `None` if the update
+    /// was stored or resolved from a newer event.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put<'a>(
+        conn: &Transaction<'a>,
+        stripe_subscription_id: &String,
+        expires_at: DateTime<Utc>,
+        billing_cycle_anchor: Option<DateTime<Utc>>,
+        trial_end: Option<DateTime<Utc>>,
+        trial_from_plan: Option<bool>,
+        items: Vec<PendingUpdateItem>,
+        event_timestamp: i64,
+    ) -> Result<Option<Self>, DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(PendingUpdateIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_subscription_id.into(),
+                expires_at.into(),
+                billing_cycle_anchor.into(),
+                trial_end.into(),
+                trial_from_plan.into(),
+                event_timestamp.into(),
+            ])?
+            .on_conflict(
+                OnConflict::columns([
+                    PendingUpdateIden::StripeSubscriptionId,
+                    PendingUpdateIden::ExpiresAt,
+                ])
+                .update_columns(Self::PUT_COLUMNS)
+                .action_and_where(
+                    Expr::col((
+                        PendingUpdateIden::Table,
+                        PendingUpdateIden::EventTimestamp,
+                    ))
+                    .lte(event_timestamp),
+                )
+                .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let Some(row) =
+            conn.query_opt(sql.as_str(), &values.as_params()).await?
+        else {
+            return Ok(None);
+        };
+
+        let mut pending_update = Self::from(row);
+
+        let (sql, values) = Query::delete()
+            .from_table(PendingUpdateItemIden::Table)
+            .and_where(
+                Expr::col(PendingUpdateItemIden::PendingUpdateId)
+                    .eq(pending_update.pending_update_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        for item in items.iter() {
+            let (sql, values) = Query::insert()
+                .into_table(PendingUpdateItemIden::Table)
+                .columns(Self::PUT_ITEM_COLUMNS)
+                .values([
+                    pending_update.pending_update_id.into(),
+                    item.stripe_subscription_item_id.as_str().into(),
+                    item.price_id.clone().into(),
+                    item.quantity.into(),
+                    item.unit_amount.into(),
+                ])?
+                .build_postgres(PostgresQueryBuilder);
+
+            conn.execute(sql.as_str(), &values.as_params()).await?;
+        }
+
+        pending_update.items = items;
+
+        Ok(Some(pending_update))
+    }
+
+    /// Marks the pending updates of the subscription that were stored from
+    /// events up to `event_timestamp` as applied or expired. Returns the
+    /// updates that were still pending.
+    pub async fn resolve(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+        update_status: &str,
+        resolving_stripe_event_id: &String,
+        event_timestamp: i64,
+    ) -> Result<Vec<Self>, DbError> {
+        let resolved_at =
+            DateTime::<Utc>::from_timestamp(event_timestamp, 0).unwrap();
+
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::update()
+            .table(PendingUpdateIden::Table)
+            .values([
+                (PendingUpdateIden::UpdateStatus, update_status.into()),
+                (PendingUpdateIden::ResolvedAt, resolved_at.into()),
+                (
+                    PendingUpdateIden::ResolvingStripeEventId,
+                    resolving_stripe_event_id.into(),
+                ),
+                (PendingUpdateIden::EventTimestamp, event_timestamp.into()),
+            ])
+            .and_where(
+                Expr::col(PendingUpdateIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .and_where(
+                Expr::col(PendingUpdateIden::UpdateStatus)
+                    .eq(Self::STATUS_PENDING),
+            )
+            .and_where(
+                Expr::col(PendingUpdateIden::EventTimestamp)
+                    .lte(event_timestamp),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    pub async fn list_by_stripe_subscription_id(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(PendingUpdateIden::Table)
+            .and_where(
+                Expr::col(PendingUpdateIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .order_by(PendingUpdateIden::CreatedAt, Order::Desc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        let mut pending_updates: Vec<Self> =
+            rows.into_iter().map(Self::from).collect();
+
+        for pending_update in pending_updates.iter_mut() {
+            pending_update.items =
+                Self::list_items(&conn, &pending_update.pending_update_id)
+                    .await?;
+        }
+
+        Ok(pending_updates)
+    }
+
+    async fn list_items(
+        conn: &impl GenericClient,
+        pending_update_id: &Uuid,
+    ) -> Result<Vec<PendingUpdateItem>, DbError> {
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(PendingUpdateItemIden::Table)
+            .and_where(
+                Expr::col(PendingUpdateItemIden::PendingUpdateId)
+                    .eq(*pending_update_id),
+            )
+            .order_by(
+                PendingUpdateItemIden::StripeSubscriptionItemId,
+                Order::Asc,
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(PendingUpdateItem::from).collect())
+    }
+}
+
+impl From<Row> for PendingUpdate {
+    fn from(row: Row) -> Self {
+        Self {
+            pending_update_id: row
+                .get(PendingUpdateIden::PendingUpdateId.to_string().as_str()),
+            stripe_subscription_id: row.get(
+                PendingUpdateIden::StripeSubscriptionId.to_string().as_str(),
+            ),
+            expires_at: row
+                .get(PendingUpdateIden::ExpiresAt.to_string().as_str()),
+            billing_cycle_anchor: row.get(
+                PendingUpdateIden::BillingCycleAnchor.to_string().as_str(),
+            ),
+            trial_end: row
+                .get(PendingUpdateIden::TrialEnd.to_string().as_str()),
+            trial_from_plan: row
+                .get(PendingUpdateIden::TrialFromPlan.to_string().as_str()),
+            update_status: row
+                .get(PendingUpdateIden::UpdateStatus.to_string().as_str()),
+            resolved_at: row
+                .get(PendingUpdateIden::ResolvedAt.to_string().as_str()),
+            resolving_stripe_event_id: row.get(
+                PendingUpdateIden::ResolvingStripeEventId
+                    .to_string()
+                    .as_str(),
+            ),
+            event_timestamp: row
+                .get(PendingUpdateIden::EventTimestamp.to_string().as_str()),
+            created_at: row
+                .get(PendingUpdateIden::CreatedAt.to_string().as_str()),
+            updated_at: row
+                .get(PendingUpdateIden::UpdatedAt.to_string().as_str()),
+            items: Vec::new(),
+        }
+    }
+}
+
+impl From<Row> for PendingUpdateItem {
+    fn from(row: Row) -> Self {
+        Self {
+            stripe_subscription_item_id: row.get(
+                PendingUpdateItemIden::StripeSubscriptionItemId
+                    .to_string()
+                    .as_str(),
+            ),
+            price_id: row
+                .get(PendingUpdateItemIden::PriceId.to_string().as_str()),
+            quantity: row
+                .get(PendingUpdateItemIden::Quantity.to_string().as_str()),
+            unit_amount: row
+                .get(PendingUpdateItemIden::UnitAmount.to_string().as_str()),
+        }
+    }
+}
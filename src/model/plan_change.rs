@@ -0,0 +1,244 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use sea_query::{
+    Asterisk, Cond, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+    SelectStatement,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "plan_changes")]
+enum PlanChangeIden {
+    Table,
+    PlanChangeId,
+    StripeEventId,
+    StripeSubscriptionId,
+    StripeSubscriptionItemId,
+    OldPriceId,
+    NewPriceId,
+    OldQuantity,
+    NewQuantity,
+    OldUnitAmount,
+    NewUnitAmount,
+    Currency,
+    ProrationAmount,
+    StripeInvoiceId,
+    ChangedAt,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Price and quantity of a subscription item on one side of a plan change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanItem {
+    pub price_id: String,
+    pub quantity: Option<i64>,
+    pub unit_amount: Option<i64>,
+}
+
+/// Change of price or quantity of a subscription item, e.g. an upgrade in the
+/// middle of a period. Added and removed items have no old or new price.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanChange {
+    pub plan_change_id: Uuid,
+    pub stripe_event_id: String,
+    pub stripe_subscription_id: String,
+    pub stripe_subscription_item_id: String,
+    pub old_price_id: Option<String>,
+    pub new_price_id: Option<String>,
+    pub old_quantity: Option<i64>,
+    pub new_quantity: Option<i64>,
+    pub old_unit_amount: Option<i64>,
+    pub new_unit_amount: Option<i64>,
+    pub currency: Option<String>,
+    pub proration_amount: Option<i64>,
+    pub stripe_invoice_id: Option<String>,
+    pub changed_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PlanChange {
+    const INSERT_COLUMNS: [PlanChangeIden; 11] = [
+        PlanChangeIden::StripeEventId,
+        PlanChangeIden::StripeSubscriptionId,
+        PlanChangeIden::StripeSubscriptionItemId,
+        PlanChangeIden::OldPriceId,
+        PlanChangeIden::NewPriceId,
+        PlanChangeIden::OldQuantity,
+        PlanChangeIden::NewQuantity,
+        PlanChangeIden::OldUnitAmount,
+        PlanChangeIden::NewUnitAmount,
+        PlanChangeIden::Currency,
+        PlanChangeIden::ChangedAt,
+    ];
+
+    /// Records the change. Returns `None` if it was already recorded for the
+    /// event, i.e. the event was delivered more than once.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn record<'a>(
+        conn: &Transaction<'a>,
+        stripe_event_id: &String,
+        stripe_subscription_id: &String,
+        stripe_subscription_item_id: &String,
+        old: Option<&PlanItem>,
+        new: Option<&PlanItem>,
+        currency: Option<String>,
+        changed_at: DateTime<Utc>,
+    ) -> Result<Option<Self>, DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(PlanChangeIden::Table)
+            .columns(Self::INSERT_COLUMNS)
+            .values([
+                stripe_event_id.into(),
+                stripe_subscription_id.into(),
+                stripe_subscription_item_id.into(),
+                old.map(|o| o.price_id.as_str()).into(),
+                new.map(|n| n.price_id.as_str()).into(),
+                old.and_then(|o| o.quantity).into(),
+                new.and_then(|n| n.quantity).into(),
+                old.and_then(|o| o.unit_amount).into(),
+                new.and_then(|n| n.unit_amount).into(),
+                currency.into(),
+                changed_at.into(),
+            ])?
+            .on_conflict(
+                OnConflict::columns([
+                    PlanChangeIden::StripeEventId,
+                    PlanChangeIden::StripeSubscriptionItemId,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Attributes the proration lines of an invoice to the latest change of
+    /// the subscription item that is not attributed to another invoice yet.
+    pub async fn put_proration(
+        conn: &impl GenericClient,
+        stripe_subscription_item_id: &String,
+        stripe_invoice_id: &String,
+        proration_amount: i64,
+    ) -> Result<(), DbError> {
+        let latest_change = Query::select()
+            .column(PlanChangeIden::PlanChangeId)
+            .from(PlanChangeIden::Table)
+            .and_where(
+                Expr::col(PlanChangeIden::StripeSubscriptionItemId)
+                    .eq(stripe_subscription_item_id),
+            )
+            .cond_where(
+                Cond::any()
+                    .add(Expr::col(PlanChangeIden::StripeInvoiceId).is_null())
+                    .add(
+                        Expr::col(PlanChangeIden::StripeInvoiceId)
+                            .eq(stripe_invoice_id),
+                    ),
+            )
+            .order_by(PlanChangeIden::ChangedAt, Order::Desc)
+            .limit(1)
+            .to_owned();
+
+        let (sql, values) = Query::update()
+            .table(PlanChangeIden::Table)
+            .values([
+                (PlanChangeIden::ProrationAmount, proration_amount.into()),
+                (PlanChangeIden::StripeInvoiceId, stripe_invoice_id.into()),
+            ])
+            .and_where(
+                Expr::col(PlanChangeIden::PlanChangeId)
+                    .in_subquery(latest_change),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    /// Selects the invoices whose proration lines are attributed to a change
+    /// of the subscription item.
+    pub(super) fn select_stripe_invoice_ids(
+        stripe_subscription_item_id: &String,
+    ) -> SelectStatement {
+        Query::select()
+            .column(PlanChangeIden::StripeInvoiceId)
+            .from(PlanChangeIden::Table)
+            .and_where(
+                Expr::col(PlanChangeIden::StripeSubscriptionItemId)
+                    .eq(stripe_subscription_item_id),
+            )
+            .and_where(Expr::col(PlanChangeIden::StripeInvoiceId).is_not_null())
+            .to_owned()
+    }
+
+    pub async fn list_by_stripe_subscription_id(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(PlanChangeIden::Table)
+            .and_where(
+                Expr::col(PlanChangeIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .order_by(PlanChangeIden::ChangedAt, Order::Desc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+}
+
+impl From<Row> for PlanChange {
+    fn from(row: Row) -> Self {
+        Self {
+            plan_change_id: row
+                .get(PlanChangeIden::PlanChangeId.to_string().as_str()),
+            stripe_event_id: row
+                .get(PlanChangeIden::StripeEventId.to_string().as_str()),
+            stripe_subscription_id: row
+                .get(PlanChangeIden::StripeSubscriptionId.to_string().as_str()),
+            stripe_subscription_item_id: row.get(
+                PlanChangeIden::StripeSubscriptionItemId
+                    .to_string()
+                    .as_str(),
+            ),
+            old_price_id: row
+                .get(PlanChangeIden::OldPriceId.to_string().as_str()),
+            new_price_id: row
+                .get(PlanChangeIden::NewPriceId.to_string().as_str()),
+            old_quantity: row
+                .get(PlanChangeIden::OldQuantity.to_string().as_str()),
+            new_quantity: row
+                .get(PlanChangeIden::NewQuantity.to_string().as_str()),
+            old_unit_amount: row
+                .get(PlanChangeIden::OldUnitAmount.to_string().as_str()),
+            new_unit_amount: row
+                .get(PlanChangeIden::NewUnitAmount.to_string().as_str()),
+            currency: row.get(PlanChangeIden::Currency.to_string().as_str()),
+            proration_amount: row
+                .get(PlanChangeIden::ProrationAmount.to_string().as_str()),
+            stripe_invoice_id: row
+                .get(PlanChangeIden::StripeInvoiceId.to_string().as_str()),
+            changed_at: row.get(PlanChangeIden::ChangedAt.to_string().as_str()),
+            created_at: row.get(PlanChangeIden::CreatedAt.to_string().as_str()),
+            updated_at: row.get(PlanChangeIden::UpdatedAt.to_string().as_str()),
+        }
+    }
+}
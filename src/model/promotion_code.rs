@@ -0,0 +1,67 @@
+use crate::DbError;
+use deadpool_postgres::GenericClient;
+use sea_query::{Expr, Iden, OnConflict, PostgresQueryBuilder, Query};
+use sea_query_postgres::PostgresBinder;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "promotion_codes")]
+pub(super) enum PromotionCodeIden {
+    Table,
+    StripePromotionCodeId,
+    Code,
+    StripeCouponId,
+    Active,
+    EventTimestamp,
+}
+
+/// Customer-facing code redeeming a coupon.
+pub struct PromotionCode;
+
+impl PromotionCode {
+    const PUT_COLUMNS: [PromotionCodeIden; 5] = [
+        PromotionCodeIden::StripePromotionCodeId,
+        PromotionCodeIden::Code,
+        PromotionCodeIden::StripeCouponId,
+        PromotionCodeIden::Active,
+        PromotionCodeIden::EventTimestamp,
+    ];
+
+    /// Inserts or updates the promotion code unless the stored promotion code
+    /// was written by an event newer than `event_timestamp`.
+    pub async fn put(
+        conn: &impl GenericClient,
+        stripe_promotion_code_id: &String,
+        code: &String,
+        stripe_coupon_id: &String,
+        active: bool,
+        event_timestamp: i64,
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(PromotionCodeIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_promotion_code_id.into(),
+                code.into(),
+                stripe_coupon_id.into(),
+                active.into(),
+                event_timestamp.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(PromotionCodeIden::StripePromotionCodeId)
+                    .update_columns(Self::PUT_COLUMNS)
+                    .action_and_where(
+                        Expr::col((
+                            PromotionCodeIden::Table,
+                            PromotionCodeIden::EventTimestamp,
+                        ))
+                        .lte(event_timestamp),
+                    )
+                    .to_owned(),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
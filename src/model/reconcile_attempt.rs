@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::Pool;
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "reconcile_attempts")]
+enum ReconcileAttemptIden {
+    Table,
+    StripeSubscriptionId,
+    Attempts,
+    LastAttemptAt,
+}
+
+/// Failed attempts to complete a stalled subscription from the Stripe API.
+#[derive(Debug, Clone)]
+pub struct ReconcileAttempt {
+    pub stripe_subscription_id: String,
+    pub attempts: i64,
+    pub last_attempt_at: DateTime<Utc>,
+}
+
+impl ReconcileAttempt {
+    pub async fn list(pool: &Pool) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(ReconcileAttemptIden::Table)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Counts a failed attempt made at `attempted_at`.
+    pub async fn record(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+        attempted_at: DateTime<Utc>,
+    ) -> Result<Self, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::insert()
+            .into_table(ReconcileAttemptIden::Table)
+            .columns([
+                ReconcileAttemptIden::StripeSubscriptionId,
+                ReconcileAttemptIden::Attempts,
+                ReconcileAttemptIden::LastAttemptAt,
+            ])
+            .values([
+                stripe_subscription_id.into(),
+                1i64.into(),
+                attempted_at.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(ReconcileAttemptIden::StripeSubscriptionId)
+                    .value(
+                        ReconcileAttemptIden::Attempts,
+                        Expr::col((
+                            ReconcileAttemptIden::Table,
+                            ReconcileAttemptIden::Attempts,
+                        ))
+                        .add(1i64),
+                    )
+                    .update_column(ReconcileAttemptIden::LastAttemptAt)
+                    .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Self::from(row))
+    }
+
+    pub async fn delete(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+    ) -> Result<(), DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(ReconcileAttemptIden::Table)
+            .and_where(
+                Expr::col(ReconcileAttemptIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
+
+impl From<Row> for ReconcileAttempt {
+    fn from(row: Row) -> Self {
+        Self {
+            stripe_subscription_id: row.get(
+                ReconcileAttemptIden::StripeSubscriptionId
+                    .to_string()
+                    .as_str(),
+            ),
+            attempts: row
+                .get(ReconcileAttemptIden::Attempts.to_string().as_str()),
+            last_attempt_at: row
+                .get(ReconcileAttemptIden::LastAttemptAt.to_string().as_str()),
+        }
+    }
+}
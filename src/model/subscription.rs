@@ -2,16 +2,20 @@ use chrono::{DateTime, Utc};
 use deadpool_postgres::tokio_postgres::Row;
 use deadpool_postgres::{GenericClient, Pool, Transaction};
 use sea_query::{
-    Asterisk, Expr, Iden, OnConflict, PostgresQueryBuilder, Query,
+    Alias, Asterisk, Cond, Expr, Func, Iden, OnConflict, Order,
+    PostgresQueryBuilder, Query, SimpleExpr,
 };
 use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
 use uuid::Uuid;
 
 use crate::DbError;
 
+use super::{Customer, SubscriptionStatus};
+
 #[derive(Debug, Clone, Iden)]
 #[iden(rename = "subscriptions")]
-enum SubscriptionIden {
+pub(super) enum SubscriptionIden {
     Table,
     SubscriptionId,
     StripeSubscriptionId,
@@ -28,9 +32,21 @@ enum SubscriptionIden {
     CanceledAt,
     CancelAt,
     EventTimestamp,
+    Amount,
+    Currency,
+    RecurringInterval,
+    RecurringIntervalCount,
+    TrialStart,
+    TrialEnd,
+    AccessExpiredAt,
+    AccessUntil,
+    StripeCustomerId,
+    PauseBehavior,
+    PauseResumesAt,
+    BuyerEventTimestamp,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Subscription {
     pub subscription_id: Uuid,
     pub stripe_subscription_id: String,
@@ -39,7 +55,7 @@ pub struct Subscription {
     pub shop_id: Option<Uuid>,
     pub current_period_start: Option<DateTime<Utc>>,
     pub current_period_end: Option<DateTime<Utc>>,
-    pub subscription_status: Option<String>,
+    pub subscription_status: Option<SubscriptionStatus>,
     pub payed_at: Option<DateTime<Utc>>,
     pub payed_until: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
@@ -47,18 +63,31 @@ pub struct Subscription {
     pub canceled_at: Option<DateTime<Utc>>,
     pub cancel_at: Option<DateTime<Utc>>,
     pub event_timestamp: i64,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    pub recurring_interval: Option<String>,
+    pub recurring_interval_count: Option<i64>,
+    pub trial_start: Option<DateTime<Utc>>,
+    pub trial_end: Option<DateTime<Utc>>,
+    pub access_expired_at: Option<DateTime<Utc>>,
+    pub access_until: Option<DateTime<Utc>>,
+    pub stripe_customer_id: Option<String>,
+    pub pause_behavior: Option<String>,
+    pub pause_resumes_at: Option<DateTime<Utc>>,
+    pub buyer_event_timestamp: i64,
 }
 
 impl Subscription {
-    const PUT_CHECKOUT_SESSION_COLUMNS: [SubscriptionIden; 5] = [
+    const PUT_CHECKOUT_SESSION_COLUMNS: [SubscriptionIden; 6] = [
         SubscriptionIden::StripeSubscriptionId,
         SubscriptionIden::BuyerUserId,
         SubscriptionIden::OfferId,
         SubscriptionIden::ShopId,
         SubscriptionIden::EventTimestamp,
+        SubscriptionIden::BuyerEventTimestamp,
     ];
 
-    const PUT_SUBSCRIPTION_COLUMNS: [SubscriptionIden; 7] = [
+    const PUT_SUBSCRIPTION_COLUMNS: [SubscriptionIden; 16] = [
         SubscriptionIden::StripeSubscriptionId,
         SubscriptionIden::CurrentPeriodStart,
         SubscriptionIden::CurrentPeriodEnd,
@@ -66,6 +95,15 @@ impl Subscription {
         SubscriptionIden::CanceledAt,
         SubscriptionIden::CancelAt,
         SubscriptionIden::EventTimestamp,
+        SubscriptionIden::Amount,
+        SubscriptionIden::Currency,
+        SubscriptionIden::RecurringInterval,
+        SubscriptionIden::RecurringIntervalCount,
+        SubscriptionIden::TrialStart,
+        SubscriptionIden::TrialEnd,
+        SubscriptionIden::StripeCustomerId,
+        SubscriptionIden::PauseBehavior,
+        SubscriptionIden::PauseResumesAt,
     ];
 
     const PUT_INVOICE_COLUMNS: [SubscriptionIden; 4] = [
@@ -75,6 +113,239 @@ impl Subscription {
         SubscriptionIden::EventTimestamp,
     ];
 
+    /// Returns the period the buyer has access for. Paid periods come from
+    /// invoices. A trialing subscription grants access until the end of the
+    /// trial, even though no invoice has been paid for it yet.
+    pub fn access_period(
+        &self,
+    ) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        match (self.payed_at, self.payed_until) {
+            (Some(payed_at), Some(payed_until)) => {
+                (Some(payed_at), Some(payed_until))
+            }
+            _ if self.subscription_status
+                == Some(SubscriptionStatus::Trialing) =>
+            {
+                (self.trial_start, self.trial_end)
+            }
+            other => other,
+        }
+    }
+
+    /// Whether payment collection is paused while the service continues,
+    /// i.e. invoices are kept as draft or marked uncollectible. With `void`,
+    /// the service stops until collection resumes.
+    pub fn is_paused_with_access(&self) -> bool {
+        matches!(
+            self.pause_behavior.as_deref(),
+            Some("keep_as_draft") | Some("mark_uncollectible")
+        )
+    }
+
+    /// Returns the time the buyer loses access at: the end of the access
+    /// period extended by `grace_period`, but no later than `cancel_at`.
+    /// While collection is paused with access, the access period lasts at
+    /// least until collection resumes, if a resumption is scheduled.
+    pub fn compute_access_until(
+        &self,
+        grace_period: chrono::Duration,
+    ) -> Option<DateTime<Utc>> {
+        let (_, payed_until) = self.access_period();
+
+        let access_end = if self.is_paused_with_access() {
+            payed_until.max(self.pause_resumes_at)
+        } else {
+            payed_until
+        };
+
+        [access_end.map(|p| p + grace_period), self.cancel_at]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    /// Names of the fields that are required to publish the subscription to
+    /// the media service, but are not known yet.
+    pub fn missing_fields(&self) -> Vec<&'static str> {
+        let (payed_at, payed_until) = self.access_period();
+
+        [
+            ("buyer_user_id", self.buyer_user_id.is_none()),
+            ("offer_id", self.offer_id.is_none()),
+            ("shop_id", self.shop_id.is_none()),
+            ("current_period_start", self.current_period_start.is_none()),
+            ("current_period_end", self.current_period_end.is_none()),
+            ("subscription_status", self.subscription_status.is_none()),
+            ("payed_at", payed_at.is_none()),
+            ("payed_until", payed_until.is_none()),
+        ]
+        .into_iter()
+        .filter_map(|(field, missing)| missing.then_some(field))
+        .collect()
+    }
+
+    /// Lists subscriptions created before `created_before` that still miss
+    /// fields required for publishing. Subscriptions that ended without ever
+    /// being paid are left out.
+    pub async fn list_incomplete(
+        pool: &Pool,
+        created_before: DateTime<Utc>,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let not_ended = Cond::any()
+            .add(Expr::col(SubscriptionIden::SubscriptionStatus).is_null())
+            .add(Expr::col(SubscriptionIden::SubscriptionStatus).is_not_in([
+                SubscriptionStatus::Canceled.as_str(),
+                SubscriptionStatus::IncompleteExpired.as_str(),
+            ]));
+
+        let not_trialing = Cond::any()
+            .add(
+                Expr::col(SubscriptionIden::SubscriptionStatus)
+                    .ne(SubscriptionStatus::Trialing.as_str()),
+            )
+            .add(Expr::col(SubscriptionIden::TrialEnd).is_null());
+
+        let unpaid = Cond::all()
+            .add(
+                Cond::any()
+                    .add(Expr::col(SubscriptionIden::PayedAt).is_null())
+                    .add(Expr::col(SubscriptionIden::PayedUntil).is_null()),
+            )
+            .add(not_trialing);
+
+        let incomplete = Cond::any()
+            .add(Expr::col(SubscriptionIden::BuyerUserId).is_null())
+            .add(Expr::col(SubscriptionIden::OfferId).is_null())
+            .add(Expr::col(SubscriptionIden::ShopId).is_null())
+            .add(Expr::col(SubscriptionIden::CurrentPeriodStart).is_null())
+            .add(Expr::col(SubscriptionIden::CurrentPeriodEnd).is_null())
+            .add(Expr::col(SubscriptionIden::SubscriptionStatus).is_null())
+            .add(unpaid);
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionIden::Table)
+            .and_where(
+                Expr::col(SubscriptionIden::CreatedAt).lt(created_before),
+            )
+            .cond_where(not_ended)
+            .cond_where(incomplete)
+            .order_by(SubscriptionIden::CreatedAt, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Lists published subscriptions whose `access_until` passed before
+    /// `now` and for which no access expiry was recorded since.
+    pub async fn list_access_expired(
+        pool: &Pool,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        // An expiry recorded before the current `access_until` belongs to an
+        // earlier access period, e.g. before the subscription was renewed.
+        let not_recorded = Cond::any()
+            .add(Expr::col(SubscriptionIden::AccessExpiredAt).is_null())
+            .add(
+                Expr::col(SubscriptionIden::AccessExpiredAt)
+                    .lt(Expr::col(SubscriptionIden::AccessUntil)),
+            );
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionIden::Table)
+            .and_where(Expr::col(SubscriptionIden::BuyerUserId).is_not_null())
+            .and_where(Expr::col(SubscriptionIden::AccessUntil).lte(now))
+            .cond_where(not_recorded)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Lists subscriptions whose `access_until` was not computed, e.g. the
+    /// ones created before it was introduced.
+    pub async fn list_without_access_until(
+        pool: &Pool,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionIden::Table)
+            .and_where(Expr::col(SubscriptionIden::AccessUntil).is_null())
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    pub async fn list_by_shop_id(
+        pool: &Pool,
+        shop_id: &Uuid,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionIden::Table)
+            .and_where(Expr::col(SubscriptionIden::ShopId).eq(*shop_id))
+            .order_by(SubscriptionIden::CreatedAt, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    pub async fn set_access_expired(
+        pool: &Pool,
+        subscription_id: &Uuid,
+    ) -> Result<(), DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::update()
+            .table(SubscriptionIden::Table)
+            .value(SubscriptionIden::AccessExpiredAt, Expr::current_timestamp())
+            .and_where(
+                Expr::col(SubscriptionIden::SubscriptionId)
+                    .eq(*subscription_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+
+    pub async fn update_access_until(
+        conn: &impl GenericClient,
+        subscription_id: &Uuid,
+        access_until: Option<DateTime<Utc>>,
+    ) -> Result<Self, DbError> {
+        let (sql, values) = Query::update()
+            .table(SubscriptionIden::Table)
+            .value(SubscriptionIden::AccessUntil, access_until)
+            .and_where(
+                Expr::col(SubscriptionIden::SubscriptionId)
+                    .eq(*subscription_id),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Self::from(row))
+    }
+
     pub async fn get<'a>(
         conn: &Transaction<'a>,
         stripe_subscription_id: &String,
@@ -93,16 +364,127 @@ impl Subscription {
         Ok(row.map(Self::from))
     }
 
-    pub async fn put_checkout_session(
+    pub async fn list_by_buyer_user_id(
         pool: &Pool,
+        buyer_user_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionIden::Table)
+            .and_where(
+                Expr::col(SubscriptionIden::BuyerUserId).eq(buyer_user_id),
+            )
+            .order_by(SubscriptionIden::CreatedAt, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Replaces the buyer of all subscriptions of `buyer_user_id`. Returns
+    /// the number of updated subscriptions.
+    pub async fn replace_buyer_user_id<'a>(
+        conn: &Transaction<'a>,
+        buyer_user_id: &String,
+        new_buyer_user_id: &String,
+    ) -> Result<u64, DbError> {
+        let (sql, values) = Query::update()
+            .table(SubscriptionIden::Table)
+            .value(SubscriptionIden::BuyerUserId, new_buyer_user_id)
+            .and_where(
+                Expr::col(SubscriptionIden::BuyerUserId).eq(buyer_user_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        Ok(conn.execute(sql.as_str(), &values.as_params()).await?)
+    }
+
+    /// Lists the subscriptions of the Stripe customer, including the ones of
+    /// its buyer that were stored without a Stripe customer id.
+    pub async fn list_by_stripe_customer_id(
+        conn: &impl GenericClient,
+        stripe_customer_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionIden::Table)
+            .cond_where(
+                Cond::any()
+                    .add(
+                        Expr::col(SubscriptionIden::StripeCustomerId)
+                            .eq(stripe_customer_id),
+                    )
+                    .add(
+                        Expr::col(SubscriptionIden::StripeCustomerId)
+                            .is_null()
+                            .and(
+                                Expr::col(SubscriptionIden::BuyerUserId)
+                                    .in_subquery(
+                                        Customer::select_buyer_user_ids(
+                                            stripe_customer_id,
+                                        ),
+                                    ),
+                            ),
+                    ),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Marks the subscription as canceled at `ended_at`. Earlier cancellation
+    /// dates are kept.
+    pub async fn end<'a>(
+        conn: &Transaction<'a>,
+        subscription_id: &Uuid,
+        ended_at: DateTime<Utc>,
+    ) -> Result<Self, DbError> {
+        let (sql, values) = Query::update()
+            .table(SubscriptionIden::Table)
+            .value(
+                SubscriptionIden::SubscriptionStatus,
+                SubscriptionStatus::Canceled.as_str(),
+            )
+            .value(
+                SubscriptionIden::CanceledAt,
+                Expr::col(SubscriptionIden::CanceledAt).if_null(ended_at),
+            )
+            .value(
+                SubscriptionIden::CancelAt,
+                Expr::case(
+                    Expr::col(SubscriptionIden::CancelAt).lt(ended_at),
+                    Expr::col(SubscriptionIden::CancelAt),
+                )
+                .finally(ended_at),
+            )
+            .and_where(
+                Expr::col(SubscriptionIden::SubscriptionId)
+                    .eq(*subscription_id),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Self::from(row))
+    }
+
+    /// Inserts or updates the subscription with the attribution of its
+    /// checkout session. The buyer is only set if the subscription has none
+    /// yet, changing it is up to the caller.
+    pub async fn put_checkout_session<'a>(
+        conn: &Transaction<'a>,
         stripe_subscription_id: &String,
         buyer_user_id: &String,
         offer_id: &Uuid,
         shop_id: &Uuid,
         event_timestamp: i64,
     ) -> Result<Self, DbError> {
-        let conn = pool.get().await?;
-
         let (sql, values) = Query::insert()
             .into_table(SubscriptionIden::Table)
             .columns(Self::PUT_CHECKOUT_SESSION_COLUMNS)
@@ -112,10 +494,30 @@ impl Subscription {
                 (*offer_id).into(),
                 (*shop_id).into(),
                 event_timestamp.into(),
+                event_timestamp.into(),
             ])?
             .on_conflict(
                 OnConflict::column(SubscriptionIden::StripeSubscriptionId)
-                    .update_columns(Self::PUT_CHECKOUT_SESSION_COLUMNS)
+                    .update_columns([
+                        SubscriptionIden::OfferId,
+                        SubscriptionIden::ShopId,
+                    ])
+                    .values([
+                        (
+                            SubscriptionIden::EventTimestamp,
+                            Self::latest_event_timestamp(),
+                        ),
+                        (
+                            SubscriptionIden::BuyerUserId,
+                            Self::if_no_buyer(SubscriptionIden::BuyerUserId),
+                        ),
+                        (
+                            SubscriptionIden::BuyerEventTimestamp,
+                            Self::if_no_buyer(
+                                SubscriptionIden::BuyerEventTimestamp,
+                            ),
+                        ),
+                    ])
                     .to_owned(),
             )
             .returning_all()
@@ -126,16 +528,37 @@ impl Subscription {
         Ok(Self::from(row))
     }
 
+    /// Takes `column` from the inserted row if the stored subscription has no
+    /// buyer yet.
+    fn if_no_buyer(column: SubscriptionIden) -> SimpleExpr {
+        Expr::case(
+            Expr::col((SubscriptionIden::Table, SubscriptionIden::BuyerUserId))
+                .is_null(),
+            Expr::col((Alias::new("excluded"), column.clone())),
+        )
+        .finally(Expr::col((SubscriptionIden::Table, column)))
+        .into()
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn put_subscription<'a>(
         conn: &Transaction<'a>,
         stripe_subscription_id: &String,
         current_period_start: &DateTime<Utc>,
         current_period_end: &DateTime<Utc>,
-        subscription_status: &String,
+        subscription_status: SubscriptionStatus,
         canceled_at: Option<DateTime<Utc>>,
         cancel_at: Option<DateTime<Utc>>,
         event_timestamp: i64,
+        amount: Option<i64>,
+        currency: &String,
+        recurring_interval: Option<String>,
+        recurring_interval_count: Option<i64>,
+        trial_start: Option<DateTime<Utc>>,
+        trial_end: Option<DateTime<Utc>>,
+        stripe_customer_id: &String,
+        pause_behavior: Option<String>,
+        pause_resumes_at: Option<DateTime<Utc>>,
     ) -> Result<Self, DbError> {
         let (sql, values) = Query::insert()
             .into_table(SubscriptionIden::Table)
@@ -144,10 +567,19 @@ impl Subscription {
                 stripe_subscription_id.into(),
                 (*current_period_start).into(),
                 (*current_period_end).into(),
-                subscription_status.into(),
+                subscription_status.as_str().into(),
                 canceled_at.into(),
                 cancel_at.into(),
                 event_timestamp.into(),
+                amount.into(),
+                currency.into(),
+                recurring_interval.into(),
+                recurring_interval_count.into(),
+                trial_start.into(),
+                trial_end.into(),
+                stripe_customer_id.into(),
+                pause_behavior.into(),
+                pause_resumes_at.into(),
             ])?
             .on_conflict(
                 OnConflict::column(SubscriptionIden::StripeSubscriptionId)
@@ -182,7 +614,20 @@ impl Subscription {
             ])?
             .on_conflict(
                 OnConflict::column(SubscriptionIden::StripeSubscriptionId)
-                    .update_columns(Self::PUT_INVOICE_COLUMNS)
+                    .values([
+                        (
+                            SubscriptionIden::PayedAt,
+                            Self::if_paid_further(SubscriptionIden::PayedAt),
+                        ),
+                        (
+                            SubscriptionIden::PayedUntil,
+                            Self::if_paid_further(SubscriptionIden::PayedUntil),
+                        ),
+                        (
+                            SubscriptionIden::EventTimestamp,
+                            Self::latest_event_timestamp(),
+                        ),
+                    ])
                     .to_owned(),
             )
             .returning_all()
@@ -193,23 +638,75 @@ impl Subscription {
         Ok(Self::from(row))
     }
 
+    /// Keeps the newer of the stored and the inserted `event_timestamp`, so
+    /// older events cannot lower it.
+    fn latest_event_timestamp() -> SimpleExpr {
+        Func::cust(Alias::new("GREATEST"))
+            .args([
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::EventTimestamp,
+                ))
+                .into(),
+                Expr::col((
+                    Alias::new("excluded"),
+                    SubscriptionIden::EventTimestamp,
+                ))
+                .into(),
+            ])
+            .into()
+    }
+
+    /// Takes `column` from the inserted row if it extends `payed_until`, so
+    /// that invoices arriving out of order do not shorten the paid period.
+    fn if_paid_further(column: SubscriptionIden) -> SimpleExpr {
+        let excluded = Alias::new("excluded");
+
+        Expr::case(
+            Cond::any()
+                .add(
+                    Expr::col((
+                        SubscriptionIden::Table,
+                        SubscriptionIden::PayedUntil,
+                    ))
+                    .is_null(),
+                )
+                .add(
+                    Expr::col((excluded.clone(), SubscriptionIden::PayedUntil))
+                        .gt(Expr::col((
+                            SubscriptionIden::Table,
+                            SubscriptionIden::PayedUntil,
+                        ))),
+                ),
+            Expr::col((excluded, column.clone())),
+        )
+        .finally(Expr::col((SubscriptionIden::Table, column)))
+        .into()
+    }
+
+    /// Sets the buyer of the subscription. `event_timestamp` is the time of
+    /// the event or request the buyer was taken from, so older events cannot
+    /// move the subscription back.
     pub async fn update_buyer_user_id<'a>(
         conn: &Transaction<'a>,
         stripe_subscription_id: &String,
         buyer_user_id: &String,
-    ) -> Result<(), DbError> {
+        event_timestamp: i64,
+    ) -> Result<Self, DbError> {
         let (sql, values) = Query::update()
             .table(SubscriptionIden::Table)
             .value(SubscriptionIden::BuyerUserId, buyer_user_id)
+            .value(SubscriptionIden::BuyerEventTimestamp, event_timestamp)
             .and_where(
                 Expr::col(SubscriptionIden::StripeSubscriptionId)
                     .eq(stripe_subscription_id),
             )
+            .returning_all()
             .build_postgres(PostgresQueryBuilder);
 
-        conn.query(sql.as_str(), &values.as_params()).await?;
+        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
 
-        Ok(())
+        Ok(Self::from(row))
     }
 }
 
@@ -230,7 +727,10 @@ impl From<Row> for Subscription {
             current_period_end: row
                 .get(SubscriptionIden::CurrentPeriodEnd.to_string().as_str()),
             subscription_status: row
-                .get(SubscriptionIden::SubscriptionStatus.to_string().as_str()),
+                .get::<_, Option<String>>(
+                    SubscriptionIden::SubscriptionStatus.to_string().as_str(),
+                )
+                .map(|s| s.parse().unwrap()),
             payed_at: row.get(SubscriptionIden::PayedAt.to_string().as_str()),
             payed_until: row
                 .get(SubscriptionIden::PayedUntil.to_string().as_str()),
@@ -243,6 +743,193 @@ impl From<Row> for Subscription {
             cancel_at: row.get(SubscriptionIden::CancelAt.to_string().as_str()),
             event_timestamp: row
                 .get(SubscriptionIden::EventTimestamp.to_string().as_str()),
+            amount: row.get(SubscriptionIden::Amount.to_string().as_str()),
+            currency: row.get(SubscriptionIden::Currency.to_string().as_str()),
+            recurring_interval: row
+                .get(SubscriptionIden::RecurringInterval.to_string().as_str()),
+            recurring_interval_count: row.get(
+                SubscriptionIden::RecurringIntervalCount
+                    .to_string()
+                    .as_str(),
+            ),
+            trial_start: row
+                .get(SubscriptionIden::TrialStart.to_string().as_str()),
+            trial_end: row.get(SubscriptionIden::TrialEnd.to_string().as_str()),
+            access_expired_at: row
+                .get(SubscriptionIden::AccessExpiredAt.to_string().as_str()),
+            access_until: row
+                .get(SubscriptionIden::AccessUntil.to_string().as_str()),
+            stripe_customer_id: row
+                .get(SubscriptionIden::StripeCustomerId.to_string().as_str()),
+            pause_behavior: row
+                .get(SubscriptionIden::PauseBehavior.to_string().as_str()),
+            pause_resumes_at: row
+                .get(SubscriptionIden::PauseResumesAt.to_string().as_str()),
+            buyer_event_timestamp: row.get(
+                SubscriptionIden::BuyerEventTimestamp.to_string().as_str(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn paid_subscription(payed_until: DateTime<Utc>) -> Subscription {
+        let created_at = payed_until - Duration::days(30);
+
+        Subscription {
+            subscription_id: Uuid::nil(),
+            stripe_subscription_id: "sub_test".to_string(),
+            buyer_user_id: None,
+            offer_id: None,
+            shop_id: None,
+            current_period_start: Some(created_at),
+            current_period_end: Some(payed_until),
+            subscription_status: Some(SubscriptionStatus::Active),
+            payed_at: Some(created_at),
+            payed_until: Some(payed_until),
+            created_at,
+            updated_at: created_at,
+            canceled_at: None,
+            cancel_at: None,
+            event_timestamp: 0,
+            amount: None,
+            currency: None,
+            recurring_interval: None,
+            recurring_interval_count: None,
+            trial_start: None,
+            trial_end: None,
+            access_expired_at: None,
+            access_until: None,
+            stripe_customer_id: None,
+            pause_behavior: None,
+            pause_resumes_at: None,
+            buyer_event_timestamp: 0,
+        }
+    }
+
+    fn period_end() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn access_lasts_until_end_of_grace_period() {
+        let subscription = paid_subscription(period_end());
+
+        assert_eq!(
+            subscription.compute_access_until(Duration::days(3)),
+            Some(period_end() + Duration::days(3))
+        );
+    }
+
+    #[test]
+    fn access_is_capped_at_cancel_at() {
+        let mut subscription = paid_subscription(period_end());
+        subscription.cancel_at = Some(period_end() + Duration::days(1));
+
+        assert_eq!(
+            subscription.compute_access_until(Duration::days(3)),
+            Some(period_end() + Duration::days(1))
+        );
+    }
+
+    #[test]
+    fn grace_period_applies_before_cancel_at() {
+        let mut subscription = paid_subscription(period_end());
+        subscription.cancel_at = Some(period_end() + Duration::days(5));
+
+        assert_eq!(
+            subscription.compute_access_until(Duration::days(3)),
+            Some(period_end() + Duration::days(3))
+        );
+    }
+
+    fn paused(
+        behavior: &str,
+        resumes_at: Option<DateTime<Utc>>,
+    ) -> Subscription {
+        let mut subscription = paid_subscription(period_end());
+        subscription.pause_behavior = Some(behavior.to_string());
+        subscription.pause_resumes_at = resumes_at;
+        subscription
+    }
+
+    #[test]
+    fn paused_access_lasts_until_resumption() {
+        let resumes_at = Some(period_end() + Duration::days(10));
+
+        for behavior in ["keep_as_draft", "mark_uncollectible"] {
+            assert_eq!(
+                paused(behavior, resumes_at)
+                    .compute_access_until(Duration::days(3)),
+                Some(period_end() + Duration::days(13)),
+                "{behavior}"
+            );
+        }
+    }
+
+    #[test]
+    fn paused_access_without_resumption_ends_after_paid_period() {
+        for behavior in ["keep_as_draft", "mark_uncollectible"] {
+            assert_eq!(
+                paused(behavior, None).compute_access_until(Duration::days(3)),
+                Some(period_end() + Duration::days(3)),
+                "{behavior}"
+            );
         }
     }
+
+    #[test]
+    fn paused_access_is_not_shortened_by_earlier_resumption() {
+        let resumes_at = Some(period_end() - Duration::days(10));
+
+        assert_eq!(
+            paused("keep_as_draft", resumes_at)
+                .compute_access_until(Duration::days(3)),
+            Some(period_end() + Duration::days(3))
+        );
+    }
+
+    #[test]
+    fn paused_access_is_capped_at_cancel_at() {
+        let mut subscription = paused(
+            "mark_uncollectible",
+            Some(period_end() + Duration::days(10)),
+        );
+        subscription.cancel_at = Some(period_end() + Duration::days(5));
+
+        assert_eq!(
+            subscription.compute_access_until(Duration::days(3)),
+            Some(period_end() + Duration::days(5))
+        );
+    }
+
+    #[test]
+    fn voided_pause_does_not_extend_access() {
+        assert_eq!(
+            paused("void", Some(period_end() + Duration::days(10)))
+                .compute_access_until(Duration::days(3)),
+            Some(period_end() + Duration::days(3))
+        );
+    }
+
+    #[test]
+    fn unpaid_subscription_has_access_until_cancel_at() {
+        let mut subscription = paid_subscription(period_end());
+        subscription.payed_at = None;
+        subscription.payed_until = None;
+
+        assert_eq!(subscription.compute_access_until(Duration::days(3)), None);
+
+        subscription.cancel_at = Some(period_end());
+
+        assert_eq!(
+            subscription.compute_access_until(Duration::days(3)),
+            Some(period_end())
+        );
+    }
 }
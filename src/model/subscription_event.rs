@@ -0,0 +1,309 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{Pool, Transaction};
+use sea_query::{Asterisk, Expr, Iden, Order, PostgresQueryBuilder, Query};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+use super::subscription::SubscriptionIden;
+use super::{Subscription, SubscriptionStatus};
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "subscription_events")]
+enum SubscriptionEventIden {
+    Table,
+    SubscriptionEventId,
+    SubscriptionId,
+    StripeEventId,
+    OldStatus,
+    NewStatus,
+    OldCurrentPeriodStart,
+    NewCurrentPeriodStart,
+    OldCurrentPeriodEnd,
+    NewCurrentPeriodEnd,
+    OldCancelAt,
+    NewCancelAt,
+    OldCanceledAt,
+    NewCanceledAt,
+    OldBuyerUserId,
+    NewBuyerUserId,
+    EventTimestamp,
+    CreatedAt,
+}
+
+/// A state transition of a subscription. Rows are only ever inserted, so the
+/// table holds the full history of status, periods, cancellation and buyer.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionEvent {
+    pub subscription_event_id: Uuid,
+    pub subscription_id: Uuid,
+    pub stripe_event_id: Option<String>,
+    pub old_status: Option<SubscriptionStatus>,
+    pub new_status: Option<SubscriptionStatus>,
+    pub old_current_period_start: Option<DateTime<Utc>>,
+    pub new_current_period_start: Option<DateTime<Utc>>,
+    pub old_current_period_end: Option<DateTime<Utc>>,
+    pub new_current_period_end: Option<DateTime<Utc>>,
+    pub old_cancel_at: Option<DateTime<Utc>>,
+    pub new_cancel_at: Option<DateTime<Utc>>,
+    pub old_canceled_at: Option<DateTime<Utc>>,
+    pub new_canceled_at: Option<DateTime<Utc>>,
+    pub old_buyer_user_id: Option<String>,
+    pub new_buyer_user_id: Option<String>,
+    pub event_timestamp: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SubscriptionEvent {
+    const INSERT_COLUMNS: [SubscriptionEventIden; 15] = [
+        SubscriptionEventIden::SubscriptionId,
+        SubscriptionEventIden::StripeEventId,
+        SubscriptionEventIden::OldStatus,
+        SubscriptionEventIden::NewStatus,
+        SubscriptionEventIden::OldCurrentPeriodStart,
+        SubscriptionEventIden::NewCurrentPeriodStart,
+        SubscriptionEventIden::OldCurrentPeriodEnd,
+        SubscriptionEventIden::NewCurrentPeriodEnd,
+        SubscriptionEventIden::OldCancelAt,
+        SubscriptionEventIden::NewCancelAt,
+        SubscriptionEventIden::OldCanceledAt,
+        SubscriptionEventIden::NewCanceledAt,
+        SubscriptionEventIden::OldBuyerUserId,
+        SubscriptionEventIden::NewBuyerUserId,
+        SubscriptionEventIden::EventTimestamp,
+    ];
+
+    /// Records the transition from `old` to `new`. Returns `None` without
+    /// writing anything if none of the tracked fields changed.
+    pub async fn record<'a>(
+        conn: &Transaction<'a>,
+        stripe_event_id: Option<&String>,
+        old: Option<&Subscription>,
+        new: &Subscription,
+        event_timestamp: i64,
+    ) -> Result<Option<Self>, DbError> {
+        let old_status = old.and_then(|o| o.subscription_status);
+        let old_current_period_start = old.and_then(|o| o.current_period_start);
+        let old_current_period_end = old.and_then(|o| o.current_period_end);
+        let old_cancel_at = old.and_then(|o| o.cancel_at);
+        let old_canceled_at = old.and_then(|o| o.canceled_at);
+        let old_buyer_user_id = old.and_then(|o| o.buyer_user_id.clone());
+
+        if old_status == new.subscription_status
+            && old_current_period_start == new.current_period_start
+            && old_current_period_end == new.current_period_end
+            && old_cancel_at == new.cancel_at
+            && old_canceled_at == new.canceled_at
+            && old_buyer_user_id == new.buyer_user_id
+        {
+            return Ok(None);
+        }
+
+        let (sql, values) = Query::insert()
+            .into_table(SubscriptionEventIden::Table)
+            .columns(Self::INSERT_COLUMNS)
+            .values([
+                new.subscription_id.into(),
+                stripe_event_id.map(|s| s.as_str()).into(),
+                old_status.map(|s| s.as_str()).into(),
+                new.subscription_status.map(|s| s.as_str()).into(),
+                old_current_period_start.into(),
+                new.current_period_start.into(),
+                old_current_period_end.into(),
+                new.current_period_end.into(),
+                old_cancel_at.into(),
+                new.cancel_at.into(),
+                old_canceled_at.into(),
+                new.canceled_at.into(),
+                old_buyer_user_id.into(),
+                new.buyer_user_id.clone().into(),
+                event_timestamp.into(),
+            ])?
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Some(Self::from(row)))
+    }
+
+    pub async fn list_by_stripe_subscription_id(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column((SubscriptionEventIden::Table, Asterisk))
+            .from(SubscriptionEventIden::Table)
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::SubscriptionId,
+                ))
+                .equals((
+                    SubscriptionEventIden::Table,
+                    SubscriptionEventIden::SubscriptionId,
+                )),
+            )
+            .and_where(
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::StripeSubscriptionId,
+                ))
+                .eq(stripe_subscription_id),
+            )
+            .order_by(
+                (
+                    SubscriptionEventIden::Table,
+                    SubscriptionEventIden::CreatedAt,
+                ),
+                Order::Desc,
+            )
+            .limit(limit)
+            .offset(offset)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    /// Replaces the buyer in the recorded transitions. Returns the number of
+    /// updated rows.
+    pub async fn replace_buyer_user_id<'a>(
+        conn: &Transaction<'a>,
+        buyer_user_id: &String,
+        new_buyer_user_id: &String,
+    ) -> Result<u64, DbError> {
+        let mut updated = 0;
+
+        for column in [
+            SubscriptionEventIden::OldBuyerUserId,
+            SubscriptionEventIden::NewBuyerUserId,
+        ] {
+            let (sql, values) = Query::update()
+                .table(SubscriptionEventIden::Table)
+                .value(column.clone(), new_buyer_user_id)
+                .and_where(Expr::col(column).eq(buyer_user_id))
+                .build_postgres(PostgresQueryBuilder);
+
+            updated += conn.execute(sql.as_str(), &values.as_params()).await?;
+        }
+
+        Ok(updated)
+    }
+
+    pub async fn list_by_buyer_user_id(
+        pool: &Pool,
+        buyer_user_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column((SubscriptionEventIden::Table, Asterisk))
+            .from(SubscriptionEventIden::Table)
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::SubscriptionId,
+                ))
+                .equals((
+                    SubscriptionEventIden::Table,
+                    SubscriptionEventIden::SubscriptionId,
+                )),
+            )
+            .and_where(
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::BuyerUserId,
+                ))
+                .eq(buyer_user_id),
+            )
+            .order_by(
+                (
+                    SubscriptionEventIden::Table,
+                    SubscriptionEventIden::CreatedAt,
+                ),
+                Order::Asc,
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+}
+
+fn get_status(
+    row: &Row,
+    column: SubscriptionEventIden,
+) -> Option<SubscriptionStatus> {
+    row.get::<_, Option<String>>(column.to_string().as_str())
+        .map(|s| s.parse().unwrap())
+}
+
+impl From<Row> for SubscriptionEvent {
+    fn from(row: Row) -> Self {
+        Self {
+            subscription_event_id: row.get(
+                SubscriptionEventIden::SubscriptionEventId
+                    .to_string()
+                    .as_str(),
+            ),
+            subscription_id: row.get(
+                SubscriptionEventIden::SubscriptionId.to_string().as_str(),
+            ),
+            stripe_event_id: row
+                .get(SubscriptionEventIden::StripeEventId.to_string().as_str()),
+            old_status: get_status(&row, SubscriptionEventIden::OldStatus),
+            new_status: get_status(&row, SubscriptionEventIden::NewStatus),
+            old_current_period_start: row.get(
+                SubscriptionEventIden::OldCurrentPeriodStart
+                    .to_string()
+                    .as_str(),
+            ),
+            new_current_period_start: row.get(
+                SubscriptionEventIden::NewCurrentPeriodStart
+                    .to_string()
+                    .as_str(),
+            ),
+            old_current_period_end: row.get(
+                SubscriptionEventIden::OldCurrentPeriodEnd
+                    .to_string()
+                    .as_str(),
+            ),
+            new_current_period_end: row.get(
+                SubscriptionEventIden::NewCurrentPeriodEnd
+                    .to_string()
+                    .as_str(),
+            ),
+            old_cancel_at: row
+                .get(SubscriptionEventIden::OldCancelAt.to_string().as_str()),
+            new_cancel_at: row
+                .get(SubscriptionEventIden::NewCancelAt.to_string().as_str()),
+            old_canceled_at: row
+                .get(SubscriptionEventIden::OldCanceledAt.to_string().as_str()),
+            new_canceled_at: row
+                .get(SubscriptionEventIden::NewCanceledAt.to_string().as_str()),
+            old_buyer_user_id: row.get(
+                SubscriptionEventIden::OldBuyerUserId.to_string().as_str(),
+            ),
+            new_buyer_user_id: row.get(
+                SubscriptionEventIden::NewBuyerUserId.to_string().as_str(),
+            ),
+            event_timestamp: row.get(
+                SubscriptionEventIden::EventTimestamp.to_string().as_str(),
+            ),
+            created_at: row
+                .get(SubscriptionEventIden::CreatedAt.to_string().as_str()),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Transaction};
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "subscription_items")]
+pub(super) enum SubscriptionItemIden {
+    Table,
+    SubscriptionItemId,
+    SubscriptionId,
+    StripeSubscriptionItemId,
+    PriceId,
+    ProductId,
+    Quantity,
+    UnitAmount,
+    Currency,
+    UsageType,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionItem {
+    pub subscription_item_id: Uuid,
+    pub subscription_id: Uuid,
+    pub stripe_subscription_item_id: String,
+    pub price_id: String,
+    pub product_id: Option<String>,
+    pub quantity: Option<i64>,
+    pub unit_amount: Option<i64>,
+    pub currency: Option<String>,
+    pub usage_type: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SubscriptionItem {
+    const PUT_COLUMNS: [SubscriptionItemIden; 8] = [
+        SubscriptionItemIden::SubscriptionId,
+        SubscriptionItemIden::StripeSubscriptionItemId,
+        SubscriptionItemIden::PriceId,
+        SubscriptionItemIden::ProductId,
+        SubscriptionItemIden::Quantity,
+        SubscriptionItemIden::UnitAmount,
+        SubscriptionItemIden::Currency,
+        SubscriptionItemIden::UsageType,
+    ];
+
+    pub async fn list(
+        conn: &impl GenericClient,
+        subscription_id: &Uuid,
+    ) -> Result<Vec<Self>, DbError> {
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionItemIden::Table)
+            .and_where(
+                Expr::col(SubscriptionItemIden::SubscriptionId)
+                    .eq(*subscription_id),
+            )
+            .order_by(SubscriptionItemIden::CreatedAt, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put<'a>(
+        conn: &Transaction<'a>,
+        subscription_id: &Uuid,
+        stripe_subscription_item_id: &String,
+        price_id: &String,
+        product_id: Option<String>,
+        quantity: Option<i64>,
+        unit_amount: Option<i64>,
+        currency: Option<String>,
+        usage_type: Option<String>,
+    ) -> Result<Self, DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(SubscriptionItemIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                (*subscription_id).into(),
+                stripe_subscription_item_id.into(),
+                price_id.into(),
+                product_id.into(),
+                quantity.into(),
+                unit_amount.into(),
+                currency.into(),
+                usage_type.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(
+                    SubscriptionItemIden::StripeSubscriptionItemId,
+                )
+                .update_columns(Self::PUT_COLUMNS)
+                .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Self::from(row))
+    }
+
+    /// Removes items that are no longer part of the subscription, e.g. after
+    /// the buyer switched to a different price.
+    pub async fn delete_except<'a>(
+        conn: &Transaction<'a>,
+        subscription_id: &Uuid,
+        stripe_subscription_item_ids: &[String],
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(SubscriptionItemIden::Table)
+            .and_where(
+                Expr::col(SubscriptionItemIden::SubscriptionId)
+                    .eq(*subscription_id),
+            )
+            .and_where(
+                Expr::col(SubscriptionItemIden::StripeSubscriptionItemId)
+                    .is_not_in(stripe_subscription_item_ids.iter().cloned()),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        Ok(())
+    }
+}
+
+impl From<Row> for SubscriptionItem {
+    fn from(row: Row) -> Self {
+        Self {
+            subscription_item_id: row.get(
+                SubscriptionItemIden::SubscriptionItemId
+                    .to_string()
+                    .as_str(),
+            ),
+            subscription_id: row
+                .get(SubscriptionItemIden::SubscriptionId.to_string().as_str()),
+            stripe_subscription_item_id: row.get(
+                SubscriptionItemIden::StripeSubscriptionItemId
+                    .to_string()
+                    .as_str(),
+            ),
+            price_id: row
+                .get(SubscriptionItemIden::PriceId.to_string().as_str()),
+            product_id: row
+                .get(SubscriptionItemIden::ProductId.to_string().as_str()),
+            quantity: row
+                .get(SubscriptionItemIden::Quantity.to_string().as_str()),
+            unit_amount: row
+                .get(SubscriptionItemIden::UnitAmount.to_string().as_str()),
+            currency: row
+                .get(SubscriptionItemIden::Currency.to_string().as_str()),
+            usage_type: row
+                .get(SubscriptionItemIden::UsageType.to_string().as_str()),
+            created_at: row
+                .get(SubscriptionItemIden::CreatedAt.to_string().as_str()),
+            updated_at: row
+                .get(SubscriptionItemIden::UpdatedAt.to_string().as_str()),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "subscription_schedules")]
+pub(super) enum SubscriptionScheduleIden {
+    Table,
+    SubscriptionScheduleId,
+    StripeSubscriptionScheduleId,
+    StripeSubscriptionId,
+    ScheduleStatus,
+    EndBehavior,
+    CurrentPhaseStart,
+    CurrentPhaseEnd,
+    CanceledAt,
+    CompletedAt,
+    ReleasedAt,
+    EventTimestamp,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Stripe subscription schedule, i.e. a sequence of phases with their own
+/// prices that a subscription goes through.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionSchedule {
+    pub subscription_schedule_id: Uuid,
+    pub stripe_subscription_schedule_id: String,
+    pub stripe_subscription_id: Option<String>,
+    pub schedule_status: String,
+    pub end_behavior: String,
+    pub current_phase_start: Option<DateTime<Utc>>,
+    pub current_phase_end: Option<DateTime<Utc>>,
+    pub canceled_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub released_at: Option<DateTime<Utc>>,
+    pub event_timestamp: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SubscriptionSchedule {
+    const PUT_COLUMNS: [SubscriptionScheduleIden; 10] = [
+        SubscriptionScheduleIden::StripeSubscriptionScheduleId,
+        SubscriptionScheduleIden::StripeSubscriptionId,
+        SubscriptionScheduleIden::ScheduleStatus,
+        SubscriptionScheduleIden::EndBehavior,
+        SubscriptionScheduleIden::CurrentPhaseStart,
+        SubscriptionScheduleIden::CurrentPhaseEnd,
+        SubscriptionScheduleIden::CanceledAt,
+        SubscriptionScheduleIden::CompletedAt,
+        SubscriptionScheduleIden::ReleasedAt,
+        SubscriptionScheduleIden::EventTimestamp,
+    ];
+
+    pub async fn get(
+        conn: &impl GenericClient,
+        stripe_subscription_schedule_id: &String,
+    ) -> Result<Option<Self>, DbError> {
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionScheduleIden::Table)
+            .and_where(
+                Expr::col(
+                    SubscriptionScheduleIden::StripeSubscriptionScheduleId,
+                )
+                .eq(stripe_subscription_schedule_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Returns the latest schedule of the subscription. Released schedules
+    /// keep their subscription, so there can be several.
+    pub async fn get_by_stripe_subscription_id(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+    ) -> Result<Option<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SubscriptionScheduleIden::Table)
+            .and_where(
+                Expr::col(SubscriptionScheduleIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .order_by(SubscriptionScheduleIden::CreatedAt, Order::Desc)
+            .limit(1)
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Inserts or updates the schedule. Returns `None` if the stored schedule
+    /// was written by an event newer than `event_timestamp`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put<'a>(
+        conn: &Transaction<'a>,
+        stripe_subscription_schedule_id: &String,
+        stripe_subscription_id: Option<String>,
+        schedule_status: &String,
+        end_behavior: &String,
+        current_phase_start: Option<DateTime<Utc>>,
+        current_phase_end: Option<DateTime<Utc>>,
+        canceled_at: Option<DateTime<Utc>>,
+        completed_at: Option<DateTime<Utc>>,
+        released_at: Option<DateTime<Utc>>,
+        event_timestamp: i64,
+    ) -> Result<Option<Self>, DbError> {
+        let (sql, values) = Query::insert()
+            .into_table(SubscriptionScheduleIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_subscription_schedule_id.into(),
+                stripe_subscription_id.into(),
+                schedule_status.into(),
+                end_behavior.into(),
+                current_phase_start.into(),
+                current_phase_end.into(),
+                canceled_at.into(),
+                completed_at.into(),
+                released_at.into(),
+                event_timestamp.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(
+                    SubscriptionScheduleIden::StripeSubscriptionScheduleId,
+                )
+                .update_columns(Self::PUT_COLUMNS)
+                .action_and_where(
+                    Expr::col((
+                        SubscriptionScheduleIden::Table,
+                        SubscriptionScheduleIden::EventTimestamp,
+                    ))
+                    .lte(event_timestamp),
+                )
+                .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+}
+
+impl From<Row> for SubscriptionSchedule {
+    fn from(row: Row) -> Self {
+        Self {
+            subscription_schedule_id: row.get(
+                SubscriptionScheduleIden::SubscriptionScheduleId
+                    .to_string()
+                    .as_str(),
+            ),
+            stripe_subscription_schedule_id: row.get(
+                SubscriptionScheduleIden::StripeSubscriptionScheduleId
+                    .to_string()
+                    .as_str(),
+            ),
+            stripe_subscription_id: row.get(
+                SubscriptionScheduleIden::StripeSubscriptionId
+                    .to_string()
+                    .as_str(),
+            ),
+            schedule_status: row.get(
+                SubscriptionScheduleIden::ScheduleStatus
+                    .to_string()
+                    .as_str(),
+            ),
+            end_behavior: row.get(
+                SubscriptionScheduleIden::EndBehavior.to_string().as_str(),
+            ),
+            current_phase_start: row.get(
+                SubscriptionScheduleIden::CurrentPhaseStart
+                    .to_string()
+                    .as_str(),
+            ),
+            current_phase_end: row.get(
+                SubscriptionScheduleIden::CurrentPhaseEnd
+                    .to_string()
+                    .as_str(),
+            ),
+            canceled_at: row
+                .get(SubscriptionScheduleIden::CanceledAt.to_string().as_str()),
+            completed_at: row.get(
+                SubscriptionScheduleIden::CompletedAt.to_string().as_str(),
+            ),
+            released_at: row
+                .get(SubscriptionScheduleIden::ReleasedAt.to_string().as_str()),
+            event_timestamp: row.get(
+                SubscriptionScheduleIden::EventTimestamp
+                    .to_string()
+                    .as_str(),
+            ),
+            created_at: row
+                .get(SubscriptionScheduleIden::CreatedAt.to_string().as_str()),
+            updated_at: row
+                .get(SubscriptionScheduleIden::UpdatedAt.to_string().as_str()),
+        }
+    }
+}
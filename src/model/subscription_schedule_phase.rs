@@ -0,0 +1,333 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::{GenericClient, Pool, Transaction};
+use sea_query::{Asterisk, Expr, Iden, Order, PostgresQueryBuilder, Query};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::DbError;
+
+use super::subscription::SubscriptionIden;
+use super::subscription_schedule::SubscriptionScheduleIden;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "subscription_schedule_phases")]
+enum SchedulePhaseIden {
+    Table,
+    StripeSubscriptionScheduleId,
+    PhaseIndex,
+    StartAt,
+    EndAt,
+    Currency,
+    TrialEnd,
+}
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "subscription_schedule_phase_items")]
+enum SchedulePhaseItemIden {
+    Table,
+    StripeSubscriptionScheduleId,
+    PhaseIndex,
+    PriceId,
+    Quantity,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionSchedulePhaseItem {
+    pub price_id: String,
+    pub quantity: Option<i64>,
+}
+
+/// Phase of a subscription schedule, i.e. the prices a subscription is billed
+/// with between `start_at` and `end_at`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionSchedulePhase {
+    pub stripe_subscription_schedule_id: String,
+    pub phase_index: i64,
+    pub start_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+    pub currency: String,
+    pub trial_end: Option<DateTime<Utc>>,
+    pub items: Vec<SubscriptionSchedulePhaseItem>,
+}
+
+/// Phase that has not started yet, together with the subscription it will
+/// apply to.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpcomingSchedulePhase {
+    pub stripe_subscription_id: String,
+    #[serde(flatten)]
+    pub phase: SubscriptionSchedulePhase,
+}
+
+impl SubscriptionSchedulePhase {
+    const PUT_COLUMNS: [SchedulePhaseIden; 6] = [
+        SchedulePhaseIden::StripeSubscriptionScheduleId,
+        SchedulePhaseIden::PhaseIndex,
+        SchedulePhaseIden::StartAt,
+        SchedulePhaseIden::EndAt,
+        SchedulePhaseIden::Currency,
+        SchedulePhaseIden::TrialEnd,
+    ];
+
+    const PUT_ITEM_COLUMNS: [SchedulePhaseItemIden; 4] = [
+        SchedulePhaseItemIden::StripeSubscriptionScheduleId,
+        SchedulePhaseItemIden::PhaseIndex,
+        SchedulePhaseItemIden::PriceId,
+        SchedulePhaseItemIden::Quantity,
+    ];
+
+    /// Replaces the phases and their items stored for the schedule.
+    pub async fn replace<'a>(
+        conn: &Transaction<'a>,
+        stripe_subscription_schedule_id: &String,
+        phases: &[Self],
+    ) -> Result<(), DbError> {
+        let (sql, values) = Query::delete()
+            .from_table(SchedulePhaseItemIden::Table)
+            .and_where(
+                Expr::col(SchedulePhaseItemIden::StripeSubscriptionScheduleId)
+                    .eq(stripe_subscription_schedule_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(SchedulePhaseIden::Table)
+            .and_where(
+                Expr::col(SchedulePhaseIden::StripeSubscriptionScheduleId)
+                    .eq(stripe_subscription_schedule_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        conn.execute(sql.as_str(), &values.as_params()).await?;
+
+        for phase in phases {
+            let (sql, values) = Query::insert()
+                .into_table(SchedulePhaseIden::Table)
+                .columns(Self::PUT_COLUMNS)
+                .values([
+                    stripe_subscription_schedule_id.into(),
+                    phase.phase_index.into(),
+                    phase.start_at.into(),
+                    phase.end_at.into(),
+                    phase.currency.as_str().into(),
+                    phase.trial_end.into(),
+                ])?
+                .build_postgres(PostgresQueryBuilder);
+
+            conn.execute(sql.as_str(), &values.as_params()).await?;
+
+            for item in phase.items.iter() {
+                let (sql, values) = Query::insert()
+                    .into_table(SchedulePhaseItemIden::Table)
+                    .columns(Self::PUT_ITEM_COLUMNS)
+                    .values([
+                        stripe_subscription_schedule_id.into(),
+                        phase.phase_index.into(),
+                        item.price_id.as_str().into(),
+                        item.quantity.into(),
+                    ])?
+                    .build_postgres(PostgresQueryBuilder);
+
+                conn.execute(sql.as_str(), &values.as_params()).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_by_stripe_subscription_schedule_id(
+        pool: &Pool,
+        stripe_subscription_schedule_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SchedulePhaseIden::Table)
+            .and_where(
+                Expr::col(SchedulePhaseIden::StripeSubscriptionScheduleId)
+                    .eq(stripe_subscription_schedule_id),
+            )
+            .order_by(SchedulePhaseIden::PhaseIndex, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        let mut phases: Vec<Self> = rows.into_iter().map(Self::from).collect();
+
+        Self::attach_items(&conn, &mut phases).await?;
+
+        Ok(phases)
+    }
+
+    /// Lists the phases that start after `now` of the active and not yet
+    /// started schedules of the subscriptions of the shop.
+    pub async fn list_upcoming_by_shop_id(
+        pool: &Pool,
+        shop_id: &Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<UpcomingSchedulePhase>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column((SchedulePhaseIden::Table, Asterisk))
+            .column((
+                SubscriptionScheduleIden::Table,
+                SubscriptionScheduleIden::StripeSubscriptionId,
+            ))
+            .from(SchedulePhaseIden::Table)
+            .inner_join(
+                SubscriptionScheduleIden::Table,
+                Expr::col((
+                    SubscriptionScheduleIden::Table,
+                    SubscriptionScheduleIden::StripeSubscriptionScheduleId,
+                ))
+                .equals((
+                    SchedulePhaseIden::Table,
+                    SchedulePhaseIden::StripeSubscriptionScheduleId,
+                )),
+            )
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::StripeSubscriptionId,
+                ))
+                .equals((
+                    SubscriptionScheduleIden::Table,
+                    SubscriptionScheduleIden::StripeSubscriptionId,
+                )),
+            )
+            .and_where(
+                Expr::col((SubscriptionIden::Table, SubscriptionIden::ShopId))
+                    .eq(*shop_id),
+            )
+            .and_where(
+                Expr::col((
+                    SubscriptionScheduleIden::Table,
+                    SubscriptionScheduleIden::ScheduleStatus,
+                ))
+                .is_in(["active", "not_started"]),
+            )
+            .and_where(
+                Expr::col((
+                    SchedulePhaseIden::Table,
+                    SchedulePhaseIden::StartAt,
+                ))
+                .gt(now),
+            )
+            .order_by(
+                (SchedulePhaseIden::Table, SchedulePhaseIden::StartAt),
+                Order::Asc,
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        let subscription_ids: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                row.get(
+                    SubscriptionScheduleIden::StripeSubscriptionId
+                        .to_string()
+                        .as_str(),
+                )
+            })
+            .collect();
+
+        let mut phases: Vec<Self> = rows.into_iter().map(Self::from).collect();
+
+        Self::attach_items(&conn, &mut phases).await?;
+
+        Ok(subscription_ids
+            .into_iter()
+            .zip(phases)
+            .map(|(stripe_subscription_id, phase)| UpcomingSchedulePhase {
+                stripe_subscription_id,
+                phase,
+            })
+            .collect())
+    }
+
+    async fn attach_items(
+        conn: &impl GenericClient,
+        phases: &mut [Self],
+    ) -> Result<(), DbError> {
+        let mut schedule_ids: Vec<&str> = phases
+            .iter()
+            .map(|p| p.stripe_subscription_schedule_id.as_str())
+            .collect();
+        schedule_ids.sort_unstable();
+        schedule_ids.dedup();
+
+        if schedule_ids.is_empty() {
+            return Ok(());
+        }
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(SchedulePhaseItemIden::Table)
+            .and_where(
+                Expr::col(SchedulePhaseItemIden::StripeSubscriptionScheduleId)
+                    .is_in(schedule_ids),
+            )
+            .order_by(SchedulePhaseItemIden::PriceId, Order::Asc)
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        for row in rows {
+            let schedule_id: String = row.get(
+                SchedulePhaseItemIden::StripeSubscriptionScheduleId
+                    .to_string()
+                    .as_str(),
+            );
+            let phase_index: i64 =
+                row.get(SchedulePhaseItemIden::PhaseIndex.to_string().as_str());
+
+            if let Some(phase) = phases.iter_mut().find(|p| {
+                p.stripe_subscription_schedule_id == schedule_id
+                    && p.phase_index == phase_index
+            }) {
+                phase.items.push(SubscriptionSchedulePhaseItem::from(row));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Row> for SubscriptionSchedulePhase {
+    fn from(row: Row) -> Self {
+        Self {
+            stripe_subscription_schedule_id: row.get(
+                SchedulePhaseIden::StripeSubscriptionScheduleId
+                    .to_string()
+                    .as_str(),
+            ),
+            phase_index: row
+                .get(SchedulePhaseIden::PhaseIndex.to_string().as_str()),
+            start_at: row.get(SchedulePhaseIden::StartAt.to_string().as_str()),
+            end_at: row.get(SchedulePhaseIden::EndAt.to_string().as_str()),
+            currency: row.get(SchedulePhaseIden::Currency.to_string().as_str()),
+            trial_end: row
+                .get(SchedulePhaseIden::TrialEnd.to_string().as_str()),
+            items: Vec::new(),
+        }
+    }
+}
+
+impl From<Row> for SubscriptionSchedulePhaseItem {
+    fn from(row: Row) -> Self {
+        Self {
+            price_id: row
+                .get(SchedulePhaseItemIden::PriceId.to_string().as_str()),
+            quantity: row
+                .get(SchedulePhaseItemIden::Quantity.to_string().as_str()),
+        }
+    }
+}
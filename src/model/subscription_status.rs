@@ -0,0 +1,75 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+/// Status of a subscription as stored in `subscriptions.subscription_status`.
+/// The column only accepts the values of `as_str`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    Incomplete,
+    IncompleteExpired,
+    Trialing,
+    Active,
+    PastDue,
+    Canceled,
+    Unpaid,
+    Paused,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Incomplete => "incomplete",
+            Self::IncompleteExpired => "incomplete_expired",
+            Self::Trialing => "trialing",
+            Self::Active => "active",
+            Self::PastDue => "past_due",
+            Self::Canceled => "canceled",
+            Self::Unpaid => "unpaid",
+            Self::Paused => "paused",
+        }
+    }
+}
+
+impl fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SubscriptionStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "incomplete" => Ok(Self::Incomplete),
+            "incomplete_expired" => Ok(Self::IncompleteExpired),
+            "trialing" => Ok(Self::Trialing),
+            "active" => Ok(Self::Active),
+            "past_due" => Ok(Self::PastDue),
+            "canceled" => Ok(Self::Canceled),
+            "unpaid" => Ok(Self::Unpaid),
+            "paused" => Ok(Self::Paused),
+            other => Err(format!("unknown subscription status '{other}'")),
+        }
+    }
+}
+
+impl From<stripe::SubscriptionStatus> for SubscriptionStatus {
+    fn from(status: stripe::SubscriptionStatus) -> Self {
+        match status {
+            stripe::SubscriptionStatus::Incomplete => Self::Incomplete,
+            stripe::SubscriptionStatus::IncompleteExpired => {
+                Self::IncompleteExpired
+            }
+            stripe::SubscriptionStatus::Trialing => Self::Trialing,
+            stripe::SubscriptionStatus::Active => Self::Active,
+            stripe::SubscriptionStatus::PastDue => Self::PastDue,
+            stripe::SubscriptionStatus::Canceled => Self::Canceled,
+            stripe::SubscriptionStatus::Unpaid => Self::Unpaid,
+            stripe::SubscriptionStatus::Paused => Self::Paused,
+        }
+    }
+}
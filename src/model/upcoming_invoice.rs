@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::Pool;
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+
+use crate::DbError;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "upcoming_invoices")]
+enum UpcomingInvoiceIden {
+    Table,
+    StripeSubscriptionId,
+    AmountDue,
+    Subtotal,
+    Tax,
+    Total,
+    Currency,
+    PeriodStart,
+    PeriodEnd,
+    NextPaymentAttempt,
+    EventTimestamp,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Projection of the next invoice of a subscription, as announced by Stripe
+/// with `invoice.upcoming` some days before the renewal.
+#[derive(Debug, Clone, Serialize)]
+pub struct UpcomingInvoice {
+    pub stripe_subscription_id: String,
+    pub amount_due: Option<i64>,
+    pub subtotal: Option<i64>,
+    pub tax: Option<i64>,
+    pub total: Option<i64>,
+    pub currency: Option<String>,
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub next_payment_attempt: Option<DateTime<Utc>>,
+    pub event_timestamp: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UpcomingInvoice {
+    const PUT_COLUMNS: [UpcomingInvoiceIden; 10] = [
+        UpcomingInvoiceIden::StripeSubscriptionId,
+        UpcomingInvoiceIden::AmountDue,
+        UpcomingInvoiceIden::Subtotal,
+        UpcomingInvoiceIden::Tax,
+        UpcomingInvoiceIden::Total,
+        UpcomingInvoiceIden::Currency,
+        UpcomingInvoiceIden::PeriodStart,
+        UpcomingInvoiceIden::PeriodEnd,
+        UpcomingInvoiceIden::NextPaymentAttempt,
+        UpcomingInvoiceIden::EventTimestamp,
+    ];
+
+    pub async fn get(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+    ) -> Result<Option<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column(Asterisk)
+            .from(UpcomingInvoiceIden::Table)
+            .and_where(
+                Expr::col(UpcomingInvoiceIden::StripeSubscriptionId)
+                    .eq(stripe_subscription_id),
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+
+    /// Inserts or replaces the upcoming invoice of the subscription. Returns
+    /// `None` if the stored one was written by a newer event.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+        amount_due: Option<i64>,
+        subtotal: Option<i64>,
+        tax: Option<i64>,
+        total: Option<i64>,
+        currency: Option<String>,
+        period_start: Option<DateTime<Utc>>,
+        period_end: Option<DateTime<Utc>>,
+        next_payment_attempt: Option<DateTime<Utc>>,
+        event_timestamp: i64,
+    ) -> Result<Option<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::insert()
+            .into_table(UpcomingInvoiceIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_subscription_id.into(),
+                amount_due.into(),
+                subtotal.into(),
+                tax.into(),
+                total.into(),
+                currency.into(),
+                period_start.into(),
+                period_end.into(),
+                next_payment_attempt.into(),
+                event_timestamp.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(UpcomingInvoiceIden::StripeSubscriptionId)
+                    .update_columns(Self::PUT_COLUMNS)
+                    .action_and_where(
+                        Expr::col((
+                            UpcomingInvoiceIden::Table,
+                            UpcomingInvoiceIden::EventTimestamp,
+                        ))
+                        .lte(event_timestamp),
+                    )
+                    .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_opt(sql.as_str(), &values.as_params()).await?;
+
+        Ok(row.map(Self::from))
+    }
+}
+
+impl From<Row> for UpcomingInvoice {
+    fn from(row: Row) -> Self {
+        Self {
+            stripe_subscription_id: row.get(
+                UpcomingInvoiceIden::StripeSubscriptionId
+                    .to_string()
+                    .as_str(),
+            ),
+            amount_due: row
+                .get(UpcomingInvoiceIden::AmountDue.to_string().as_str()),
+            subtotal: row
+                .get(UpcomingInvoiceIden::Subtotal.to_string().as_str()),
+            tax: row.get(UpcomingInvoiceIden::Tax.to_string().as_str()),
+            total: row.get(UpcomingInvoiceIden::Total.to_string().as_str()),
+            currency: row
+                .get(UpcomingInvoiceIden::Currency.to_string().as_str()),
+            period_start: row
+                .get(UpcomingInvoiceIden::PeriodStart.to_string().as_str()),
+            period_end: row
+                .get(UpcomingInvoiceIden::PeriodEnd.to_string().as_str()),
+            next_payment_attempt: row.get(
+                UpcomingInvoiceIden::NextPaymentAttempt.to_string().as_str(),
+            ),
+            event_timestamp: row
+                .get(UpcomingInvoiceIden::EventTimestamp.to_string().as_str()),
+            created_at: row
+                .get(UpcomingInvoiceIden::CreatedAt.to_string().as_str()),
+            updated_at: row
+                .get(UpcomingInvoiceIden::UpdatedAt.to_string().as_str()),
+        }
+    }
+}
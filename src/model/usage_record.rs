@@ -0,0 +1,165 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::tokio_postgres::Row;
+use deadpool_postgres::Pool;
+use sea_query::{
+    Asterisk, Expr, Iden, OnConflict, Order, PostgresQueryBuilder, Query,
+};
+use sea_query_postgres::PostgresBinder;
+use serde::Serialize;
+
+use crate::DbError;
+
+use super::subscription::SubscriptionIden;
+use super::subscription_item::SubscriptionItemIden;
+
+#[derive(Debug, Clone, Iden)]
+#[iden(rename = "usage_records")]
+enum UsageRecordIden {
+    Table,
+    StripeUsageRecordSummaryId,
+    StripeSubscriptionItemId,
+    StripeInvoiceId,
+    TotalUsage,
+    PeriodStart,
+    PeriodEnd,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Usage reported to Stripe for a metered subscription item, summed up per
+/// billing period. The summary of the current period has no invoice yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRecord {
+    pub stripe_usage_record_summary_id: String,
+    pub stripe_subscription_item_id: String,
+    pub stripe_invoice_id: Option<String>,
+    pub total_usage: i64,
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl UsageRecord {
+    const PUT_COLUMNS: [UsageRecordIden; 6] = [
+        UsageRecordIden::StripeUsageRecordSummaryId,
+        UsageRecordIden::StripeSubscriptionItemId,
+        UsageRecordIden::StripeInvoiceId,
+        UsageRecordIden::TotalUsage,
+        UsageRecordIden::PeriodStart,
+        UsageRecordIden::PeriodEnd,
+    ];
+
+    pub async fn put(
+        pool: &Pool,
+        stripe_usage_record_summary_id: &String,
+        stripe_subscription_item_id: &String,
+        stripe_invoice_id: Option<String>,
+        total_usage: i64,
+        period_start: Option<DateTime<Utc>>,
+        period_end: Option<DateTime<Utc>>,
+    ) -> Result<Self, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::insert()
+            .into_table(UsageRecordIden::Table)
+            .columns(Self::PUT_COLUMNS)
+            .values([
+                stripe_usage_record_summary_id.into(),
+                stripe_subscription_item_id.into(),
+                stripe_invoice_id.into(),
+                total_usage.into(),
+                period_start.into(),
+                period_end.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(UsageRecordIden::StripeUsageRecordSummaryId)
+                    .update_columns(Self::PUT_COLUMNS)
+                    .to_owned(),
+            )
+            .returning_all()
+            .build_postgres(PostgresQueryBuilder);
+
+        let row = conn.query_one(sql.as_str(), &values.as_params()).await?;
+
+        Ok(Self::from(row))
+    }
+
+    pub async fn list_by_stripe_subscription_id(
+        pool: &Pool,
+        stripe_subscription_id: &String,
+    ) -> Result<Vec<Self>, DbError> {
+        let conn = pool.get().await?;
+
+        let (sql, values) = Query::select()
+            .column((UsageRecordIden::Table, Asterisk))
+            .from(UsageRecordIden::Table)
+            .inner_join(
+                SubscriptionItemIden::Table,
+                Expr::col((
+                    SubscriptionItemIden::Table,
+                    SubscriptionItemIden::StripeSubscriptionItemId,
+                ))
+                .equals((
+                    UsageRecordIden::Table,
+                    UsageRecordIden::StripeSubscriptionItemId,
+                )),
+            )
+            .inner_join(
+                SubscriptionIden::Table,
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::SubscriptionId,
+                ))
+                .equals((
+                    SubscriptionItemIden::Table,
+                    SubscriptionItemIden::SubscriptionId,
+                )),
+            )
+            .and_where(
+                Expr::col((
+                    SubscriptionIden::Table,
+                    SubscriptionIden::StripeSubscriptionId,
+                ))
+                .eq(stripe_subscription_id),
+            )
+            .order_by(
+                (UsageRecordIden::Table, UsageRecordIden::PeriodStart),
+                Order::Desc,
+            )
+            .build_postgres(PostgresQueryBuilder);
+
+        let rows = conn.query(sql.as_str(), &values.as_params()).await?;
+
+        Ok(rows.into_iter().map(Self::from).collect())
+    }
+}
+
+impl From<Row> for UsageRecord {
+    fn from(row: Row) -> Self {
+        Self {
+            stripe_usage_record_summary_id: row.get(
+                UsageRecordIden::StripeUsageRecordSummaryId
+                    .to_string()
+                    .as_str(),
+            ),
+            stripe_subscription_item_id: row.get(
+                UsageRecordIden::StripeSubscriptionItemId
+                    .to_string()
+                    .as_str(),
+            ),
+            stripe_invoice_id: row
+                .get(UsageRecordIden::StripeInvoiceId.to_string().as_str()),
+            total_usage: row
+                .get(UsageRecordIden::TotalUsage.to_string().as_str()),
+            period_start: row
+                .get(UsageRecordIden::PeriodStart.to_string().as_str()),
+            period_end: row
+                .get(UsageRecordIden::PeriodEnd.to_string().as_str()),
+            created_at: row
+                .get(UsageRecordIden::CreatedAt.to_string().as_str()),
+            updated_at: row
+                .get(UsageRecordIden::UpdatedAt.to_string().as_str()),
+        }
+    }
+}
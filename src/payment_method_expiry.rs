@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use deadpool_postgres::Pool;
+
+use crate::api::sited_io::stripe_webhooks::v1::PaymentMethodExpiringResponse;
+use crate::model::{PaymentMethod, Subscription, SubscriptionStatus};
+use crate::{DbError, Publisher};
+
+/// Periodically emits a payment-method-expiring message for every running
+/// subscription of a customer whose default card expires within
+/// `warning_period`, so shops can ask buyers to update it before renewals
+/// fail. Each card is warned about once per expiry date.
+#[derive(Debug, Clone)]
+pub struct PaymentMethodExpiryScheduler {
+    pool: Pool,
+    publisher: Publisher,
+    warning_period: Duration,
+    check_interval: Duration,
+}
+
+impl PaymentMethodExpiryScheduler {
+    pub fn new(
+        pool: Pool,
+        publisher: Publisher,
+        warning_period: Duration,
+        check_interval: Duration,
+    ) -> Self {
+        Self {
+            pool,
+            publisher,
+            warning_period,
+            check_interval,
+        }
+    }
+
+    async fn check(&self) -> Result<(), DbError> {
+        let expires_before = Utc::now()
+            + chrono::Duration::from_std(self.warning_period).unwrap();
+
+        let payment_methods =
+            PaymentMethod::list_expiring_defaults(&self.pool, expires_before)
+                .await?;
+
+        for payment_method in payment_methods {
+            let (
+                Some(stripe_customer_id),
+                Some(exp_month),
+                Some(exp_year),
+                Some(expires_at),
+            ) = (
+                &payment_method.stripe_customer_id,
+                payment_method.card_exp_month,
+                payment_method.card_exp_year,
+                payment_method.expires_at,
+            )
+            else {
+                continue;
+            };
+
+            let conn = self.pool.get().await?;
+            let subscriptions = Subscription::list_by_stripe_customer_id(
+                &conn,
+                stripe_customer_id,
+            )
+            .await?;
+
+            for subscription in subscriptions {
+                if matches!(
+                    subscription.subscription_status,
+                    Some(SubscriptionStatus::Canceled)
+                        | Some(SubscriptionStatus::IncompleteExpired)
+                ) {
+                    continue;
+                }
+
+                let Some(buyer_user_id) = subscription.buyer_user_id else {
+                    continue;
+                };
+
+                self.publisher
+                    .publish_payment_method_expiring(
+                        &PaymentMethodExpiringResponse {
+                            media_subscription_id: subscription
+                                .subscription_id
+                                .to_string(),
+                            stripe_subscription_id: subscription
+                                .stripe_subscription_id,
+                            buyer_user_id,
+                            shop_id: subscription
+                                .shop_id
+                                .map(|id| id.to_string()),
+                            offer_id: subscription
+                                .offer_id
+                                .map(|id| id.to_string()),
+                            stripe_payment_method_id: payment_method
+                                .stripe_payment_method_id
+                                .clone(),
+                            card_brand: payment_method.card_brand.clone(),
+                            card_last4: payment_method.card_last4.clone(),
+                            exp_month: exp_month.try_into().unwrap(),
+                            exp_year: exp_year.try_into().unwrap(),
+                            expires_at: expires_at
+                                .timestamp()
+                                .try_into()
+                                .unwrap(),
+                        },
+                    )
+                    .await;
+            }
+
+            PaymentMethod::set_expiry_warned(
+                &self.pool,
+                &payment_method.payment_method_id,
+            )
+            .await?;
+
+            tracing::info!(
+                "[PaymentMethodExpiryScheduler.check] Payment method {} of customer {stripe_customer_id} expires at {expires_at}",
+                payment_method.stripe_payment_method_id,
+            );
+        }
+
+        Ok(())
+    }
+
+    pub async fn run(self) {
+        let mut interval = actix_web::rt::time::interval(self.check_interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.check().await {
+                tracing::error!("[PaymentMethodExpiryScheduler.run] {err:?}");
+            }
+        }
+    }
+}
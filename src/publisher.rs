@@ -1,6 +1,11 @@
 use prost::Message;
 
 use crate::api::sited_io::media::v1::MediaSubscriptionResponse;
+use crate::api::sited_io::stripe_webhooks::v1::{
+    AccessExpiredResponse, CustomerResponse, InvoiceResponse,
+    PaymentMethodExpiringResponse, PlanChangedResponse,
+    SchedulePhaseChangedResponse, SubscriptionUpdate,
+};
 
 #[derive(Debug, Clone)]
 pub struct Publisher {
@@ -8,10 +13,30 @@ pub struct Publisher {
 }
 
 impl Publisher {
+    /// Version of the `SubscriptionUpdate` message format, which is part of
+    /// its subject. Increase it whenever the meaning of existing fields
+    /// changes.
+    pub const SUBSCRIPTION_UPDATE_VERSION: u32 = 2;
+
     const SUBSCRIPTION_UPSERT_SUBJECT: &'static str =
         "stripe-webhooks.subscription.upsert";
     const SUBSCRIPTION_DELETE_SUBJECT: &'static str =
         "stripe-webhooks.subscription.delete";
+    const PLAN_CHANGED_SUBJECT: &'static str =
+        "stripe-webhooks.subscription.plan-changed";
+    const SCHEDULE_PHASE_CHANGED_SUBJECT: &'static str =
+        "stripe-webhooks.subscription-schedule.phase-changed";
+    const ACCESS_EXPIRED_SUBJECT: &'static str =
+        "stripe-webhooks.access.expired";
+    const PAYMENT_METHOD_EXPIRING_SUBJECT: &'static str =
+        "stripe-webhooks.payment-method.expiring";
+    const INVOICE_FINALIZED_SUBJECT: &'static str =
+        "stripe-webhooks.invoice.finalized";
+    const INVOICE_PAID_SUBJECT: &'static str = "stripe-webhooks.invoice.paid";
+    const CUSTOMER_UPSERT_SUBJECT: &'static str =
+        "stripe-webhooks.customer.upsert";
+    const CUSTOMER_DELETE_SUBJECT: &'static str =
+        "stripe-webhooks.customer.delete";
 
     pub fn new(client: async_nats::Client) -> Self {
         Self { client }
@@ -24,35 +49,91 @@ impl Publisher {
         self.client.flush().await
     }
 
-    pub async fn publish_subscription_upsert(
-        &self,
-        subscription: &MediaSubscriptionResponse,
-    ) {
+    async fn publish(&self, subject: &str, message: &impl Message) {
         if let Err(err) = self
             .client
-            .publish(
-                Self::SUBSCRIPTION_UPSERT_SUBJECT,
-                subscription.encode_to_vec().into(),
-            )
+            .publish(subject.to_string(), message.encode_to_vec().into())
             .await
         {
-            tracing::error!("[Publisher.publish_subscription_upsert]: {err}");
+            tracing::error!("[Publisher.publish] {subject}: {err}");
         }
     }
 
+    pub async fn publish_subscription_upsert(
+        &self,
+        subscription: &MediaSubscriptionResponse,
+    ) {
+        self.publish(Self::SUBSCRIPTION_UPSERT_SUBJECT, subscription)
+            .await;
+    }
+
     pub async fn publish_subscription_delete(
         &self,
         subscription: &MediaSubscriptionResponse,
     ) {
-        if let Err(err) = self
-            .client
-            .publish(
-                Self::SUBSCRIPTION_DELETE_SUBJECT,
-                subscription.encode_to_vec().into(),
-            )
-            .await
-        {
-            tracing::error!("[Publisher.publish_subscription_delete]: {err}");
-        }
+        self.publish(Self::SUBSCRIPTION_DELETE_SUBJECT, subscription)
+            .await;
+    }
+
+    pub async fn publish_subscription_update(
+        &self,
+        subscription_update: &SubscriptionUpdate,
+    ) {
+        let subject = format!(
+            "stripe-webhooks.v{}.subscription.update",
+            Self::SUBSCRIPTION_UPDATE_VERSION
+        );
+
+        self.publish(&subject, subscription_update).await;
+    }
+
+    pub async fn publish_plan_changed(
+        &self,
+        plan_changed: &PlanChangedResponse,
+    ) {
+        self.publish(Self::PLAN_CHANGED_SUBJECT, plan_changed).await;
+    }
+
+    pub async fn publish_schedule_phase_changed(
+        &self,
+        phase_changed: &SchedulePhaseChangedResponse,
+    ) {
+        self.publish(Self::SCHEDULE_PHASE_CHANGED_SUBJECT, phase_changed)
+            .await;
+    }
+
+    pub async fn publish_access_expired(
+        &self,
+        access_expired: &AccessExpiredResponse,
+    ) {
+        self.publish(Self::ACCESS_EXPIRED_SUBJECT, access_expired)
+            .await;
+    }
+
+    pub async fn publish_payment_method_expiring(
+        &self,
+        payment_method_expiring: &PaymentMethodExpiringResponse,
+    ) {
+        self.publish(
+            Self::PAYMENT_METHOD_EXPIRING_SUBJECT,
+            payment_method_expiring,
+        )
+        .await;
+    }
+
+    pub async fn publish_invoice_finalized(&self, invoice: &InvoiceResponse) {
+        self.publish(Self::INVOICE_FINALIZED_SUBJECT, invoice).await;
+    }
+
+    pub async fn publish_invoice_paid(&self, invoice: &InvoiceResponse) {
+        self.publish(Self::INVOICE_PAID_SUBJECT, invoice).await;
+    }
+
+    pub async fn publish_customer_upsert(&self, customer: &CustomerResponse) {
+        self.publish(Self::CUSTOMER_UPSERT_SUBJECT, customer).await;
+    }
+
+    pub async fn publish_customer_delete(&self, customer: &CustomerResponse) {
+        self.publish(Self::CUSTOMER_DELETE_SUBJECT, customer).await;
     }
 }
@@ -1,13 +1,102 @@
-use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
 use stripe::Webhook;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
 
-use crate::{AppSettings, EventService, HttpError};
+use crate::model::{
+    Discount, GracePeriod, Invoice, InvoiceLine, InvoiceTaxAmount,
+    PaymentAttempt, PendingUpdate, PlanChange, SubscriptionEvent,
+    SubscriptionSchedule, SubscriptionSchedulePhase, UpcomingInvoice,
+    UsageRecord,
+};
+use crate::{
+    AppSettings, BuyerData, EventService, HttpError, Metrics,
+    StalledSubscriptionDetector,
+};
+
+#[derive(Debug, Deserialize)]
+struct PaginationQuery {
+    page: Option<u64>,
+    size: Option<u64>,
+}
+
+impl PaginationQuery {
+    const DEFAULT_SIZE: u64 = 20;
+    const MAX_SIZE: u64 = 100;
+
+    fn limit_offset(&self) -> (u64, u64) {
+        let size = self.size.unwrap_or(Self::DEFAULT_SIZE).min(Self::MAX_SIZE);
+        let page = self.page.unwrap_or(1).max(1);
+
+        (size, (page - 1) * size)
+    }
+}
+
+fn check_admin_token(
+    request: &HttpRequest,
+    app_settings: &AppSettings,
+) -> Result<(), HttpError> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    // compared in constant time, so the token cannot be guessed by timing
+    let is_admin = token.is_some_and(|token| {
+        token
+            .as_bytes()
+            .ct_eq(app_settings.admin_token.as_bytes())
+            .into()
+    });
+
+    if is_admin {
+        Ok(())
+    } else {
+        Err(HttpError::unauthorized())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct StalledSubscriptionResponse {
+    subscription_id: Uuid,
+    stripe_subscription_id: String,
+    created_at: DateTime<Utc>,
+    missing_fields: Vec<&'static str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferSubscriptionRequest {
+    buyer_user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TaxReportQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GracePeriodRequest {
+    grace_period_seconds: u32,
+}
 
 #[get("/health")]
 async fn health() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
 
+#[get("/metrics")]
+async fn metrics(metrics: web::Data<Metrics>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render())
+}
+
 #[post("/webhook")]
 async fn webhook(
     request: HttpRequest,
@@ -46,8 +135,485 @@ async fn webhook(
     event_service.handle_event(event).await
 }
 
+#[get("/admin/subscriptions/{stripe_subscription_id}/invoices")]
+async fn list_subscription_invoices(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pagination: web::Query<PaginationQuery>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let (limit, offset) = pagination.limit_offset();
+
+    let invoices =
+        Invoice::list_by_stripe_subscription_id(&pool, &path, limit, offset)
+            .await?;
+
+    Ok(HttpResponse::Ok().json(invoices))
+}
+
+#[get("/admin/subscriptions/{stripe_subscription_id}/payment-attempts")]
+async fn list_subscription_payment_attempts(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pagination: web::Query<PaginationQuery>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let (limit, offset) = pagination.limit_offset();
+
+    let payment_attempts = PaymentAttempt::list_by_stripe_subscription_id(
+        &pool, &path, limit, offset,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(payment_attempts))
+}
+
+#[get("/admin/subscriptions/{stripe_subscription_id}/events")]
+async fn list_subscription_events(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pagination: web::Query<PaginationQuery>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let (limit, offset) = pagination.limit_offset();
+
+    let subscription_events =
+        SubscriptionEvent::list_by_stripe_subscription_id(
+            &pool, &path, limit, offset,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(subscription_events))
+}
+
+#[get("/admin/subscriptions/{stripe_subscription_id}/plan-changes")]
+async fn list_subscription_plan_changes(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let plan_changes =
+        PlanChange::list_by_stripe_subscription_id(&pool, &path).await?;
+
+    Ok(HttpResponse::Ok().json(plan_changes))
+}
+
+#[get("/admin/subscriptions/{stripe_subscription_id}/pending-updates")]
+async fn list_subscription_pending_updates(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let pending_updates =
+        PendingUpdate::list_by_stripe_subscription_id(&pool, &path).await?;
+
+    Ok(HttpResponse::Ok().json(pending_updates))
+}
+
+#[get("/admin/subscriptions/{stripe_subscription_id}/usage")]
+async fn list_subscription_usage(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let usage_records =
+        UsageRecord::list_by_stripe_subscription_id(&pool, &path).await?;
+
+    Ok(HttpResponse::Ok().json(usage_records))
+}
+
+#[get("/admin/subscriptions/{stripe_subscription_id}/upcoming-invoice")]
+async fn get_upcoming_invoice(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let upcoming_invoice =
+        UpcomingInvoice::get(&pool, &path).await?.ok_or_else(|| {
+            HttpError::from_message(
+                StatusCode::NOT_FOUND,
+                "no upcoming invoice found",
+            )
+        })?;
+
+    Ok(HttpResponse::Ok().json(upcoming_invoice))
+}
+
+#[derive(Debug, Serialize)]
+struct SubscriptionScheduleResponse {
+    #[serde(flatten)]
+    schedule: SubscriptionSchedule,
+    phases: Vec<SubscriptionSchedulePhase>,
+}
+
+#[get("/admin/subscriptions/{stripe_subscription_id}/schedule")]
+async fn get_subscription_schedule(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let schedule =
+        SubscriptionSchedule::get_by_stripe_subscription_id(&pool, &path)
+            .await?
+            .ok_or_else(|| {
+                HttpError::from_message(
+                    StatusCode::NOT_FOUND,
+                    "no subscription schedule found",
+                )
+            })?;
+
+    let phases =
+        SubscriptionSchedulePhase::list_by_stripe_subscription_schedule_id(
+            &pool,
+            &schedule.stripe_subscription_schedule_id,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .json(SubscriptionScheduleResponse { schedule, phases }))
+}
+
+#[get("/admin/invoices/{stripe_invoice_id}/lines")]
+async fn list_invoice_lines(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let lines = InvoiceLine::list_by_stripe_invoice_id(&pool, &path).await?;
+
+    Ok(HttpResponse::Ok().json(lines))
+}
+
+#[post("/admin/subscriptions/{stripe_subscription_id}/transfer")]
+async fn transfer_subscription(
+    request: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<TransferSubscriptionRequest>,
+    event_service: web::Data<EventService>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    event_service
+        .transfer_subscription(&path, &body.buyer_user_id)
+        .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/admin/shops/{shop_id}/invoices")]
+async fn list_shop_invoices(
+    request: HttpRequest,
+    path: web::Path<Uuid>,
+    pagination: web::Query<PaginationQuery>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let (limit, offset) = pagination.limit_offset();
+
+    let invoices =
+        Invoice::list_by_shop_id(&pool, &path, limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(invoices))
+}
+
+#[get("/admin/shops/{shop_id}/taxes")]
+async fn list_shop_taxes(
+    request: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<TaxReportQuery>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    if query.from >= query.to {
+        return Err(HttpError::bad_request("from must be before to"));
+    }
+
+    let taxes = InvoiceTaxAmount::summarize_by_shop_id(
+        &pool, &path, query.from, query.to,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(taxes))
+}
+
+#[get("/admin/shops/{shop_id}/promotion-codes")]
+async fn list_shop_promotion_codes(
+    request: HttpRequest,
+    path: web::Path<Uuid>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let usage =
+        Discount::list_promotion_code_usage_by_shop_id(&pool, &path).await?;
+
+    Ok(HttpResponse::Ok().json(usage))
+}
+
+#[get("/admin/shops/{shop_id}/upcoming-phases")]
+async fn list_shop_upcoming_phases(
+    request: HttpRequest,
+    path: web::Path<Uuid>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let phases = SubscriptionSchedulePhase::list_upcoming_by_shop_id(
+        &pool,
+        &path,
+        Utc::now(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(phases))
+}
+
+#[get("/admin/subscriptions/stalled")]
+async fn list_stalled_subscriptions(
+    request: HttpRequest,
+    stalled_subscription_detector: web::Data<StalledSubscriptionDetector>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let stalled_subscriptions: Vec<StalledSubscriptionResponse> =
+        stalled_subscription_detector
+            .list_stalled()
+            .await?
+            .into_iter()
+            .map(|subscription| StalledSubscriptionResponse {
+                missing_fields: subscription.missing_fields(),
+                subscription_id: subscription.subscription_id,
+                stripe_subscription_id: subscription.stripe_subscription_id,
+                created_at: subscription.created_at,
+            })
+            .collect();
+
+    Ok(HttpResponse::Ok().json(stalled_subscriptions))
+}
+
+#[get("/admin/shops/{shop_id}/grace-periods")]
+async fn list_grace_periods(
+    request: HttpRequest,
+    path: web::Path<Uuid>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let grace_periods = GracePeriod::list(&pool, &path).await?;
+
+    Ok(HttpResponse::Ok().json(grace_periods))
+}
+
+async fn put_grace_period(
+    shop_id: Uuid,
+    offer_id: Option<Uuid>,
+    grace_period_seconds: u32,
+    pool: &Pool,
+    event_service: &EventService,
+) -> Result<HttpResponse, HttpError> {
+    let grace_period = GracePeriod::put(
+        pool,
+        &shop_id,
+        offer_id.as_ref(),
+        grace_period_seconds.into(),
+    )
+    .await?;
+
+    event_service.refresh_access_until(&shop_id).await?;
+
+    Ok(HttpResponse::Ok().json(grace_period))
+}
+
+async fn delete_grace_period(
+    shop_id: Uuid,
+    offer_id: Option<Uuid>,
+    pool: &Pool,
+    event_service: &EventService,
+) -> Result<HttpResponse, HttpError> {
+    GracePeriod::delete(pool, &shop_id, offer_id.as_ref()).await?;
+
+    event_service.refresh_access_until(&shop_id).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[put("/admin/shops/{shop_id}/grace-period")]
+async fn put_shop_grace_period(
+    request: HttpRequest,
+    path: web::Path<Uuid>,
+    body: web::Json<GracePeriodRequest>,
+    pool: web::Data<Pool>,
+    event_service: web::Data<EventService>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    put_grace_period(
+        path.into_inner(),
+        None,
+        body.grace_period_seconds,
+        &pool,
+        &event_service,
+    )
+    .await
+}
+
+#[delete("/admin/shops/{shop_id}/grace-period")]
+async fn delete_shop_grace_period(
+    request: HttpRequest,
+    path: web::Path<Uuid>,
+    pool: web::Data<Pool>,
+    event_service: web::Data<EventService>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    delete_grace_period(path.into_inner(), None, &pool, &event_service).await
+}
+
+#[put("/admin/shops/{shop_id}/offers/{offer_id}/grace-period")]
+async fn put_offer_grace_period(
+    request: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    body: web::Json<GracePeriodRequest>,
+    pool: web::Data<Pool>,
+    event_service: web::Data<EventService>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let (shop_id, offer_id) = path.into_inner();
+
+    put_grace_period(
+        shop_id,
+        Some(offer_id),
+        body.grace_period_seconds,
+        &pool,
+        &event_service,
+    )
+    .await
+}
+
+#[delete("/admin/shops/{shop_id}/offers/{offer_id}/grace-period")]
+async fn delete_offer_grace_period(
+    request: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    pool: web::Data<Pool>,
+    event_service: web::Data<EventService>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let (shop_id, offer_id) = path.into_inner();
+
+    delete_grace_period(shop_id, Some(offer_id), &pool, &event_service).await
+}
+
+#[delete("/admin/buyers/{buyer_user_id}/customers")]
+async fn delete_buyer_customers(
+    request: HttpRequest,
+    path: web::Path<String>,
+    event_service: web::Data<EventService>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    event_service.delete_customers(&path).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/admin/buyers/{buyer_user_id}/export")]
+async fn export_buyer_data(
+    request: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let buyer_data = BuyerData::export(&pool, &path).await?;
+
+    Ok(HttpResponse::Ok().json(buyer_data))
+}
+
+#[post("/admin/buyers/{buyer_user_id}/erase")]
+async fn erase_buyer_data(
+    request: HttpRequest,
+    path: web::Path<String>,
+    event_service: web::Data<EventService>,
+    app_settings: web::Data<AppSettings>,
+) -> Result<HttpResponse, HttpError> {
+    check_admin_token(&request, &app_settings)?;
+
+    let pseudonymization = event_service.erase_buyer(&path).await?;
+
+    Ok(HttpResponse::Ok().json(pseudonymization))
+}
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(health);
+    cfg.service(metrics);
 
     cfg.service(webhook);
+
+    cfg.service(list_stalled_subscriptions);
+    cfg.service(list_subscription_invoices);
+    cfg.service(list_subscription_payment_attempts);
+    cfg.service(list_subscription_events);
+    cfg.service(list_subscription_plan_changes);
+    cfg.service(list_subscription_pending_updates);
+    cfg.service(list_subscription_usage);
+    cfg.service(get_upcoming_invoice);
+    cfg.service(get_subscription_schedule);
+    cfg.service(list_invoice_lines);
+    cfg.service(transfer_subscription);
+    cfg.service(list_shop_invoices);
+    cfg.service(list_shop_taxes);
+    cfg.service(list_shop_promotion_codes);
+    cfg.service(list_shop_upcoming_phases);
+    cfg.service(list_grace_periods);
+    cfg.service(put_shop_grace_period);
+    cfg.service(delete_shop_grace_period);
+    cfg.service(put_offer_grace_period);
+    cfg.service(delete_offer_grace_period);
+    cfg.service(delete_buyer_customers);
+    cfg.service(export_buyer_data);
+    cfg.service(erase_buyer_data);
 }
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+
+use crate::model::{ReconcileAttempt, Subscription};
+use crate::{DbError, EventService, Metrics};
+
+/// Periodically looks for subscriptions that stay incomplete for longer than
+/// `max_age`, which means they were never published to the media service.
+/// Tries to complete them from the Stripe API and reports the ones that are
+/// still incomplete afterwards. Failed subscriptions are retried with
+/// exponential backoff and given up after `max_attempts`.
+#[derive(Clone)]
+pub struct StalledSubscriptionDetector {
+    pool: Pool,
+    event_service: EventService,
+    metrics: Metrics,
+    max_age: Duration,
+    check_interval: Duration,
+    max_attempts: i64,
+}
+
+impl StalledSubscriptionDetector {
+    pub fn new(
+        pool: Pool,
+        event_service: EventService,
+        metrics: Metrics,
+        max_age: Duration,
+        check_interval: Duration,
+        max_attempts: i64,
+    ) -> Self {
+        Self {
+            pool,
+            event_service,
+            metrics,
+            max_age,
+            check_interval,
+            max_attempts,
+        }
+    }
+
+    pub async fn list_stalled(&self) -> Result<Vec<Subscription>, DbError> {
+        let created_before =
+            Utc::now() - chrono::Duration::from_std(self.max_age).unwrap();
+
+        Subscription::list_incomplete(&self.pool, created_before).await
+    }
+
+    /// Returns whether the subscription is due for another attempt, waiting
+    /// twice as long after each failed one.
+    fn is_due(&self, attempt: &ReconcileAttempt, now: DateTime<Utc>) -> bool {
+        let backoff = chrono::Duration::from_std(self.check_interval).unwrap()
+            * 2_i32.pow((attempt.attempts - 1).clamp(0, 16) as u32);
+
+        attempt.attempts < self.max_attempts
+            && attempt.last_attempt_at + backoff <= now
+    }
+
+    async fn check(&self) -> Result<(), DbError> {
+        let now = Utc::now();
+        let stalled = self.list_stalled().await?;
+
+        let mut attempts: HashMap<String, ReconcileAttempt> =
+            ReconcileAttempt::list(&self.pool)
+                .await?
+                .into_iter()
+                .map(|a| (a.stripe_subscription_id.clone(), a))
+                .collect();
+
+        let mut attempted = Vec::new();
+
+        for subscription in stalled.iter() {
+            let stripe_subscription_id = &subscription.stripe_subscription_id;
+
+            if let Some(attempt) = attempts.remove(stripe_subscription_id) {
+                if !self.is_due(&attempt, now) {
+                    continue;
+                }
+            }
+
+            let result = self
+                .event_service
+                .reconcile_subscription(stripe_subscription_id)
+                .await;
+
+            if let Err(err) = &result {
+                tracing::error!(
+                    "[StalledSubscriptionDetector.check] {stripe_subscription_id}: {err}"
+                );
+            }
+
+            attempted.push((subscription, result.is_ok()));
+        }
+
+        // Subscriptions completed otherwise need no attempts anymore.
+        for stripe_subscription_id in attempts.keys() {
+            ReconcileAttempt::delete(&self.pool, stripe_subscription_id)
+                .await?;
+        }
+
+        let remaining = self.list_stalled().await?;
+
+        let mut fixed: u64 = 0;
+
+        for (subscription, reconciled) in attempted {
+            let stripe_subscription_id = &subscription.stripe_subscription_id;
+
+            let Some(subscription) = remaining
+                .iter()
+                .find(|s| s.stripe_subscription_id == *stripe_subscription_id)
+            else {
+                ReconcileAttempt::delete(&self.pool, stripe_subscription_id)
+                    .await?;
+
+                if reconciled {
+                    fixed += 1;
+                }
+
+                continue;
+            };
+
+            let attempt = ReconcileAttempt::record(
+                &self.pool,
+                stripe_subscription_id,
+                now,
+            )
+            .await?;
+
+            tracing::warn!(
+                "[StalledSubscriptionDetector.check] Subscription {} is stalled since {}, missing fields: {}, attempts: {}",
+                subscription.stripe_subscription_id,
+                subscription.created_at,
+                subscription.missing_fields().join(", "),
+                attempt.attempts
+            );
+
+            if attempt.attempts >= self.max_attempts {
+                tracing::error!(
+                    "[StalledSubscriptionDetector.check] Giving up on subscription {stripe_subscription_id}"
+                );
+            }
+        }
+
+        self.metrics.set(
+            "stripe_webhooks_stalled_subscriptions",
+            &[],
+            remaining.len().try_into().unwrap(),
+        );
+
+        self.metrics.add(
+            "stripe_webhooks_stalled_subscriptions_fixed_total",
+            &[],
+            fixed,
+        );
+
+        Ok(())
+    }
+
+    pub async fn run(self) {
+        let mut interval = actix_web::rt::time::interval(self.check_interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.check().await {
+                tracing::error!("[StalledSubscriptionDetector.run] {err:?}");
+            }
+        }
+    }
+}
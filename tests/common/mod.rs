@@ -0,0 +1,152 @@
+//! Helpers shared by the tests running against the local database described
+//! in the README.
+
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use deadpool_postgres::Pool;
+use prost::Message;
+use stripe_webhooks::api::sited_io::media::v1::MediaSubscriptionResponse;
+use stripe_webhooks::{
+    get_env_var, init_db_pool, migrate, EventService, MetadataMapping, Metrics,
+    Publisher,
+};
+
+pub const SUBSCRIPTION_UPSERT_SUBJECT: &str =
+    "stripe-webhooks.subscription.upsert";
+pub const SUBSCRIPTION_DELETE_SUBJECT: &str =
+    "stripe-webhooks.subscription.delete";
+
+pub async fn init_pool() -> Pool {
+    let pool = init_db_pool(
+        get_env_var("DB_HOST"),
+        get_env_var("DB_PORT").parse().unwrap(),
+        get_env_var("DB_USER"),
+        get_env_var("DB_PASSWORD"),
+        get_env_var("DB_DBNAME"),
+        std::env::var("DB_ROOT_CERT").ok(),
+    )
+    .unwrap();
+
+    migrate(&pool).await.unwrap();
+
+    pool
+}
+
+/// Returns an event service without Stripe client, which takes the
+/// attribution of checkouts from the session metadata.
+pub fn event_service(pool: &Pool, publisher: Publisher) -> EventService {
+    EventService::new(
+        pool.clone(),
+        publisher,
+        None,
+        MetadataMapping::new("session", "user_id", "offer_id", "shop_id")
+            .unwrap(),
+        Metrics::new(),
+        Duration::ZERO,
+    )
+}
+
+/// Subjects and payloads of the published messages, in order.
+type Messages = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+/// NATS server that only records the published messages, so the tests do not
+/// need a running NATS.
+pub struct FakeNats {
+    address: String,
+    messages: Messages,
+}
+
+impl FakeNats {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = messages.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let recorded = recorded.clone();
+                std::thread::spawn(move || serve(stream.unwrap(), recorded));
+            }
+        });
+
+        Self { address, messages }
+    }
+
+    pub async fn publisher(&self) -> Publisher {
+        Publisher::new(async_nats::connect(&self.address).await.unwrap())
+    }
+
+    /// Returns the messages published on `subject` so far. The publisher has
+    /// to be flushed before.
+    pub fn messages(&self, subject: &str) -> Vec<Vec<u8>> {
+        std::thread::sleep(Duration::from_millis(100));
+
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(s, _)| s == subject)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+
+    pub fn media_subscriptions(
+        &self,
+        subject: &str,
+    ) -> Vec<MediaSubscriptionResponse> {
+        self.messages(subject)
+            .into_iter()
+            .map(|payload| {
+                MediaSubscriptionResponse::decode(payload.as_slice()).unwrap()
+            })
+            .collect()
+    }
+}
+
+fn serve(stream: TcpStream, messages: Messages) {
+    let mut writer = stream.try_clone().unwrap();
+    let port = stream.local_addr().unwrap().port();
+
+    writer
+        .write_all(
+            format!(
+                "INFO {{\"server_id\":\"fake\",\"server_name\":\"fake\",\
+                \"version\":\"2.10.0\",\"go\":\"go1.22\",\
+                \"host\":\"127.0.0.1\",\"port\":{port},\"headers\":true,\
+                \"max_payload\":1048576,\"proto\":1}}\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    while reader.read_line(&mut line).unwrap_or(0) > 0 {
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        match args.first() {
+            Some(&"PING") => writer.write_all(b"PONG\r\n").unwrap(),
+            Some(&"PUB") => {
+                let length: usize = args.last().unwrap().parse().unwrap();
+                let mut payload = vec![0; length + 2];
+                reader.read_exact(&mut payload).unwrap();
+                payload.truncate(length);
+
+                messages
+                    .lock()
+                    .unwrap()
+                    .push((args[1].to_string(), payload));
+            }
+            _ => {}
+        }
+
+        line.clear();
+    }
+}
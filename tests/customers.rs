@@ -0,0 +1,251 @@
+//! Runs against the local database described in the README:
+//!
+//! ```sh
+//! cargo test -- --ignored
+//! ```
+
+mod common;
+
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use deadpool_postgres::Pool;
+use prost::Message;
+use stripe::{
+    Customer as StripeCustomer, Event, EventObject, EventType,
+    NotificationEventData,
+};
+use stripe_webhooks::api::sited_io::stripe_webhooks::v1::CustomerResponse;
+use stripe_webhooks::BuyerData;
+use uuid::Uuid;
+
+use common::{
+    event_service, init_pool, FakeNats, SUBSCRIPTION_DELETE_SUBJECT,
+    SUBSCRIPTION_UPSERT_SUBJECT,
+};
+
+const CUSTOMER_UPSERT_SUBJECT: &str = "stripe-webhooks.customer.upsert";
+const CUSTOMER_DELETE_SUBJECT: &str = "stripe-webhooks.customer.delete";
+
+fn customer_updated(
+    stripe_customer_id: &str,
+    buyer_user_id: &str,
+    email: &str,
+    created: i64,
+) -> Event {
+    customer_event(
+        EventType::CustomerUpdated,
+        stripe_customer_id,
+        buyer_user_id,
+        email,
+        created,
+    )
+}
+
+fn customer_event(
+    type_: EventType,
+    stripe_customer_id: &str,
+    buyer_user_id: &str,
+    email: &str,
+    created: i64,
+) -> Event {
+    let customer = StripeCustomer {
+        id: stripe_customer_id.parse().unwrap(),
+        email: Some(email.to_string()),
+        metadata: Some(HashMap::from([(
+            "user_id".to_string(),
+            buyer_user_id.to_string(),
+        )])),
+        ..Default::default()
+    };
+
+    Event {
+        id: format!("evt_{}", Uuid::new_v4().simple()).parse().unwrap(),
+        type_,
+        created,
+        data: NotificationEventData {
+            object: EventObject::Customer(customer),
+            previous_attributes: None,
+        },
+        ..Default::default()
+    }
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn deleted_customers_are_not_recreated_by_events() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let publisher = nats.publisher().await;
+    let event_service = event_service(&pool, publisher.clone());
+    let buyer_user_id = Uuid::new_v4().to_string();
+    let stripe_customer_id = format!("cus_{}", Uuid::new_v4().simple());
+    let created = Utc::now().timestamp();
+
+    event_service
+        .handle_event(customer_updated(
+            &stripe_customer_id,
+            &buyer_user_id,
+            "buyer@example.com",
+            created,
+        ))
+        .await
+        .unwrap();
+
+    event_service
+        .delete_customers(&buyer_user_id)
+        .await
+        .unwrap();
+
+    event_service
+        .handle_event(customer_updated(
+            &stripe_customer_id,
+            &buyer_user_id,
+            "buyer@example.com",
+            created + 1,
+        ))
+        .await
+        .unwrap();
+
+    publisher.flush().await.unwrap();
+
+    let buyer_data = BuyerData::export(&pool, &buyer_user_id).await.unwrap();
+    assert!(buyer_data.customers.is_empty());
+
+    assert_eq!(nats.messages(CUSTOMER_UPSERT_SUBJECT).len(), 1);
+
+    let deleted: Vec<CustomerResponse> = nats
+        .messages(CUSTOMER_DELETE_SUBJECT)
+        .into_iter()
+        .map(|payload| CustomerResponse::decode(payload.as_slice()).unwrap())
+        .collect();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].stripe_customer_id, stripe_customer_id);
+    assert_eq!(deleted[0].buyer_user_id.as_ref(), Some(&buyer_user_id));
+    assert_eq!(deleted[0].email, None);
+}
+
+/// Inserts a paid subscription stored before subscriptions had a Stripe
+/// customer id, together with customer and card of the buyer.
+async fn insert_legacy_subscription(
+    pool: &Pool,
+    buyer_user_id: &str,
+    stripe_customer_id: &str,
+) -> String {
+    let conn = pool.get().await.unwrap();
+    let stripe_subscription_id = format!("sub_{}", Uuid::new_v4().simple());
+    let now = Utc::now();
+
+    conn.execute(
+        "INSERT INTO subscriptions \
+            (stripe_subscription_id, buyer_user_id, offer_id, shop_id, \
+            subscription_status, current_period_start, current_period_end, \
+            payed_at, payed_until) \
+        VALUES ($1, $2, $3, $4, 'active', $5, $6, $5, $6)",
+        &[
+            &stripe_subscription_id,
+            &buyer_user_id,
+            &Uuid::new_v4(),
+            &Uuid::new_v4(),
+            &now,
+            &(now + Duration::days(30)),
+        ],
+    )
+    .await
+    .unwrap();
+
+    conn.execute(
+        "INSERT INTO customers \
+            (stripe_customer_id, buyer_user_id, email, name) \
+        VALUES ($1, $2, 'buyer@example.com', 'Jane Doe')",
+        &[&stripe_customer_id, &buyer_user_id],
+    )
+    .await
+    .unwrap();
+
+    conn.execute(
+        "INSERT INTO payment_methods \
+            (stripe_payment_method_id, stripe_customer_id, \
+            payment_method_type, card_brand, card_last4) \
+        VALUES ($1, $2, 'card', 'visa', '4242')",
+        &[
+            &format!("pm_{}", Uuid::new_v4().simple()),
+            &stripe_customer_id,
+        ],
+    )
+    .await
+    .unwrap();
+
+    stripe_subscription_id
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn deleted_customers_end_subscriptions_and_erase_buyer() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let publisher = nats.publisher().await;
+    let event_service = event_service(&pool, publisher.clone());
+    let buyer_user_id = Uuid::new_v4().to_string();
+    let stripe_customer_id = format!("cus_{}", Uuid::new_v4().simple());
+    let stripe_subscription_id =
+        insert_legacy_subscription(&pool, &buyer_user_id, &stripe_customer_id)
+            .await;
+
+    event_service
+        .handle_event(customer_event(
+            EventType::CustomerDeleted,
+            &stripe_customer_id,
+            &buyer_user_id,
+            "buyer@example.com",
+            Utc::now().timestamp(),
+        ))
+        .await
+        .unwrap();
+
+    publisher.flush().await.unwrap();
+
+    assert!(nats
+        .media_subscriptions(SUBSCRIPTION_UPSERT_SUBJECT)
+        .is_empty());
+    let deleted = nats.media_subscriptions(SUBSCRIPTION_DELETE_SUBJECT);
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(
+        deleted[0].stripe_subscription_id.as_ref(),
+        Some(&stripe_subscription_id)
+    );
+    assert_eq!(deleted[0].buyer_user_id, buyer_user_id);
+
+    let customers: Vec<CustomerResponse> = nats
+        .messages(CUSTOMER_DELETE_SUBJECT)
+        .into_iter()
+        .map(|payload| CustomerResponse::decode(payload.as_slice()).unwrap())
+        .collect();
+    assert_eq!(customers.len(), 1);
+    assert_eq!(customers[0].buyer_user_id.as_ref(), Some(&buyer_user_id));
+
+    let buyer_data = BuyerData::export(&pool, &buyer_user_id).await.unwrap();
+    assert!(buyer_data.customers.is_empty());
+    assert!(buyer_data.payment_methods.is_empty());
+    assert_eq!(buyer_data.subscriptions.len(), 1);
+    assert_eq!(
+        buyer_data.subscriptions[0]
+            .subscription_status
+            .as_ref()
+            .map(|status| status.as_str()),
+        Some("canceled")
+    );
+
+    let conn = pool.get().await.unwrap();
+    let row = conn
+        .query_one(
+            "SELECT buyer_user_id, email, pseudonymized_at \
+            FROM customers WHERE stripe_customer_id = $1",
+            &[&stripe_customer_id],
+        )
+        .await
+        .unwrap();
+    assert_eq!(row.get::<_, Option<String>>(0), None);
+    assert_eq!(row.get::<_, Option<String>>(1), None);
+    assert!(row.get::<_, Option<chrono::DateTime<Utc>>>(2).is_some());
+}
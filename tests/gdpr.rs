@@ -0,0 +1,283 @@
+//! Runs against the local database described in the README:
+//!
+//! ```sh
+//! cargo test -- --ignored
+//! ```
+
+mod common;
+
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use deadpool_postgres::Pool;
+use stripe::{
+    CheckoutSession, Event, EventObject, EventType, Expandable,
+    NotificationEventData,
+};
+use stripe_webhooks::BuyerData;
+use uuid::Uuid;
+
+use common::{
+    event_service, init_pool, FakeNats, SUBSCRIPTION_DELETE_SUBJECT,
+    SUBSCRIPTION_UPSERT_SUBJECT,
+};
+
+/// Inserts a paid subscription with a customer, a card, a paid invoice with a
+/// tax id and its payment attempt for the buyer and returns the Stripe ids of
+/// subscription and invoice.
+async fn insert_buyer(pool: &Pool, buyer_user_id: &str) -> (String, String) {
+    let conn = pool.get().await.unwrap();
+    let stripe_subscription_id = format!("sub_{}", Uuid::new_v4().simple());
+    let stripe_customer_id = format!("cus_{}", Uuid::new_v4().simple());
+    let stripe_invoice_id = format!("in_{}", Uuid::new_v4().simple());
+
+    let now = Utc::now();
+
+    conn.execute(
+        "INSERT INTO subscriptions \
+            (stripe_subscription_id, buyer_user_id, offer_id, shop_id, \
+            subscription_status, stripe_customer_id, current_period_start, \
+            current_period_end, payed_at, payed_until) \
+        VALUES ($1, $2, $3, $4, 'active', $5, $6, $7, $6, $7)",
+        &[
+            &stripe_subscription_id,
+            &buyer_user_id,
+            &Uuid::new_v4(),
+            &Uuid::new_v4(),
+            &stripe_customer_id,
+            &now,
+            &(now + Duration::days(30)),
+        ],
+    )
+    .await
+    .unwrap();
+
+    conn.execute(
+        "INSERT INTO customers \
+            (stripe_customer_id, buyer_user_id, email, name) \
+        VALUES ($1, $2, 'buyer@example.com', 'Jane Doe')",
+        &[&stripe_customer_id, &buyer_user_id],
+    )
+    .await
+    .unwrap();
+
+    conn.execute(
+        "INSERT INTO invoices \
+            (stripe_invoice_id, stripe_subscription_id, amount_paid, \
+            currency, invoice_status) \
+        VALUES ($1, $2, 1000, 'eur', 'paid')",
+        &[&stripe_invoice_id, &stripe_subscription_id],
+    )
+    .await
+    .unwrap();
+
+    conn.execute(
+        "INSERT INTO invoice_tax_ids (stripe_invoice_id, tax_id_type, value) \
+        VALUES ($1, 'eu_vat', 'DE123456789')",
+        &[&stripe_invoice_id],
+    )
+    .await
+    .unwrap();
+
+    conn.execute(
+        "INSERT INTO payment_methods \
+            (stripe_payment_method_id, stripe_customer_id, \
+            payment_method_type, card_brand, card_last4) \
+        VALUES ($1, $2, 'card', 'visa', '4242')",
+        &[
+            &format!("pm_{}", Uuid::new_v4().simple()),
+            &stripe_customer_id,
+        ],
+    )
+    .await
+    .unwrap();
+
+    conn.execute(
+        "INSERT INTO payment_attempts \
+            (stripe_event_id, event_type, stripe_intent_id, intent_type, \
+            intent_status, stripe_invoice_id, stripe_customer_id, \
+            event_timestamp) \
+        VALUES ($1, 'payment_intent.succeeded', $2, 'payment_intent', \
+            'succeeded', $3, $4, 0)",
+        &[
+            &format!("evt_{}", Uuid::new_v4().simple()),
+            &format!("pi_{}", Uuid::new_v4().simple()),
+            &stripe_invoice_id,
+            &stripe_customer_id,
+        ],
+    )
+    .await
+    .unwrap();
+
+    (stripe_subscription_id, stripe_invoice_id)
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn export_contains_all_records_of_buyer() {
+    let pool = init_pool().await;
+    let buyer_user_id = Uuid::new_v4().to_string();
+    let (stripe_subscription_id, stripe_invoice_id) =
+        insert_buyer(&pool, &buyer_user_id).await;
+    insert_buyer(&pool, &Uuid::new_v4().to_string()).await;
+
+    let buyer_data = BuyerData::export(&pool, &buyer_user_id).await.unwrap();
+
+    assert_eq!(buyer_data.subscriptions.len(), 1);
+    assert_eq!(
+        buyer_data.subscriptions[0].stripe_subscription_id,
+        stripe_subscription_id
+    );
+    assert_eq!(buyer_data.customers.len(), 1);
+    assert_eq!(
+        buyer_data.customers[0].email.as_deref(),
+        Some("buyer@example.com")
+    );
+    assert_eq!(buyer_data.invoices.len(), 1);
+    assert_eq!(buyer_data.invoices[0].stripe_invoice_id, stripe_invoice_id);
+    assert_eq!(buyer_data.invoice_tax_ids[&stripe_invoice_id].len(), 1);
+    assert_eq!(buyer_data.payment_methods.len(), 1);
+    assert_eq!(buyer_data.payment_attempts.len(), 1);
+
+    let json = serde_json::to_value(&buyer_data).unwrap();
+    assert_eq!(json["buyer_user_id"], buyer_user_id);
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn pseudonymize_keeps_financial_records() {
+    let pool = init_pool().await;
+    let buyer_user_id = Uuid::new_v4().to_string();
+    let (stripe_subscription_id, stripe_invoice_id) =
+        insert_buyer(&pool, &buyer_user_id).await;
+
+    let pseudonymization = BuyerData::pseudonymize(&pool, &buyer_user_id)
+        .await
+        .unwrap();
+
+    assert_eq!(pseudonymization.subscriptions, 1);
+    assert_eq!(pseudonymization.customers, 1);
+    assert_eq!(pseudonymization.payment_methods, 1);
+    assert_eq!(pseudonymization.payment_attempts, 1);
+    assert_eq!(pseudonymization.invoice_tax_ids, 1);
+
+    let buyer_data = BuyerData::export(&pool, &buyer_user_id).await.unwrap();
+    assert!(buyer_data.subscriptions.is_empty());
+    assert!(buyer_data.customers.is_empty());
+    assert!(buyer_data.invoices.is_empty());
+
+    let pseudonymized_data =
+        BuyerData::export(&pool, &pseudonymization.pseudonym)
+            .await
+            .unwrap();
+
+    assert_eq!(pseudonymized_data.subscriptions.len(), 1);
+    assert_eq!(
+        pseudonymized_data.subscriptions[0].stripe_subscription_id,
+        stripe_subscription_id
+    );
+    assert_eq!(pseudonymized_data.invoices.len(), 1);
+    assert_eq!(
+        pseudonymized_data.invoices[0].stripe_invoice_id,
+        stripe_invoice_id
+    );
+    assert_eq!(pseudonymized_data.invoices[0].amount_paid, Some(1000));
+    assert_eq!(pseudonymized_data.customers.len(), 1);
+    assert_eq!(pseudonymized_data.customers[0].email, None);
+    assert_eq!(pseudonymized_data.customers[0].name, None);
+    assert!(pseudonymized_data.customers[0].pseudonymized_at.is_some());
+    assert!(pseudonymized_data.invoice_tax_ids.is_empty());
+    assert!(pseudonymized_data.payment_methods.is_empty());
+    assert!(pseudonymized_data.payment_attempts.is_empty());
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn erase_republishes_subscriptions_under_pseudonym() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let publisher = nats.publisher().await;
+    let event_service = event_service(&pool, publisher.clone());
+    let buyer_user_id = Uuid::new_v4().to_string();
+    let (stripe_subscription_id, _) = insert_buyer(&pool, &buyer_user_id).await;
+
+    let pseudonymization =
+        event_service.erase_buyer(&buyer_user_id).await.unwrap();
+    publisher.flush().await.unwrap();
+
+    let deleted = nats.media_subscriptions(SUBSCRIPTION_DELETE_SUBJECT);
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].buyer_user_id, buyer_user_id);
+    assert_eq!(
+        deleted[0].stripe_subscription_id.as_ref(),
+        Some(&stripe_subscription_id)
+    );
+
+    let upserted = nats.media_subscriptions(SUBSCRIPTION_UPSERT_SUBJECT);
+    assert_eq!(upserted.len(), 1);
+    assert_eq!(upserted[0].buyer_user_id, pseudonymization.pseudonym);
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn checkout_does_not_restore_erased_buyer() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let event_service = event_service(&pool, nats.publisher().await);
+    let buyer_user_id = Uuid::new_v4().to_string();
+    let (stripe_subscription_id, _) = insert_buyer(&pool, &buyer_user_id).await;
+
+    let pseudonymization =
+        event_service.erase_buyer(&buyer_user_id).await.unwrap();
+
+    event_service
+        .handle_event(checkout_session_completed(
+            &stripe_subscription_id,
+            &buyer_user_id,
+        ))
+        .await
+        .unwrap();
+
+    let buyer_data = BuyerData::export(&pool, &buyer_user_id).await.unwrap();
+    assert!(buyer_data.subscriptions.is_empty());
+
+    let pseudonymized_data =
+        BuyerData::export(&pool, &pseudonymization.pseudonym)
+            .await
+            .unwrap();
+    assert_eq!(pseudonymized_data.subscriptions.len(), 1);
+}
+
+/// Returns a redelivered `checkout.session.completed` event of the
+/// subscription, attributed to `buyer_user_id`.
+fn checkout_session_completed(
+    stripe_subscription_id: &str,
+    buyer_user_id: &str,
+) -> Event {
+    let created = Utc::now().timestamp();
+
+    let checkout_session = CheckoutSession {
+        id: format!("cs_{}", Uuid::new_v4().simple()).parse().unwrap(),
+        subscription: Some(Expandable::Id(
+            stripe_subscription_id.parse().unwrap(),
+        )),
+        metadata: Some(HashMap::from([
+            ("user_id".to_string(), buyer_user_id.to_string()),
+            ("offer_id".to_string(), Uuid::new_v4().to_string()),
+            ("shop_id".to_string(), Uuid::new_v4().to_string()),
+        ])),
+        created,
+        ..Default::default()
+    };
+
+    Event {
+        id: format!("evt_{}", Uuid::new_v4().simple()).parse().unwrap(),
+        type_: EventType::CheckoutSessionCompleted,
+        created,
+        data: NotificationEventData {
+            object: EventObject::CheckoutSession(checkout_session),
+            previous_attributes: None,
+        },
+        ..Default::default()
+    }
+}
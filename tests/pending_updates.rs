@@ -0,0 +1,185 @@
+//! Runs against the local database described in the README:
+//!
+//! ```sh
+//! cargo test -- --ignored
+//! ```
+
+mod common;
+
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use stripe::{
+    Currency, Event, EventObject, EventType, List, NotificationEventData,
+    Price, Subscription as StripeSubscription, SubscriptionItem,
+    SubscriptionsResourcePendingUpdate,
+};
+use stripe_webhooks::EventService;
+use uuid::Uuid;
+
+use common::{event_service, init_pool, FakeNats};
+
+/// Subscription with a pending update that expires in a day.
+struct Pending {
+    stripe_subscription_id: String,
+    expires_at: i64,
+}
+
+impl Pending {
+    fn new() -> Self {
+        Self {
+            stripe_subscription_id: format!("sub_{}", Uuid::new_v4().simple()),
+            expires_at: Utc::now().timestamp() + 86_400,
+        }
+    }
+
+    fn item(price_id: &str) -> SubscriptionItem {
+        SubscriptionItem {
+            id: "si_pending".parse().unwrap(),
+            price: Some(Price {
+                id: price_id.parse().unwrap(),
+                ..Default::default()
+            }),
+            quantity: Some(1),
+            ..Default::default()
+        }
+    }
+
+    /// Event for the subscription whose pending update would set the price
+    /// of its item to `price_id`.
+    fn event(&self, type_: EventType, price_id: &str, created: i64) -> Event {
+        let subscription = StripeSubscription {
+            id: self.stripe_subscription_id.parse().unwrap(),
+            currency: Currency::EUR,
+            items: List {
+                data: vec![Self::item("price_current")],
+                ..Default::default()
+            },
+            pending_update: Some(SubscriptionsResourcePendingUpdate {
+                expires_at: self.expires_at,
+                subscription_items: Some(vec![Self::item(price_id)]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        Event {
+            id: format!("evt_{}", Uuid::new_v4().simple()).parse().unwrap(),
+            type_,
+            created,
+            data: NotificationEventData {
+                object: EventObject::Subscription(subscription),
+                previous_attributes: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// `customer.subscription.pending_update_applied`, after which the
+    /// subscription has no pending update anymore.
+    fn applied(&self, created: i64) -> Event {
+        let mut event = self.event(
+            EventType::CustomerSubscriptionPendingUpdateApplied,
+            "price_new",
+            created,
+        );
+
+        if let EventObject::Subscription(subscription) = &mut event.data.object
+        {
+            subscription.pending_update = None;
+        }
+
+        event
+    }
+
+    /// Status and item prices of the stored pending updates.
+    async fn stored(&self, pool: &Pool) -> Vec<(String, Option<String>)> {
+        let conn = pool.get().await.unwrap();
+
+        conn.query(
+            "SELECT u.update_status, i.price_id \
+            FROM pending_updates u \
+            JOIN pending_update_items i \
+                ON i.pending_update_id = u.pending_update_id \
+            WHERE u.stripe_subscription_id = $1",
+            &[&self.stripe_subscription_id],
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+    }
+}
+
+async fn handle(event_service: &EventService, event: Event) {
+    event_service.handle_event(event).await.unwrap();
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn older_events_do_not_overwrite_pending_updates() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let event_service = event_service(&pool, nats.publisher().await);
+    let pending = Pending::new();
+    let created = Utc::now().timestamp();
+
+    handle(
+        &event_service,
+        pending.event(
+            EventType::CustomerSubscriptionUpdated,
+            "price_new",
+            created + 10,
+        ),
+    )
+    .await;
+    handle(
+        &event_service,
+        pending.event(
+            EventType::CustomerSubscriptionUpdated,
+            "price_old",
+            created,
+        ),
+    )
+    .await;
+
+    assert_eq!(
+        pending.stored(&pool).await,
+        [("pending".to_string(), Some("price_new".to_string()))]
+    );
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn older_events_do_not_reopen_resolved_pending_updates() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let event_service = event_service(&pool, nats.publisher().await);
+    let pending = Pending::new();
+    let created = Utc::now().timestamp();
+
+    handle(
+        &event_service,
+        pending.event(
+            EventType::CustomerSubscriptionCreated,
+            "price_new",
+            created,
+        ),
+    )
+    .await;
+    handle(&event_service, pending.applied(created + 10)).await;
+    handle(
+        &event_service,
+        pending.event(
+            EventType::CustomerSubscriptionUpdated,
+            "price_old",
+            created + 5,
+        ),
+    )
+    .await;
+
+    assert_eq!(
+        pending.stored(&pool).await,
+        [("applied".to_string(), Some("price_new".to_string()))]
+    );
+}
@@ -0,0 +1,218 @@
+//! Runs against the local database described in the README:
+//!
+//! ```sh
+//! cargo test -- --ignored
+//! ```
+
+mod common;
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use serde_json::json;
+use stripe::{
+    Currency, Event, EventObject, EventType, Expandable, Invoice,
+    InvoiceLineItem, List, NotificationEventData, Period, Price,
+    Subscription as StripeSubscription, SubscriptionItem,
+};
+use stripe_webhooks::EventService;
+use uuid::Uuid;
+
+use common::{event_service, init_pool, FakeNats};
+
+/// Ids of a subscription with one item whose price changed.
+struct Upgrade {
+    stripe_subscription_id: String,
+    stripe_subscription_item_id: String,
+    stripe_invoice_id: String,
+    changed_at: i64,
+}
+
+impl Upgrade {
+    fn new() -> Self {
+        Self {
+            stripe_subscription_id: format!("sub_{}", Uuid::new_v4().simple()),
+            stripe_subscription_item_id: format!(
+                "si_{}",
+                Uuid::new_v4().simple()
+            ),
+            stripe_invoice_id: format!("in_{}", Uuid::new_v4().simple()),
+            changed_at: Utc::now().timestamp(),
+        }
+    }
+
+    fn event(&self, type_: EventType, object: EventObject) -> Event {
+        Event {
+            id: format!("evt_{}", Uuid::new_v4().simple()).parse().unwrap(),
+            type_,
+            created: self.changed_at,
+            data: NotificationEventData {
+                object,
+                previous_attributes: None,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// `customer.subscription.updated` from the old to the new price.
+    fn subscription_updated(&self) -> Event {
+        let subscription = StripeSubscription {
+            id: self.stripe_subscription_id.parse().unwrap(),
+            currency: Currency::EUR,
+            items: List {
+                data: vec![SubscriptionItem {
+                    id: self.stripe_subscription_item_id.parse().unwrap(),
+                    price: Some(Price {
+                        id: "price_new".parse().unwrap(),
+                        unit_amount: Some(2000),
+                        ..Default::default()
+                    }),
+                    quantity: Some(1),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut event = self.event(
+            EventType::CustomerSubscriptionUpdated,
+            EventObject::Subscription(subscription),
+        );
+        event.data.previous_attributes = Some(HashMap::from([(
+            "items".to_string(),
+            json!({
+                "data": [{
+                    "id": self.stripe_subscription_item_id,
+                    "price": { "id": "price_old", "unit_amount": 1000 },
+                    "quantity": 1,
+                }]
+            }),
+        )]));
+
+        event
+    }
+
+    fn proration_line(&self, amount: i64) -> InvoiceLineItem {
+        InvoiceLineItem {
+            id: format!("il_{}", Uuid::new_v4().simple()).parse().unwrap(),
+            amount,
+            currency: Currency::EUR,
+            proration: true,
+            subscription: Some(Expandable::Id(
+                self.stripe_subscription_id.parse().unwrap(),
+            )),
+            subscription_item: Some(Expandable::Id(
+                self.stripe_subscription_item_id.parse().unwrap(),
+            )),
+            period: Some(Period {
+                start: Some(self.changed_at),
+                end: Some(self.changed_at + 86_400),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// `invoice.finalized` with the credit for the unused time on the old
+    /// price and the charge for the remaining time on the new one.
+    fn invoice_finalized(&self) -> Event {
+        let invoice = Invoice {
+            id: self.stripe_invoice_id.parse().unwrap(),
+            subscription: Some(Expandable::Id(
+                self.stripe_subscription_id.parse().unwrap(),
+            )),
+            currency: Some(Currency::EUR),
+            created: Some(self.changed_at),
+            lines: Some(List {
+                data: vec![self.proration_line(-300), self.proration_line(600)],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        self.event(EventType::InvoiceFinalized, EventObject::Invoice(invoice))
+    }
+
+    async fn prorations(
+        &self,
+        pool: &Pool,
+    ) -> Vec<(Option<i64>, Option<String>)> {
+        let conn = pool.get().await.unwrap();
+
+        conn.query(
+            "SELECT proration_amount, stripe_invoice_id FROM plan_changes \
+            WHERE stripe_subscription_item_id = $1",
+            &[&self.stripe_subscription_item_id],
+        )
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+    }
+}
+
+async fn handle(event_service: &EventService, event: Event) {
+    event_service.handle_event(event).await.unwrap();
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn prorations_attach_to_earlier_plan_changes() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let event_service = event_service(&pool, nats.publisher().await);
+    let upgrade = Upgrade::new();
+
+    handle(&event_service, upgrade.subscription_updated()).await;
+    handle(&event_service, upgrade.invoice_finalized()).await;
+
+    assert_eq!(
+        upgrade.prorations(&pool).await,
+        [(Some(300), Some(upgrade.stripe_invoice_id.clone()))]
+    );
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn prorations_attach_to_later_plan_changes() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let event_service = event_service(&pool, nats.publisher().await);
+    let upgrade = Upgrade::new();
+
+    handle(&event_service, upgrade.invoice_finalized()).await;
+    handle(&event_service, upgrade.subscription_updated()).await;
+
+    assert_eq!(
+        upgrade.prorations(&pool).await,
+        [(Some(300), Some(upgrade.stripe_invoice_id.clone()))]
+    );
+}
+
+#[actix_web::test]
+#[ignore = "requires a local database"]
+async fn prorations_are_attributed_once() {
+    let pool = init_pool().await;
+    let nats = FakeNats::start();
+    let event_service = event_service(&pool, nats.publisher().await);
+    let upgrade = Upgrade::new();
+
+    handle(&event_service, upgrade.invoice_finalized()).await;
+    handle(&event_service, upgrade.subscription_updated()).await;
+
+    let downgrade = Upgrade {
+        stripe_invoice_id: format!("in_{}", Uuid::new_v4().simple()),
+        changed_at: upgrade.changed_at + 60,
+        ..upgrade
+    };
+    handle(&event_service, downgrade.subscription_updated()).await;
+
+    let mut prorations = downgrade.prorations(&pool).await;
+    prorations.sort();
+
+    assert_eq!(prorations.len(), 2);
+    assert_eq!(prorations[0], (None, None));
+    assert_eq!(prorations[1].0, Some(300));
+}